@@ -0,0 +1,47 @@
+---@meta _
+---@class Animation
+Animation = {}
+
+---Plays the clip on the skeleton of `entity` or of its closest ancestor, replacing
+---whatever was playing. Returns false until the model has loaded or when it has no such clip
+---@param entity Entity
+---@param clip string
+---@param loop boolean? true by default
+---@return boolean
+function Animation.play(entity, clip, loop) end
+
+---Fades the clip in and the playing ones out over `duration` seconds
+---@param entity Entity
+---@param clip string
+---@param duration number
+---@param loop boolean? true by default
+---@return boolean
+function Animation.crossFade(entity, clip, duration, loop) end
+
+---Plays the clip along with the others, weighted against them. Zero weight stops it
+---@param entity Entity
+---@param clip string
+---@param weight number
+---@param loop boolean? true by default
+---@return boolean
+function Animation.blend(entity, clip, weight, loop) end
+
+---@param entity Entity
+---@param clip string
+---@param speed number 1 is the authored speed, negative plays backwards
+---@return boolean
+function Animation.setSpeed(entity, clip, speed) end
+
+---Stops every clip, the model goes back to its rest pose
+---@param entity Entity
+function Animation.stop(entity) end
+
+---@param entity Entity
+---@param clip string
+---@return boolean
+function Animation.isPlaying(entity, clip) end
+
+---Names of the clips the model has
+---@param entity Entity
+---@return string[]
+function Animation.getClips(entity) end
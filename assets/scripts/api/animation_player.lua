@@ -0,0 +1,41 @@
+---@meta _
+---Script objects of an entity with a player get `onAnimationEvent(self, clip, event)`
+---called when playback passes an event of the clip
+---@class AnimationPlayer
+AnimationPlayer = {}
+
+---Plays the clip of the entity's animation player from its start. Returns false when the
+---entity has no player or the player has no such clip. Players with clips of a model are
+---attached once the model has loaded
+---@param entity Entity
+---@param clip string
+---@return boolean
+function AnimationPlayer.play(entity, clip) end
+
+---@param entity Entity
+---@return boolean
+function AnimationPlayer.pause(entity) end
+
+---Continues the paused clip
+---@param entity Entity
+---@return boolean
+function AnimationPlayer.resume(entity) end
+
+---Entities stay where the clip has left them
+---@param entity Entity
+---@return boolean
+function AnimationPlayer.stop(entity) end
+
+---@param entity Entity
+---@return boolean
+function AnimationPlayer.isPlaying(entity) end
+
+---@param entity Entity
+---@param speed number 1 is the authored speed, negative plays backwards
+---@return boolean
+function AnimationPlayer.setSpeed(entity, speed) end
+
+---@param entity Entity
+---@param mode "once"|"loop"|"ping_pong"
+---@return boolean
+function AnimationPlayer.setMode(entity, mode) end
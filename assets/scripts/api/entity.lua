@@ -1,4 +1,13 @@
+---@meta _
 ---@class Entity
+Entity = {}
+
+---Finds the closest entity with the name among the children of `entity` and
+---their descendants, nodes of a model spawned as a hierarchy are named after the nodes
+---@param entity Entity
+---@param name string
+---@return Entity?
+function Entity.findChild(entity, name) end
 
 ---@class Component
 ---@field _entity Entity
@@ -0,0 +1,47 @@
+---@meta _
+---@class Mesh
+Mesh = {}
+
+---Gives the entity another mesh once the model has loaded. `path` is a model under the
+---asset root or a generated shape: builtin://cube, plane, uv_sphere, ico_sphere, cylinder,
+---cone, torus or capsule, with parameters like builtin://uv_sphere?radius=1&rings=24.
+---`material` is the name of a material asset, the model's own materials are used otherwise
+---and when the asset can't be loaded
+---@param entity Entity
+---@param path string
+---@param material string?
+function Mesh.add(entity, path, material) end
+
+---@class Geometry
+---@field positions number[] x, y, z of each vertex
+---@field normals number[]? smoothed from the triangles when left out
+---@field uvs number[]? u, v of each vertex
+---@field colors number[]? r, g, b, a of each vertex
+---@field indices integer[]? three per triangle, starting at 1. Vertices in order otherwise
+
+---Rewrites the entity's mesh, meant to be called every frame for trails, terrain edits or
+---debug shapes. An entity without a mesh gets an empty one with the default material first.
+---Meshes loaded from files or builtin:// shapes can't be changed. The last geometry set in
+---a frame is the one drawn
+---@param entity Entity
+---@param geometry Geometry
+function Mesh.setGeometry(entity, geometry) end
+
+---Sets the weight of the blend shape on the entity's mesh, 0 leaves the mesh as it is and
+---1 applies the whole shape. Returns false until the model has loaded or when it has no such
+---shape. Models spawned as a hierarchy have their meshes on the entities of the nodes
+---@param entity Entity
+---@param target string
+---@param weight number
+---@return boolean
+function Mesh.setMorphWeight(entity, target, weight) end
+
+---@param entity Entity
+---@param target string
+---@return number?
+function Mesh.getMorphWeight(entity, target) end
+
+---Names of the blend shapes the entity's mesh has
+---@param entity Entity
+---@return string[]
+function Mesh.getMorphTargets(entity) end
@@ -4,6 +4,12 @@
 ---@return number
 function FrameTime() end
 
+---Returns how many loading jobs are finished and how many were started,
+---loading is complete when both are equal
+---@return integer finished
+---@return integer total
+function LoadingProgress() end
+
 -- ---Deletes script component
 -- ---@param object table
 -- function DeleteScript(object) end
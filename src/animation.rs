@@ -0,0 +1,658 @@
+use crate::{
+    data3d::Mesh,
+    linear::{self, Transform},
+    resources::ModelNode,
+};
+use glm::{Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+// Length of the bone array in the vertex shader, weights to bones past it are dropped on import
+pub const MAX_BONES: usize = 128;
+
+// Keyframed transforms of the nodes of a model, imported with it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    pub duration: f32, // seconds
+    pub channels: Vec<Channel>,
+    #[serde(default)]
+    pub morph_channels: Vec<MorphChannel>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Channel {
+    pub node: usize, // in `ModelNode` order
+    #[serde(flatten)]
+    pub keys: TransformKeys,
+}
+
+// Keys of a single node or entity. Any of the lists can be empty, the value isn't animated
+// then
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct TransformKeys {
+    pub positions: Vec<Key<[f32; 3]>>,
+    pub rotations: Vec<Key<[f32; 4]>>, // x, y, z, w
+    pub scales: Vec<Key<[f32; 3]>>,
+}
+
+// Blend shape weights of the mesh of a node
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MorphChannel {
+    pub node: usize,
+    #[serde(flatten)]
+    pub keys: MorphKeys,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct MorphKeys {
+    pub targets: Vec<String>,
+    pub weights: Vec<Key<Vec<f32>>>, // one per target
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct Key<T> {
+    pub time: f32, // seconds
+    pub value: T,
+}
+
+// Node that vertices are weighted to
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Bone {
+    pub node: usize,
+    pub offset: [f32; 16], // column-major, from the mesh space to the bone's
+}
+
+#[derive(Clone, Copy)]
+struct NodePose {
+    position: Vec3,
+    rotation: Quat,
+    scale: Vec3,
+}
+
+impl NodePose {
+    fn from_matrix(matrix: &Mat4) -> Self {
+        let (position, rotation, scale) = linear::decompose(matrix);
+        Self {
+            position,
+            rotation,
+            scale,
+        }
+    }
+
+    fn matrix(&self) -> Mat4 {
+        glm::translation(&self.position)
+            * glm::quat_to_mat4(&self.rotation)
+            * glm::scaling(&self.scale)
+    }
+
+    fn blend(&self, other: &Self, weight: f32) -> Self {
+        Self {
+            position: glm::lerp(&self.position, &other.position, weight),
+            rotation: nlerp(&self.rotation, &other.rotation, weight),
+            scale: glm::lerp(&self.scale, &other.scale, weight),
+        }
+    }
+}
+
+// Shortest path, close enough to slerp for keys sampled this densely
+fn nlerp(a: &Quat, b: &Quat, weight: f32) -> Quat {
+    let b = if a.dot(b) < 0.0 { -*b } else { *b };
+    (*a * (1.0 - weight) + b * weight).normalize()
+}
+
+fn vec3(value: [f32; 3]) -> Vec3 {
+    glm::vec3(value[0], value[1], value[2])
+}
+
+fn quat(value: [f32; 4]) -> Quat {
+    glm::quat(value[0], value[1], value[2], value[3])
+}
+
+// Keys are sorted by time, the ends are held outside of them
+fn sample<T: Copy, V>(
+    keys: &[Key<T>],
+    time: f32,
+    value: fn(T) -> V,
+    mix: fn(&V, &V, f32) -> V,
+) -> Option<V> {
+    let next = keys.partition_point(|key| key.time <= time);
+    if next == 0 || next == keys.len() {
+        return keys.get(next.saturating_sub(1)).map(|key| value(key.value));
+    }
+    let (a, b) = (&keys[next - 1], &keys[next]);
+    let weight = (time - a.time) / (b.time - a.time);
+    Some(mix(&value(a.value), &value(b.value), weight))
+}
+
+impl TransformKeys {
+    fn position(&self, time: f32) -> Option<Vec3> {
+        sample(&self.positions, time, vec3, |a, b, t| glm::lerp(a, b, t))
+    }
+
+    fn rotation(&self, time: f32) -> Option<Quat> {
+        sample(&self.rotations, time, quat, nlerp)
+    }
+
+    fn scale(&self, time: f32) -> Option<Vec3> {
+        sample(&self.scales, time, vec3, |a, b, t| glm::lerp(a, b, t))
+    }
+
+    fn sample(&self, time: f32, rest: &NodePose) -> NodePose {
+        NodePose {
+            position: self.position(time).unwrap_or(rest.position),
+            rotation: self.rotation(time).unwrap_or(rest.rotation),
+            scale: self.scale(time).unwrap_or(rest.scale),
+        }
+    }
+
+    // Writes only the animated values
+    fn apply(&self, time: f32, transform: &mut Transform) {
+        if let Some(position) = self.position(time) {
+            transform.position = position;
+        }
+        if let Some(rotation) = self.rotation(time) {
+            transform.orientation = rotation;
+        }
+        if let Some(scale) = self.scale(time) {
+            transform.scale = scale;
+        }
+    }
+}
+
+impl MorphKeys {
+    pub fn is_empty(&self) -> bool {
+        self.weights.is_empty()
+    }
+
+    // Targets the mesh doesn't have are skipped
+    fn apply(&self, time: f32, mesh: &mut Mesh) {
+        let next = self.weights.partition_point(|key| key.time <= time);
+        let (a, b, weight) = if next == 0 || next == self.weights.len() {
+            let key = &self.weights[next.saturating_sub(1)];
+            (key, key, 0.0)
+        } else {
+            let (a, b) = (&self.weights[next - 1], &self.weights[next]);
+            (a, b, (time - a.time) / (b.time - a.time))
+        };
+        for (i, target) in self.targets.iter().enumerate() {
+            let value = |key: &Key<Vec<f32>>| key.value.get(i).copied().unwrap_or(0.0);
+            let value = value(a) + (value(b) - value(a)) * weight;
+            _ = mesh.set_morph_weight(target, value);
+        }
+    }
+}
+
+// Node graph, bones and clips of a skinned model, shared by the skeletons spawned from it
+pub struct SkeletonData {
+    parents: Vec<Option<usize>>,
+    rest: Vec<NodePose>,
+    bones: Vec<(usize, Mat4)>,
+    // Skinned vertices end up in the space of the root node, which meshes that aren't
+    // skinned don't take into account
+    root_inverse: Mat4,
+    clips: Vec<AnimationClip>,
+}
+
+impl SkeletonData {
+    pub fn new(nodes: &[ModelNode], bones: &[Bone], clips: Vec<AnimationClip>) -> Self {
+        let rest = nodes
+            .iter()
+            .map(|node| NodePose::from_matrix(&glm::make_mat4(&node.transform)))
+            .collect::<Vec<NodePose>>();
+        let root_inverse = rest
+            .first()
+            .map_or(Mat4::identity(), |root| glm::inverse(&root.matrix()));
+        Self {
+            parents: nodes.iter().map(|node| node.parent).collect(),
+            rest,
+            bones: bones
+                .iter()
+                .map(|bone| (bone.node, glm::make_mat4(&bone.offset)))
+                .collect(),
+            root_inverse,
+            clips,
+        }
+    }
+
+    fn find_clip(&self, name: &str) -> Option<usize> {
+        self.clips.iter().position(|clip| clip.name == name)
+    }
+
+    fn sample(&self, clip: usize, time: f32) -> Vec<NodePose> {
+        let mut pose = self.rest.clone();
+        for channel in &self.clips[clip].channels {
+            pose[channel.node] = channel.keys.sample(time, &self.rest[channel.node]);
+        }
+        pose
+    }
+}
+
+// Clip being played, blended with the others by its weight
+struct Layer {
+    clip: usize,
+    time: f32,
+    speed: f32,
+    looping: bool,
+    weight: f32,
+    target_weight: f32,
+    fade_rate: f32, // weight per second, zero unless fading
+}
+
+// Animated pose of a skinned model. Meshes of the entity and of its descendants are
+// skinned to it
+pub struct Skeleton {
+    data: Rc<SkeletonData>,
+    layers: Vec<Layer>,
+    palette: Vec<Mat4>, // bone matrices, in the space of the entity
+}
+
+impl Skeleton {
+    pub fn new(data: Rc<SkeletonData>) -> Self {
+        let mut skeleton = Self {
+            data,
+            layers: Vec::new(),
+            palette: Vec::new(),
+        };
+        skeleton.update_palette();
+        skeleton
+    }
+
+    pub fn palette(&self) -> &[Mat4] {
+        &self.palette
+    }
+
+    pub fn clip_names(&self) -> impl Iterator<Item = &str> {
+        self.data.clips.iter().map(|clip| clip.name.as_str())
+    }
+
+    pub fn is_playing(&self, name: &str) -> bool {
+        self.layers
+            .iter()
+            .any(|layer| self.data.clips[layer.clip].name == name && layer.target_weight > 0.0)
+    }
+
+    // Replaces whatever is playing, false if the model has no such clip
+    pub fn play(&mut self, name: &str, looping: bool) -> bool {
+        let Some(clip) = self.data.find_clip(name) else {
+            return false;
+        };
+        self.layers.clear();
+        self.layers.push(Layer::new(clip, looping, 1.0));
+        true
+    }
+
+    // Fades the clip in and everything else out over `duration` seconds. A clip that is
+    // already playing continues from where it is
+    pub fn cross_fade(&mut self, name: &str, duration: f32, looping: bool) -> bool {
+        if duration <= 0.0 {
+            return self.play(name, looping);
+        }
+        let Some(clip) = self.data.find_clip(name) else {
+            return false;
+        };
+        for layer in &mut self.layers {
+            layer.fade_to(0.0, duration);
+        }
+        let index = match self.layers.iter().position(|layer| layer.clip == clip) {
+            Some(index) => index,
+            None => {
+                self.layers.push(Layer::new(clip, looping, 0.0));
+                self.layers.len() - 1
+            }
+        };
+        let layer = &mut self.layers[index];
+        layer.looping = looping;
+        layer.fade_to(1.0, duration);
+        true
+    }
+
+    // Plays the clip alongside the others with the given weight, zero stops it
+    pub fn blend(&mut self, name: &str, weight: f32, looping: bool) -> bool {
+        let Some(clip) = self.data.find_clip(name) else {
+            return false;
+        };
+        let weight = weight.max(0.0);
+        match self.layers.iter_mut().find(|layer| layer.clip == clip) {
+            Some(layer) => {
+                layer.looping = looping;
+                layer.fade_to(weight, 0.0);
+            }
+            None => self.layers.push(Layer::new(clip, looping, weight)),
+        }
+        true
+    }
+
+    pub fn set_speed(&mut self, name: &str, speed: f32) -> bool {
+        let Some(clip) = self.data.find_clip(name) else {
+            return false;
+        };
+        self.layers
+            .iter_mut()
+            .filter(|layer| layer.clip == clip)
+            .for_each(|layer| layer.speed = speed);
+        true
+    }
+
+    // Goes back to the rest pose
+    pub fn stop(&mut self) {
+        self.layers.clear();
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        for layer in &mut self.layers {
+            let duration = self.data.clips[layer.clip].duration;
+            layer.advance(delta, duration);
+        }
+        self.layers
+            .retain(|layer| layer.weight > 0.0 || layer.target_weight > 0.0);
+        self.update_palette();
+    }
+
+    fn update_palette(&mut self) {
+        let data = &self.data;
+        let mut pose: Option<Vec<NodePose>> = None;
+        let mut total_weight = 0.0;
+        for layer in self.layers.iter().filter(|layer| layer.weight > 0.0) {
+            let sampled = data.sample(layer.clip, layer.time);
+            total_weight += layer.weight;
+            pose = Some(match pose {
+                Some(pose) => pose
+                    .iter()
+                    .zip(&sampled)
+                    .map(|(a, b)| a.blend(b, layer.weight / total_weight))
+                    .collect(),
+                None => sampled,
+            });
+        }
+        let pose = pose.unwrap_or_else(|| data.rest.clone());
+
+        // Parents come before their children
+        let mut globals = Vec::<Mat4>::with_capacity(pose.len());
+        for (node, parent) in pose.iter().zip(&data.parents) {
+            let matrix = match parent {
+                Some(parent) => globals[*parent] * node.matrix(),
+                None => node.matrix(),
+            };
+            globals.push(matrix);
+        }
+        self.palette = data
+            .bones
+            .iter()
+            .map(|(node, offset)| data.root_inverse * globals[*node] * offset)
+            .collect();
+    }
+}
+
+impl Layer {
+    fn new(clip: usize, looping: bool, weight: f32) -> Self {
+        Self {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            looping,
+            weight,
+            target_weight: weight,
+            fade_rate: 0.0,
+        }
+    }
+
+    fn fade_to(&mut self, weight: f32, duration: f32) {
+        self.target_weight = weight;
+        if duration > 0.0 {
+            self.fade_rate = (weight - self.weight).abs() / duration;
+        } else {
+            self.weight = weight;
+            self.fade_rate = 0.0;
+        }
+    }
+
+    fn advance(&mut self, delta: f32, duration: f32) {
+        self.time += delta * self.speed;
+        self.time = if self.looping && duration > 0.0 {
+            self.time.rem_euclid(duration)
+        } else {
+            self.time.clamp(0.0, duration)
+        };
+
+        if self.fade_rate > 0.0 {
+            let step = self.fade_rate * delta;
+            let difference = self.target_weight - self.weight;
+            if difference.abs() <= step {
+                self.weight = self.target_weight;
+                self.fade_rate = 0.0;
+            } else {
+                self.weight += step.copysign(difference);
+            }
+        }
+    }
+}
+
+// Keyframed transforms of an entity and its descendants, played by an `AnimationPlayer`
+pub struct TransformClip {
+    pub name: String,
+    pub duration: f32, // seconds
+    pub tracks: Vec<Track>,
+    pub events: Vec<ClipEvent>,
+}
+
+pub struct Track {
+    pub target: String, // name of a descendant, empty for the entity itself
+    pub keys: TransformKeys,
+    pub weights: MorphKeys,
+    // Rest transform of a model node, whose keys are relative to the whole parent
+    // transform. None for the keys of ".anim.json" files, which are the entity's own values
+    pub rest: Option<Mat4>,
+}
+
+// Sent to the scripts of the entity when playback passes its time
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ClipEvent {
+    pub time: f32,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayMode {
+    Once,
+    #[default]
+    Loop,
+    PingPong,
+}
+
+impl AnimationClip {
+    // Nodes of a model spawned as a hierarchy are entities with the names of the nodes
+    pub fn to_transform_clip(&self, nodes: &[ModelNode]) -> TransformClip {
+        let mut clip = TransformClip {
+            name: self.name.clone(),
+            duration: self.duration,
+            tracks: self
+                .channels
+                .iter()
+                .map(|channel| Track {
+                    target: nodes[channel.node].name.clone(),
+                    keys: channel.keys.clone(),
+                    weights: MorphKeys::default(),
+                    rest: Some(glm::make_mat4(&nodes[channel.node].transform)),
+                })
+                .collect::<Vec<Track>>(),
+            events: Vec::new(),
+        };
+        // Nodes with both keyframes and blend shape weights get a single track
+        for channel in &self.morph_channels {
+            let target = &nodes[channel.node].name;
+            match clip.tracks.iter_mut().find(|track| track.target == *target) {
+                Some(track) => track.weights = channel.keys.clone(),
+                None => clip.tracks.push(Track {
+                    target: target.clone(),
+                    keys: TransformKeys::default(),
+                    weights: channel.keys.clone(),
+                    rest: None,
+                }),
+            }
+        }
+        clip
+    }
+}
+
+pub struct AnimationPlayer {
+    clips: Vec<Rc<TransformClip>>,
+    current: Option<usize>,
+    time: f32,
+    pub speed: f32, // negative plays backwards
+    pub mode: PlayMode,
+    reversed: bool, // on the way back of a ping-pong
+    playing: bool,
+    // Events at the time playback starts from are sent as well, right after `play` or a wrap
+    at_start: bool,
+}
+
+impl AnimationPlayer {
+    pub fn new(clips: Vec<Rc<TransformClip>>, mode: PlayMode, speed: f32) -> Self {
+        Self {
+            clips,
+            current: None,
+            time: 0.0,
+            speed,
+            mode,
+            reversed: false,
+            playing: false,
+            at_start: false,
+        }
+    }
+
+    pub fn play(&mut self, name: &str) -> bool {
+        let Some(clip) = self.clips.iter().position(|clip| clip.name == name) else {
+            return false;
+        };
+        self.current = Some(clip);
+        self.reversed = false;
+        self.time = if self.speed < 0.0 {
+            self.clips[clip].duration
+        } else {
+            0.0
+        };
+        self.playing = true;
+        self.at_start = true;
+        true
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn resume(&mut self) {
+        self.playing = self.current.is_some();
+    }
+
+    // Entities stay where the clip has left them
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.current = None;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn current_clip(&self) -> Option<&str> {
+        self.current.map(|clip| self.clips[clip].name.as_str())
+    }
+
+    // Moves the playback time, returning the events passed on the way
+    pub fn advance(&mut self, delta: f32) -> Vec<String> {
+        let mut events = Vec::new();
+        let Some(clip) = self.current.map(|clip| self.clips[clip].clone()) else {
+            return events;
+        };
+        if !self.playing {
+            return events;
+        }
+
+        let duration = clip.duration;
+        let mut remaining = delta * self.speed.abs();
+        let mut forward = (self.speed >= 0.0) != self.reversed;
+        loop {
+            let end = if forward { duration } else { 0.0 };
+            let distance = (end - self.time).abs();
+            if remaining < distance || duration <= 0.0 {
+                let time = if forward {
+                    self.time + remaining
+                } else {
+                    self.time - remaining
+                };
+                self.passed_events(&clip, time, &mut events);
+                self.time = time;
+                break;
+            }
+            self.passed_events(&clip, end, &mut events);
+            self.time = end;
+            remaining -= distance;
+            match self.mode {
+                PlayMode::Once => {
+                    self.playing = false;
+                    break;
+                }
+                PlayMode::Loop => {
+                    self.time = duration - end;
+                    self.at_start = true;
+                }
+                PlayMode::PingPong => {
+                    self.reversed = !self.reversed;
+                    forward = !forward;
+                }
+            }
+        }
+        events
+    }
+
+    fn passed_events(&mut self, clip: &TransformClip, time: f32, events: &mut Vec<String>) {
+        let (from, to) = (self.time, time);
+        let passed = |event: &&ClipEvent| {
+            let started = event.time == from && self.at_start;
+            if from <= to {
+                started || (from < event.time && event.time <= to)
+            } else {
+                started || (to <= event.time && event.time < from)
+            }
+        };
+        events.extend(
+            clip.events
+                .iter()
+                .filter(passed)
+                .map(|event| event.name.clone()),
+        );
+        self.at_start = false;
+    }
+
+    // Clip and time to pose the entities with
+    pub fn sample(&self) -> Option<(Rc<TransformClip>, f32)> {
+        let clip = self.clips[self.current?].clone();
+        Some((clip, self.time))
+    }
+}
+
+impl Track {
+    // Node keys are placed under the orientation and scale of the parent the same way
+    // `SceneManager::spawn_nodes` places the nodes, `parent` is posed first
+    pub fn apply(&self, time: f32, transform: &mut Transform, parent: &Transform) {
+        let Some(rest) = &self.rest else {
+            self.keys.apply(time, transform);
+            return;
+        };
+        let rest = NodePose::from_matrix(rest);
+        let local = self.keys.sample(time, &rest).matrix();
+        let parent = glm::quat_to_mat4(&parent.orientation) * glm::scaling(&parent.scale);
+        let (position, orientation, scale) = linear::decompose(&(parent * local));
+        transform.position = position;
+        transform.orientation = orientation;
+        transform.scale = scale;
+    }
+
+    pub fn apply_weights(&self, time: f32, mesh: &mut Mesh) {
+        if !self.weights.is_empty() {
+            self.weights.apply(time, mesh);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{
+    cook::{self, Compression, Manifest},
+    resources::ImportedModel,
+    serializable::ImportOptions,
+    texture_formats,
+    utils::StbImage,
+    vfs::Vfs,
+};
+use gl::types::GLenum;
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+// CPU side of loading: files are read and decoded on worker threads, GL objects are
+// created from the results on the main thread since the context is bound to it
+pub enum Job {
+    Model {
+        key: String, // the model is cached under
+        path: String,
+        options: ImportOptions,
+    },
+    // One decode serves every texture cut out of the same file
+    Image {
+        path: String,
+        data: Option<Vec<u8>>, // contents of an embedded image, the file is read otherwise
+        targets: Vec<ImageTarget>,
+    },
+}
+
+#[derive(Clone, Copy)]
+pub struct ImageTarget {
+    pub index: usize,           // texture that is waiting for the data
+    pub channel: Option<usize>, // single channel of a merged image
+}
+
+pub enum Loaded {
+    Model {
+        key: String,
+        path: String,
+        options: ImportOptions,
+        model: Result<ImportedModel, String>,
+    },
+    Image {
+        path: String,
+        images: Option<Vec<(usize, DecodedImage)>>,
+    },
+}
+
+#[derive(Clone)]
+pub struct DecodedImage {
+    pub levels: Vec<Vec<u8>>, // mipmaps are generated on upload when only the first is given
+    pub size: (usize, usize),
+    pub channels: usize,
+    pub pixel_type: PixelType,
+    pub compression: Option<Compression>,
+}
+
+impl DecodedImage {
+    pub fn from_stb(img: &StbImage) -> Self {
+        Self {
+            levels: vec![img.data().to_vec()],
+            size: (img.x(), img.y()),
+            channels: img.channels(),
+            pixel_type: img.pixel_type(),
+            compression: None,
+        }
+    }
+
+    fn with_channel(&self, channel: Option<usize>) -> Self {
+        let component_size = self.pixel_type.size();
+        match channel {
+            Some(offset) => Self {
+                levels: self
+                    .levels
+                    .iter()
+                    .map(|level| {
+                        level
+                            .chunks_exact(self.channels * component_size)
+                            .flat_map(|pixel| {
+                                &pixel[offset * component_size..(offset + 1) * component_size]
+                            })
+                            .copied()
+                            .collect()
+                    })
+                    .collect(),
+                channels: 1,
+                ..self.clone()
+            },
+            None => self.clone(),
+        }
+    }
+}
+
+// Type of a single channel of uncompressed pixels
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PixelType {
+    U8,
+    U16,
+    F16,
+    F32,
+}
+
+impl PixelType {
+    pub fn size(&self) -> usize {
+        match self {
+            PixelType::U8 => 1,
+            PixelType::U16 | PixelType::F16 => 2,
+            PixelType::F32 => 4,
+        }
+    }
+
+    pub fn gl_type(&self) -> GLenum {
+        match self {
+            PixelType::U8 => gl::UNSIGNED_BYTE,
+            PixelType::U16 => gl::UNSIGNED_SHORT,
+            PixelType::F16 => gl::HALF_FLOAT,
+            PixelType::F32 => gl::FLOAT,
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        match self {
+            PixelType::U8 => 0,
+            PixelType::U16 => 1,
+            PixelType::F16 => 2,
+            PixelType::F32 => 3,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Option<Self> {
+        match id {
+            0 => Some(PixelType::U8),
+            1 => Some(PixelType::U16),
+            2 => Some(PixelType::F16),
+            3 => Some(PixelType::F32),
+            _ => None,
+        }
+    }
+}
+
+pub struct AssetLoader {
+    jobs: Option<Sender<Job>>,
+    results: Receiver<Loaded>,
+    workers: Vec<JoinHandle<()>>,
+    submitted: usize,
+    finished: usize,
+}
+
+impl AssetLoader {
+    pub fn new(vfs: Arc<Vfs>, cooked: Arc<Manifest>) -> Self {
+        let worker_count = thread::available_parallelism()
+            .map_or(2, |count| count.get().saturating_sub(1))
+            .max(1);
+
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, results) = mpsc::channel::<Loaded>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = (0..worker_count)
+            .map(|_| {
+                let job_receiver = job_receiver.clone();
+                let result_sender = result_sender.clone();
+                let vfs = vfs.clone();
+                let cooked = cooked.clone();
+                thread::spawn(move || loop {
+                    let job = job_receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => {
+                            if result_sender.send(Self::work(job, &vfs, &cooked)).is_err() {
+                                return;
+                            }
+                        }
+                        Err(_) => return, // loader was dropped
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            jobs: Some(jobs),
+            results,
+            workers,
+            submitted: 0,
+            finished: 0,
+        }
+    }
+
+    pub fn submit(&mut self, job: Job) {
+        self.submitted += 1;
+        self.jobs.as_ref().unwrap().send(job).unwrap();
+    }
+
+    pub fn try_recv(&mut self) -> Option<Loaded> {
+        let loaded = self.results.try_recv().ok()?;
+        self.finished += 1;
+        Some(loaded)
+    }
+
+    // (finished, submitted) since the start, enough for a loading bar
+    pub fn progress(&self) -> (usize, usize) {
+        (self.finished, self.submitted)
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.finished == self.submitted
+    }
+
+    fn work(job: Job, vfs: &Vfs, cooked: &Manifest) -> Loaded {
+        match job {
+            Job::Model { key, path, options } => {
+                let model = ImportedModel::load(&path, &options, vfs, cooked);
+                Loaded::Model {
+                    key,
+                    path,
+                    options,
+                    model,
+                }
+            }
+            Job::Image {
+                path,
+                data,
+                targets,
+            } => {
+                if let Some(images) =
+                    Self::cooked_images(&path, data.is_some(), &targets, vfs, cooked)
+                {
+                    return Loaded::Image {
+                        path,
+                        images: Some(images),
+                    };
+                }
+                // The flip flag is global in stb_image, it is always set to the same value
+                let bytes = match data {
+                    Some(bytes) => Some(bytes),
+                    None => vfs.read(&path).ok(),
+                };
+                let image = bytes.and_then(|bytes| texture_formats::decode(&bytes, true));
+                let images = image.map(|image| {
+                    targets
+                        .iter()
+                        .filter_map(|target| {
+                            // DDS and KTX2 files can be block compressed as they are
+                            if image.compression.is_some() && target.channel.is_some() {
+                                println!("Can't take a channel out of compressed image {path}");
+                                return None;
+                            }
+                            Some((target.index, image.with_channel(target.channel)))
+                        })
+                        .collect()
+                });
+                Loaded::Image { path, images }
+            }
+        }
+    }
+
+    // Channels can't be cut out of block compressed levels, the source is decoded for
+    // such targets instead
+    fn cooked_images(
+        path: &str,
+        embedded: bool,
+        targets: &[ImageTarget],
+        vfs: &Vfs,
+        cooked: &Manifest,
+    ) -> Option<Vec<(usize, DecodedImage)>> {
+        if embedded {
+            return None;
+        }
+        let image = cook::read_texture(&cooked.cooked(vfs, path, "")?)?;
+        if image.compression.is_some() && targets.iter().any(|target| target.channel.is_some()) {
+            return None;
+        }
+        Some(
+            targets
+                .iter()
+                .map(|target| (target.index, image.with_channel(target.channel)))
+                .collect(),
+        )
+    }
+}
+
+impl Drop for AssetLoader {
+    fn drop(&mut self) {
+        // Closing the channel lets the workers finish their current job and exit
+        _ = self.jobs.take();
+        for worker in self.workers.drain(..) {
+            _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::vfs;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    env, fmt,
+    hash::{Hash, Hasher},
+    path::Path,
+    sync::OnceLock,
+};
+
+pub const SCHEME: &str = "asset://";
+// Generated meshes, see `procedural`. They are kept as written and never touch the disk
+pub const BUILTIN_SCHEME: &str = "builtin://";
+const DEFAULT_ROOT: &str = "assets";
+const ROOT_VARIABLE: &str = "ASSET_ROOT";
+
+// Directory asset paths are resolved against, "assets" in the working directory unless
+// ASSET_ROOT points elsewhere
+pub fn root() -> &'static str {
+    static ROOT: OnceLock<String> = OnceLock::new();
+    ROOT.get_or_init(|| {
+        let root = env::var(ROOT_VARIABLE).unwrap_or_else(|_| DEFAULT_ROOT.to_string());
+        vfs::normalize(&root)
+    })
+}
+
+// File under the asset root, written the same way on every platform:
+// "asset://meshes/boulder_01.gltf". Assets are authored on Windows too, so paths compare
+// ignoring case
+#[derive(Clone, Debug)]
+pub struct AssetPath(String); // relative to the root, forward slashes, no "." or ".."
+
+impl AssetPath {
+    // Paths without the scheme are relative to the root as well, except for the ones that
+    // start with it, "assets\\meshes\\boulder_01.gltf", which older scene files contain
+    pub fn new(path: &str) -> Self {
+        if path.starts_with(BUILTIN_SCHEME) {
+            return Self(path.to_string());
+        }
+        if let Some(relative) = path.strip_prefix(SCHEME) {
+            return Self(vfs::normalize(relative));
+        }
+        let path = vfs::normalize(path);
+        for root in [root(), DEFAULT_ROOT] {
+            if let Some(relative) = strip_prefix_ignoring_case(&path, &format!("{root}/")) {
+                return Self(relative.to_string());
+            }
+        }
+        Self(path)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    // Path the file is read from, relative to the working directory like everything the
+    // Vfs takes
+    pub fn resolve(&self) -> String {
+        if self.is_builtin() {
+            return self.0.clone();
+        }
+        match root() {
+            "" => self.0.clone(),
+            root => format!("{root}/{}", self.0),
+        }
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        self.0.starts_with(BUILTIN_SCHEME)
+    }
+
+    // `relative` as written in a file next to this one
+    pub fn join(&self, relative: &str) -> Self {
+        Self(join(&self.0, relative))
+    }
+}
+
+// Resolves a path found inside `file_path`, a texture of a model or a material asset.
+// Those may be written with either separator no matter which platform reads them
+pub fn join(file_path: &str, relative: &str) -> String {
+    let relative = relative.replace('\\', "/");
+    if Path::new(&relative).is_absolute() {
+        return vfs::normalize(&relative);
+    }
+    let file_path = file_path.replace('\\', "/");
+    match file_path.rsplit_once('/') {
+        Some((dir, _)) => vfs::normalize(&format!("{dir}/{relative}")),
+        None => vfs::normalize(&relative),
+    }
+}
+
+fn strip_prefix_ignoring_case<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = path.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &path[prefix.len()..])
+}
+
+impl PartialEq for AssetPath {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+}
+
+impl Eq for AssetPath {}
+
+impl Hash for AssetPath {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_ascii_lowercase().hash(state);
+    }
+}
+
+impl fmt::Display for AssetPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_builtin() {
+            return write!(f, "{}", self.0);
+        }
+        write!(f, "{SCHEME}{}", self.0)
+    }
+}
+
+impl Serialize for AssetPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for AssetPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let path = String::deserialize(deserializer)?;
+        Ok(Self::new(&path))
+    }
+}
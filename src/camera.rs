@@ -21,6 +21,10 @@ impl Camera {
         self.projection_matrix * linear::view_matrix(transform)
     }
 
+    pub fn projection(&self) -> &Projection {
+        &self.projection
+    }
+
     pub fn set_projection(&mut self, projection: Projection) {
         self.projection = projection;
         self.projection_matrix = projection.matrix();
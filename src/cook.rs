@@ -0,0 +1,702 @@
+use crate::{
+    asset_loader::{DecodedImage, PixelType},
+    asset_path::{self, AssetPath},
+    resources::ImportedModel,
+    serializable::ImportOptions,
+    scripting::Scripting,
+    texture_formats,
+    vfs::{self, Vfs},
+};
+use fxhash::FxHasher64;
+use gl::types::GLenum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    array,
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    hash::Hasher,
+    mem,
+    path::Path,
+    process, ptr,
+};
+
+const USAGE: &str = "usage:
+    cook [--compress] [--force]
+
+Pre-processes models, images and scripts under the asset root (assets/ unless ASSET_ROOT is
+set) into cache/. The engine loads cooked data instead of the source as long as the content
+hash in the manifest still matches
+
+    --compress  stores 8-bit images block compressed (BC1, BC3, BC4, BC5). DDS and KTX2
+                files are always kept in their own format
+    --force     cooks everything again, even when the cache is up to date";
+
+const SCRIPT_API_DIR: &str = "scripts/api"; // annotations for the language server
+const CACHE_DIR: &str = "cache";
+const MANIFEST: &str = "cache/manifest.json";
+// Bump whenever a blob layout changes, everything cooked before is then out of date
+const COOK_VERSION: u32 = 8;
+// Arrays are stored as raw memory and data.pak carries cache/ to other machines, so blobs
+// are only taken on targets with the same byte order and pointer width
+const TARGET_LAYOUT: u32 = (cfg!(target_endian = "big") as u32) << 8 | usize::BITS;
+
+pub const MODEL_EXTENSIONS: [&str; 6] = ["fbx", "gltf", "glb", "obj", "dae", "3ds"];
+pub const IMAGE_EXTENSIONS: [&str; 10] = [
+    "png", "jpg", "jpeg", "tga", "bmp", "psd", "gif", "hdr", "dds", "ktx2",
+];
+
+// From EXT_texture_compression_s3tc and EXT_texture_sRGB, the core profile bindings lack them
+const COMPRESSED_RGB_S3TC_DXT1: GLenum = 0x83F0;
+const COMPRESSED_RGBA_S3TC_DXT3: GLenum = 0x83F2;
+const COMPRESSED_RGBA_S3TC_DXT5: GLenum = 0x83F3;
+const COMPRESSED_SRGB_S3TC_DXT1: GLenum = 0x8C4C;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT3: GLenum = 0x8C4E;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT5: GLenum = 0x8C4F;
+
+const TEXTURE_MAGIC: &[u8; 4] = b"LGTX";
+
+pub fn run(args: &[String]) {
+    let mut compress = false;
+    let mut force = false;
+    for arg in args {
+        match arg.as_str() {
+            "--compress" => compress = true,
+            "--force" => force = true,
+            _ => {
+                eprintln!("{USAGE}");
+                process::exit(2);
+            }
+        }
+    }
+
+    // Cooking always reads the loose sources
+    let vfs = Vfs::Loose;
+    let mut sources = vfs.list(asset_path::root());
+    sources.sort();
+
+    let mut manifest = Manifest::load(&vfs);
+    let scripting = Scripting::new();
+    let mut keys = BTreeSet::new();
+    let mut failed = false;
+
+    for path in &sources {
+        let kind = match Kind::of(path) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        let key = vfs::normalize(path);
+        _ = keys.insert(key.clone());
+
+        let hash = match source_hash(&vfs, path, &kind.salt()) {
+            Some(hash) => hash,
+            None => {
+                eprintln!("Failed to read {path} or its dependencies");
+                failed = true;
+                continue;
+            }
+        };
+        let options = kind.options(compress);
+        let up_to_date = manifest.entries.get(&key).is_some_and(|entry| {
+            entry.hash == hash && entry.options == options && Path::new(&entry.blob).exists()
+        });
+        if up_to_date && !force {
+            continue;
+        }
+
+        let blob = match kind.cook(&vfs, path, compress, &scripting) {
+            Ok(blob) => blob,
+            Err(error) => {
+                eprintln!("Failed to cook {path}: {error}");
+                failed = true;
+                continue;
+            }
+        };
+        let blob_path = format!("{CACHE_DIR}/{key}.{}", kind.extension());
+        fs::create_dir_all(Path::new(&blob_path).parent().unwrap()).unwrap();
+        fs::write(&blob_path, blob).unwrap();
+        println!("cooked {key}");
+
+        let entry = ManifestEntry {
+            hash,
+            options,
+            blob: blob_path,
+        };
+        _ = manifest.entries.insert(key, entry);
+    }
+
+    // Sources that were deleted or renamed
+    manifest.entries.retain(|key, entry| {
+        let keep = keys.contains(key);
+        if !keep {
+            _ = fs::remove_file(&entry.blob);
+        }
+        keep
+    });
+    manifest.save();
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Model,
+    Image,
+    Script,
+}
+
+impl Kind {
+    fn of(path: &str) -> Option<Self> {
+        if Path::new(AssetPath::new(path).as_str()).starts_with(SCRIPT_API_DIR) {
+            return None;
+        }
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        if MODEL_EXTENSIONS.contains(&extension.as_str()) {
+            Some(Kind::Model)
+        } else if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+            Some(Kind::Image)
+        } else if extension == "lua" {
+            Some(Kind::Script)
+        } else {
+            None
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Kind::Model => "mesh",
+            Kind::Image => "tex",
+            Kind::Script => "luac",
+        }
+    }
+
+    // Settings the blob depends on that the engine passes when loading, see `Manifest::cooked`
+    fn salt(&self) -> String {
+        match self {
+            Kind::Model => ImportOptions::default().salt(),
+            Kind::Image | Kind::Script => String::new(),
+        }
+    }
+
+    // Settings of the cook itself, the engine takes whatever the blob says
+    fn options(&self, compress: bool) -> String {
+        match self {
+            Kind::Image if compress => "compress".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn cook(
+        &self,
+        vfs: &Vfs,
+        path: &str,
+        compress: bool,
+        scripting: &Scripting,
+    ) -> Result<Vec<u8>, String> {
+        match self {
+            Kind::Model => {
+                let model = ImportedModel::read(path, &ImportOptions::default(), vfs)
+                    .map_err(|error| error.to_string())?;
+                Ok(model.to_cooked())
+            }
+            Kind::Image => {
+                let bytes = vfs.read(path).map_err(|error| error.to_string())?;
+                // Flipped the same way as images decoded at runtime
+                let image = texture_formats::decode(&bytes, true).ok_or("failed to decode")?;
+                Ok(write_texture(&cook_texture(image, compress)))
+            }
+            Kind::Script => {
+                let src = fs::read_to_string(path).map_err(|error| error.to_string())?;
+                let compiled = scripting
+                    .compile_script(&src, path)
+                    .map_err(|error| error.to_string())?;
+                Ok(compiled.data().to_vec())
+            }
+        }
+    }
+}
+
+// Lists cooked blobs by asset key, written by the cook and read by the engine on startup
+#[derive(Serialize, Deserialize, Default)]
+pub struct Manifest {
+    entries: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestEntry {
+    hash: String,
+    options: String,
+    blob: String,
+}
+
+impl Manifest {
+    // Nothing is cooked when there is no manifest, everything is loaded from the sources
+    pub fn load(vfs: &Vfs) -> Self {
+        vfs.read_to_string(MANIFEST)
+            .ok()
+            .and_then(|json_str| serde_json::from_str(&json_str).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) {
+        fs::create_dir_all(CACHE_DIR).unwrap();
+        let json_str = serde_json::to_string_pretty(self).unwrap();
+        fs::write(MANIFEST, json_str).unwrap();
+    }
+
+    // Blob cooked from `path` if the source, its dependencies and `salt` are the same
+    // as when it was cooked. Hashing the source is much cheaper than importing it
+    pub fn cooked(&self, vfs: &Vfs, path: &str, salt: &str) -> Option<Vec<u8>> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let entry = self.entries.get(&vfs::normalize(path))?;
+        if source_hash(vfs, path, salt)? != entry.hash {
+            return None;
+        }
+        vfs.read(&entry.blob).ok()
+    }
+}
+
+fn source_hash(vfs: &Vfs, path: &str, salt: &str) -> Option<String> {
+    let mut hasher = FxHasher64::default();
+    hasher.write_u32(COOK_VERSION);
+    hasher.write_u32(TARGET_LAYOUT);
+    hasher.write(salt.as_bytes());
+    hasher.write(&vfs.read(path).ok()?);
+    for dependency in dependencies(vfs, path) {
+        hasher.write(&vfs.read(&dependency).ok()?);
+    }
+    Some(format!("{:016x}", hasher.finish()))
+}
+
+// Files the source can't be imported without. Only buffers of .gltf files for now,
+// images referenced by models are cooked on their own
+pub fn dependencies(vfs: &Vfs, path: &str) -> Vec<String> {
+    let is_gltf = Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gltf"));
+    if !is_gltf {
+        return vec![];
+    }
+    let gltf = match vfs
+        .read_to_string(path)
+        .map(|json_str| serde_json::from_str::<Value>(&json_str))
+    {
+        Ok(Ok(gltf)) => gltf,
+        _ => return vec![],
+    };
+
+    let dir = Path::new(path).parent().unwrap();
+    gltf["buffers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|buffer| buffer["uri"].as_str())
+        .filter(|uri| !uri.starts_with("data:"))
+        .map(|uri| dir.join(uri).to_str().unwrap().to_string())
+        .collect()
+}
+
+pub struct BlobWriter(Vec<u8>);
+
+impl BlobWriter {
+    pub fn new(magic: &[u8; 4]) -> Self {
+        let mut blob = Self(magic.to_vec());
+        blob.u32(COOK_VERSION);
+        blob.u32(TARGET_LAYOUT);
+        blob
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.0.extend_from_slice(bytes);
+    }
+
+    // Plain data is stored as it is laid out in memory, see `TARGET_LAYOUT`
+    pub fn array<T: Copy>(&mut self, items: &[T]) {
+        self.u32(mem::size_of::<T>() as u32);
+        let bytes = unsafe {
+            std::slice::from_raw_parts(items.as_ptr().cast::<u8>(), mem::size_of_val(items))
+        };
+        self.bytes(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+pub struct BlobReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BlobReader<'a> {
+    pub fn new(data: &'a [u8], magic: &[u8; 4]) -> Option<Self> {
+        let mut blob = Self { data, pos: 0 };
+        if blob.take(magic.len())? != magic
+            || blob.u32()? != COOK_VERSION
+            || blob.u32()? != TARGET_LAYOUT
+        {
+            return None;
+        }
+        Some(blob)
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn array<T: Copy>(&mut self) -> Option<Vec<T>> {
+        if self.u32()? as usize != mem::size_of::<T>() {
+            return None;
+        }
+        let bytes = self.bytes()?;
+        // A partial item means the blob is corrupt
+        if bytes.len() % mem::size_of::<T>() != 0 {
+            return None;
+        }
+        let len = bytes.len() / mem::size_of::<T>();
+        let mut items = Vec::<T>::with_capacity(len);
+        // The blob gives no alignment guarantees, so it is copied byte by byte
+        unsafe {
+            let size = len * mem::size_of::<T>();
+            ptr::copy_nonoverlapping(bytes.as_ptr(), items.as_mut_ptr().cast::<u8>(), size);
+            items.set_len(len);
+        }
+        Some(items)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compression {
+    Bc1,  // rgb
+    Bc2,  // rgba, explicit alpha
+    Bc3,  // rgba
+    Bc4,  // r
+    Bc5,  // rg
+    Bc6h, // rgb half float, unsigned
+    Bc7,  // rgba
+}
+
+impl Compression {
+    pub fn channels(&self) -> usize {
+        match self {
+            Compression::Bc4 => 1,
+            Compression::Bc5 => 2,
+            Compression::Bc1 | Compression::Bc6h => 3,
+            Compression::Bc2 | Compression::Bc3 | Compression::Bc7 => 4,
+        }
+    }
+
+    // Bytes per 4x4 block
+    pub fn block_size(&self) -> usize {
+        match self {
+            Compression::Bc1 | Compression::Bc4 => 8,
+            _ => 16,
+        }
+    }
+
+    fn for_channels(channels: usize) -> Self {
+        match channels {
+            1 => Compression::Bc4,
+            2 => Compression::Bc5,
+            3 => Compression::Bc1,
+            4 => Compression::Bc3,
+            _ => unreachable!(),
+        }
+    }
+
+    fn id(&self) -> u32 {
+        match self {
+            Compression::Bc1 => 1,
+            Compression::Bc2 => 2,
+            Compression::Bc3 => 3,
+            Compression::Bc4 => 4,
+            Compression::Bc5 => 5,
+            Compression::Bc6h => 6,
+            Compression::Bc7 => 7,
+        }
+    }
+
+    fn from_id(id: u32) -> Option<Option<Self>> {
+        match id {
+            0 => Some(None),
+            1 => Some(Some(Compression::Bc1)),
+            2 => Some(Some(Compression::Bc2)),
+            3 => Some(Some(Compression::Bc3)),
+            4 => Some(Some(Compression::Bc4)),
+            5 => Some(Some(Compression::Bc5)),
+            6 => Some(Some(Compression::Bc6h)),
+            7 => Some(Some(Compression::Bc7)),
+            _ => None,
+        }
+    }
+
+    pub fn internal_format(&self, srgb: bool) -> GLenum {
+        match (self, srgb) {
+            (Compression::Bc1, false) => COMPRESSED_RGB_S3TC_DXT1,
+            (Compression::Bc1, true) => COMPRESSED_SRGB_S3TC_DXT1,
+            (Compression::Bc2, false) => COMPRESSED_RGBA_S3TC_DXT3,
+            (Compression::Bc2, true) => COMPRESSED_SRGB_ALPHA_S3TC_DXT3,
+            (Compression::Bc3, false) => COMPRESSED_RGBA_S3TC_DXT5,
+            (Compression::Bc3, true) => COMPRESSED_SRGB_ALPHA_S3TC_DXT5,
+            (Compression::Bc4, _) => gl::COMPRESSED_RED_RGTC1,
+            (Compression::Bc5, _) => gl::COMPRESSED_RG_RGTC2,
+            (Compression::Bc6h, _) => gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+            (Compression::Bc7, false) => gl::COMPRESSED_RGBA_BPTC_UNORM,
+            (Compression::Bc7, true) => gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+        }
+    }
+}
+
+pub fn level_size(size: (usize, usize), level: usize) -> (usize, usize) {
+    ((size.0 >> level).max(1), (size.1 >> level).max(1))
+}
+
+// DDS and KTX2 files are stored with the levels and format they came with. 16-bit and float
+// images keep a single level, the box filter only handles bytes, their mipmaps are generated
+// on upload
+fn cook_texture(image: DecodedImage, compress: bool) -> DecodedImage {
+    if image.levels.len() > 1 || image.compression.is_some() || image.pixel_type != PixelType::U8 {
+        return image;
+    }
+
+    let size = image.size;
+    let channels = image.channels;
+    let mut levels = mip_chain(&image.levels[0], size, channels);
+
+    let compression = compress.then(|| Compression::for_channels(channels));
+    if let Some(compression) = compression {
+        levels = levels
+            .iter()
+            .enumerate()
+            .map(|(level, data)| {
+                compress_level(data, level_size(size, level), channels, compression)
+            })
+            .collect();
+    }
+
+    DecodedImage {
+        levels,
+        size,
+        channels,
+        pixel_type: PixelType::U8,
+        compression,
+    }
+}
+
+fn write_texture(image: &DecodedImage) -> Vec<u8> {
+    let mut blob = BlobWriter::new(TEXTURE_MAGIC);
+    blob.u32(image.size.0 as u32);
+    blob.u32(image.size.1 as u32);
+    blob.u32(image.channels as u32);
+    blob.u32(image.pixel_type.id());
+    blob.u32(image.compression.map_or(0, |compression| compression.id()));
+    blob.u32(image.levels.len() as u32);
+    for level in &image.levels {
+        blob.bytes(level);
+    }
+    blob.finish()
+}
+
+pub fn read_texture(data: &[u8]) -> Option<DecodedImage> {
+    let mut blob = BlobReader::new(data, TEXTURE_MAGIC)?;
+    let size = (blob.u32()? as usize, blob.u32()? as usize);
+    let channels = blob.u32()? as usize;
+    let pixel_type = PixelType::from_id(blob.u32()?)?;
+    let compression = Compression::from_id(blob.u32()?)?;
+    let level_count = blob.u32()?;
+    let levels = (0..level_count)
+        .map(|_| blob.bytes().map(|level| level.to_vec()))
+        .collect::<Option<Vec<Vec<u8>>>>()?;
+
+    Some(DecodedImage {
+        levels,
+        size,
+        channels,
+        pixel_type,
+        compression,
+    })
+}
+
+// Box filtered levels down to 1x1, the last row and column are repeated for odd sizes
+fn mip_chain(data: &[u8], size: (usize, usize), channels: usize) -> Vec<Vec<u8>> {
+    let mut levels = vec![data.to_vec()];
+    let mut size = size;
+
+    while size.0 > 1 || size.1 > 1 {
+        let next = level_size(size, 1);
+        let prev = levels.last().unwrap();
+        let mut level = Vec::with_capacity(next.0 * next.1 * channels);
+
+        for y in 0..next.1 {
+            for x in 0..next.0 {
+                for c in 0..channels {
+                    let mut sum = 0;
+                    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let sx = (x * 2 + dx).min(size.0 - 1);
+                        let sy = (y * 2 + dy).min(size.1 - 1);
+                        sum += prev[(sy * size.0 + sx) * channels + c] as u32;
+                    }
+                    level.push(((sum + 2) / 4) as u8);
+                }
+            }
+        }
+
+        levels.push(level);
+        size = next;
+    }
+
+    levels
+}
+
+fn compress_level(
+    data: &[u8],
+    size: (usize, usize),
+    channels: usize,
+    compression: Compression,
+) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for by in (0..size.1).step_by(4) {
+        for bx in (0..size.0).step_by(4) {
+            // Texels past the edge repeat the last row and column
+            let texel = |i: usize, c: usize| {
+                let x = (bx + i % 4).min(size.0 - 1);
+                let y = (by + i / 4).min(size.1 - 1);
+                data[(y * size.0 + x) * channels + c]
+            };
+            let channel = |c: usize| array::from_fn::<u8, 16, _>(|i| texel(i, c));
+            let rgb =
+                || array::from_fn::<[u8; 3], 16, _>(|i| [texel(i, 0), texel(i, 1), texel(i, 2)]);
+
+            match compression {
+                Compression::Bc1 => out.extend(bc1_block(&rgb())),
+                Compression::Bc3 => {
+                    out.extend(bc4_block(&channel(3)));
+                    out.extend(bc1_block(&rgb()));
+                }
+                Compression::Bc4 => out.extend(bc4_block(&channel(0))),
+                Compression::Bc5 => {
+                    out.extend(bc4_block(&channel(0)));
+                    out.extend(bc4_block(&channel(1)));
+                }
+                // Only read from DDS and KTX2 files, never encoded here
+                Compression::Bc2 | Compression::Bc6h | Compression::Bc7 => unreachable!(),
+            }
+        }
+    }
+
+    out
+}
+
+// Endpoints are the corners of the color bounding box, which is fast and good enough for
+// photos and painted textures
+fn bc1_block(texels: &[[u8; 3]; 16]) -> [u8; 8] {
+    let mut min = [u8::MAX; 3];
+    let mut max = [0; 3];
+    for texel in texels {
+        for c in 0..3 {
+            min[c] = min[c].min(texel[c]);
+            max[c] = max[c].max(texel[c]);
+        }
+    }
+    // Pulling the box in a bit lowers the error for most blocks
+    for c in 0..3 {
+        let inset = (max[c] - min[c]) / 16;
+        min[c] += inset;
+        max[c] -= inset;
+    }
+
+    // max >= min for every channel, so c0 >= c1 and the block stays in four color mode
+    let c0 = to_565(max);
+    let c1 = to_565(min);
+    let mut indices = 0u32;
+    if c0 != c1 {
+        let p0 = from_565(c0);
+        let p1 = from_565(c1);
+        let palette = [
+            p0,
+            p1,
+            array::from_fn(|c| (2 * p0[c] + p1[c]) / 3),
+            array::from_fn(|c| (p0[c] + 2 * p1[c]) / 3),
+        ];
+        for (i, texel) in texels.iter().enumerate() {
+            let distance = |color: &[i32; 3]| {
+                (0..3)
+                    .map(|c| (color[c] - texel[c] as i32).pow(2))
+                    .sum::<i32>()
+            };
+            let best = (0..4usize)
+                .min_by_key(|index| distance(&palette[*index]))
+                .unwrap();
+            indices |= (best as u32) << (2 * i);
+        }
+    }
+
+    let mut block = [0; 8];
+    block[0..2].copy_from_slice(&c0.to_le_bytes());
+    block[2..4].copy_from_slice(&c1.to_le_bytes());
+    block[4..8].copy_from_slice(&indices.to_le_bytes());
+    block
+}
+
+fn to_565(color: [u8; 3]) -> u16 {
+    ((color[0] as u16 >> 3) << 11) | ((color[1] as u16 >> 2) << 5) | (color[2] as u16 >> 3)
+}
+
+fn from_565(color: u16) -> [i32; 3] {
+    let r = (color >> 11) as i32;
+    let g = ((color >> 5) & 0x3f) as i32;
+    let b = (color & 0x1f) as i32;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+fn bc4_block(values: &[u8; 16]) -> [u8; 8] {
+    let r0 = *values.iter().max().unwrap();
+    let r1 = *values.iter().min().unwrap();
+
+    let mut block = [0; 8];
+    block[0] = r0;
+    block[1] = r1;
+    if r0 > r1 {
+        // r0 > r1 selects the mode with six interpolated values
+        let palette = array::from_fn::<i32, 8, _>(|index| match index {
+            0 => r0 as i32,
+            1 => r1 as i32,
+            _ => ((8 - index as i32) * r0 as i32 + (index as i32 - 1) * r1 as i32) / 7,
+        });
+        let mut bits = 0u64;
+        for (i, value) in values.iter().enumerate() {
+            let best = (0..8usize)
+                .min_by_key(|index| (palette[*index] - *value as i32).abs())
+                .unwrap();
+            bits |= (best as u64) << (3 * i);
+        }
+        block[2..8].copy_from_slice(&bits.to_le_bytes()[..6]);
+    }
+    block
+}
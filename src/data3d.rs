@@ -3,26 +3,206 @@ use crate::{
     resources::RangeIndex, serializable::MaterialInfo,
 };
 use gl::types::GLenum;
-use russimp::{Vector2D, Vector3D};
-use std::{ffi::c_void, mem::size_of};
+use glm::{Mat4, Vec3};
+use russimp::{Color4D, Vector2D, Vector3D};
+use std::{
+    ffi::c_void,
+    mem::{size_of, size_of_val},
+    ptr,
+};
 
 pub const QUAD_VERTICES_TEX_COORDS: &[f32] = &[
     -1.0, -1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0, -1.0, 1.0, 0.0, 0.0, 1.0, -1.0, -1.0, 0.0,
     0.0, 0.0, 1.0, -1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0,
 ];
 
+// Blend shapes a submesh is drawn with, the ones past it are dropped on import
+pub const MAX_MORPH_TARGETS: usize = 64;
+
 #[repr(C)]
+#[derive(Clone, Copy, Debug)]
 pub struct Vertex {
     pub position: Vector3D,
     pub normal: Vector3D,
     pub tex_coord: Vector2D,
+    pub tangent: Vector3D, // zero when the mesh has no texture coordinates
+    pub bitangent: Vector3D,
+    pub color: Color4D,
+    pub tex_coord2: Vector2D, // lightmap coordinates
+    pub bone_indices: [f32; 4],
+    pub bone_weights: [f32; 4], // zero when the mesh isn't skinned
 }
 
-#[derive(PartialEq)]
+impl Vertex {
+    // In the order of the fields
+    pub const ATTRIBUTES: [VertexAttribute; 9] = [
+        VertexAttribute::Position,
+        VertexAttribute::Normal,
+        VertexAttribute::TexCoord,
+        VertexAttribute::Tangent,
+        VertexAttribute::Bitangent,
+        VertexAttribute::Color,
+        VertexAttribute::TexCoord2,
+        VertexAttribute::BoneIndices,
+        VertexAttribute::BoneWeights,
+    ];
+
+    // Keeps the four largest weights
+    pub fn add_bone_weight(&mut self, bone: usize, weight: f32) {
+        let (slot, smallest) = self
+            .bone_weights
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        if weight > *smallest {
+            self.bone_indices[slot] = bone as f32;
+            self.bone_weights[slot] = weight;
+        }
+    }
+
+    // Weights dropped by `add_bone_weight` are made up for by the others
+    pub fn normalize_bone_weights(&mut self) {
+        let sum = self.bone_weights.iter().sum::<f32>();
+        if sum > 0.0 {
+            for weight in &mut self.bone_weights {
+                *weight /= sum;
+            }
+        }
+    }
+}
+
+// Box along the axes and a sphere around the vertices, in the space of the model. Skinned
+// and morphed meshes are bounded in their rest pose
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub center: Vec3, // of the sphere
+    pub radius: f32,
+}
+
+impl Bounds {
+    pub fn from_vertices(vertices: &[Vertex]) -> Self {
+        let position =
+            |vertex: &Vertex| glm::vec3(vertex.position.x, vertex.position.y, vertex.position.z);
+        let first = match vertices.first() {
+            Some(vertex) => position(vertex),
+            None => return Self::default(),
+        };
+        let (min, max) = vertices
+            .iter()
+            .map(position)
+            .fold((first, first), |(min, max), position| {
+                (min.inf(&position), max.sup(&position))
+            });
+        // Centered on the box, which is close enough to the smallest sphere for culling
+        let center = (min + max) / 2.0;
+        let radius = vertices
+            .iter()
+            .map(|vertex| glm::distance(&position(vertex), &center))
+            .fold(0.0, f32::max);
+
+        Self {
+            min,
+            max,
+            center,
+            radius,
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        let offset = other.center - self.center;
+        let distance = offset.norm();
+        let (center, radius) = if distance + other.radius <= self.radius {
+            (self.center, self.radius)
+        } else if distance + self.radius <= other.radius {
+            (other.center, other.radius)
+        } else {
+            let radius = (distance + self.radius + other.radius) / 2.0;
+            (
+                self.center + offset * ((radius - self.radius) / distance),
+                radius,
+            )
+        };
+
+        Self {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+            center,
+            radius,
+        }
+    }
+
+    // Bounds of the transformed mesh, the box is grown to hold the transformed one
+    pub fn transformed(&self, matrix: &Mat4) -> Self {
+        let point = |p: Vec3| (matrix * p.push(1.0)).xyz();
+        let corners = (0..8).map(|i| {
+            glm::vec3(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        });
+        let first = point(self.min);
+        let (min, max) = corners
+            .map(point)
+            .fold((first, first), |(min, max), corner| {
+                (min.inf(&corner), max.sup(&corner))
+            });
+        let scale = (0..3)
+            .map(|i| matrix.fixed_view::<3, 1>(0, i).norm())
+            .fold(0.0, f32::max);
+
+        Self {
+            min,
+            max,
+            center: point(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+// Offsets of a blend shape from the vertices of a submesh, one per vertex
+pub struct MorphTarget {
+    pub name: String,
+    pub deltas: Vec<MorphDelta>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MorphDelta {
+    pub position: [f32; 4], // w is padding, vec3 arrays are laid out as vec4 ones
+    pub normal: [f32; 4],
+}
+
+// The value is the shader location of the attribute
+#[derive(PartialEq, Clone, Copy)]
 pub enum VertexAttribute {
     Position,
     Normal,
     TexCoord,
+    Tangent,
+    Bitangent,
+    Color,
+    TexCoord2,
+    BoneIndices, // floats as well, exact for any index the shader takes
+    BoneWeights,
+}
+
+impl VertexAttribute {
+    fn components(self) -> usize {
+        match self {
+            VertexAttribute::TexCoord | VertexAttribute::TexCoord2 => 2,
+            VertexAttribute::Position
+            | VertexAttribute::Normal
+            | VertexAttribute::Tangent
+            | VertexAttribute::Bitangent => 3,
+            VertexAttribute::Color
+            | VertexAttribute::BoneIndices
+            | VertexAttribute::BoneWeights => 4,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -32,6 +212,16 @@ pub struct MeshData {
     ebo: BufferObject,
     pub vertex_count: i32,
     pub index_count: i32,
+    vertex_data_size: usize, // of the buffers, dynamic ones may hold less
+    index_data_size: usize,
+    usage: GLenum,
+    pub skinned: bool, // drawn with the bones of a `Skeleton`
+    // Names of the blend shapes, in the order of their deltas
+    pub morph_targets: Vec<String>,
+    morph_buffer: Option<BufferObject>,
+    morph_data_size: usize,
+    pub bounds: Bounds,
+    geometry: Option<(Vec<Vertex>, Vec<u32>)>, // CPU copy, kept when asked for
 }
 
 impl MeshData {
@@ -63,22 +253,102 @@ impl MeshData {
             ebo: element_buffer,
             vertex_count,
             index_count,
+            vertex_data_size,
+            index_data_size: index_count as usize * size_of::<u32>(),
+            usage,
+            skinned: false,
+            morph_targets: Vec::new(),
+            morph_buffer: None,
+            morph_data_size: 0,
+            bounds: Bounds::default(),
+            geometry: None,
+        }
+    }
+
+    // For tools and physics, the buffers can't be read back cheaply
+    pub fn retain_geometry(&mut self, vertices: Vec<Vertex>, indices: Vec<u32>) {
+        self.geometry = Some((vertices, indices));
+    }
+
+    pub fn geometry(&self) -> Option<(&[Vertex], &[u32])> {
+        self.geometry
+            .as_ref()
+            .map(|(vertices, indices)| (vertices.as_slice(), indices.as_slice()))
+    }
+
+    // Bytes taken by the vertex, index and blend shape buffers
+    pub fn memory_size(&self) -> usize {
+        self.vertex_data_size + self.index_data_size + self.morph_data_size
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.usage != gl::STATIC_DRAW
+    }
+
+    // Replaces the geometry of a mesh created with a dynamic usage, meant to be called every
+    // frame. The buffers are orphaned first so the driver hands out fresh storage instead of
+    // waiting for draws still reading the old one, and they only ever grow
+    pub fn update(&mut self, vertices: &[Vertex], indices: &[u32]) {
+        self.vao.bind();
+        Self::stream(&self.vbo, &mut self.vertex_data_size, vertices, self.usage);
+        Self::stream(&self.ebo, &mut self.index_data_size, indices, self.usage);
+
+        self.vertex_count = vertices.len() as i32;
+        self.index_count = indices.len() as i32;
+        self.skinned = vertices
+            .iter()
+            .any(|vertex| vertex.bone_weights.iter().any(|weight| *weight > 0.0));
+        self.bounds = Bounds::from_vertices(vertices);
+        if self.geometry.is_some() {
+            self.geometry = Some((vertices.to_vec(), indices.to_vec()));
+        }
+    }
+
+    fn stream<T>(buffer: &BufferObject, capacity: &mut usize, data: &[T], usage: GLenum) {
+        let size = size_of_val(data);
+        if size > *capacity {
+            *capacity = size.next_power_of_two();
+        }
+        buffer.bind();
+        buffer.buffer_data(*capacity, ptr::null(), usage);
+        buffer.buffer_subdata(size, data.as_ptr().cast(), 0);
+    }
+
+    // Deltas of every target one after another, read by the vertex shader from a storage
+    // buffer
+    pub fn set_morph_targets(&mut self, targets: &[MorphTarget]) {
+        if targets.is_empty() {
+            return;
+        }
+        let deltas = targets
+            .iter()
+            .flat_map(|target| target.deltas.iter().copied())
+            .collect::<Vec<MorphDelta>>();
+        let size = size_of_val(deltas.as_slice());
+        let buffer = BufferObject::new(gl::SHADER_STORAGE_BUFFER).unwrap();
+        buffer.bind();
+        buffer.buffer_data(size, deltas.as_ptr().cast(), gl::STATIC_DRAW);
+
+        self.morph_targets = targets.iter().map(|target| target.name.clone()).collect();
+        self.morph_buffer = Some(buffer);
+        self.morph_data_size = size;
+    }
+
+    pub fn bind_morph_targets(&self, binding: u32) {
+        if let Some(buffer) = &self.morph_buffer {
+            buffer.bind_buffer_base(binding);
         }
     }
 
     pub fn from_vertex_index_data(
-        vertex_data: &Vec<Vertex>,
-        index_data: &Vec<u32>,
+        vertex_data: &[Vertex],
+        index_data: &[u32],
         usage: GLenum,
     ) -> MeshData {
-        let attributes = vec![
-            VertexAttribute::Position,
-            VertexAttribute::Normal,
-            VertexAttribute::TexCoord,
-        ];
+        let attributes = Vertex::ATTRIBUTES.to_vec();
         let vertex_count = vertex_data.len() as i32;
         let index_count = index_data.len() as i32;
-        MeshData::new(
+        let mut mesh_data = MeshData::new(
             vertex_count,
             vertex_data.len() * size_of::<Vertex>(),
             vertex_data.as_ptr().cast(),
@@ -86,65 +356,34 @@ impl MeshData {
             index_count,
             index_data.as_ptr().cast(),
             usage,
-        )
+        );
+        mesh_data.skinned = vertex_data
+            .iter()
+            .any(|vertex| vertex.bone_weights.iter().any(|weight| *weight > 0.0));
+        mesh_data.bounds = Bounds::from_vertices(vertex_data);
+        mesh_data
     }
 
+    // Attributes are interleaved in the given order, all of them floats
     fn configure_vertex_attributes(attributes: Vec<VertexAttribute>) {
-        let mut stride = 0;
-
-        let position_ptr = 0;
-        let mut normal_ptr = 0;
-        let mut tex_coord_ptr = 0;
+        let stride = attributes
+            .iter()
+            .map(|attribute| attribute.components())
+            .sum::<usize>()
+            * size_of::<f32>();
 
-        if attributes.contains(&VertexAttribute::Position) {
-            stride += 3;
-            normal_ptr += 3;
-            tex_coord_ptr += 3;
-        }
-        if attributes.contains(&VertexAttribute::Normal) {
-            stride += 3;
-            tex_coord_ptr += 3;
-        }
-        if attributes.contains(&VertexAttribute::TexCoord) {
-            stride += 2;
-        }
-
-        stride *= size_of::<f32>();
-        normal_ptr *= size_of::<f32>();
-        tex_coord_ptr *= size_of::<f32>();
-
-        if attributes.contains(&VertexAttribute::Position) {
-            gl_wrappers::configure_attribute(
-                VertexAttribute::Position as u32,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                stride,
-                position_ptr as *const _,
-            );
-            gl_wrappers::enable_attribute(VertexAttribute::Position as u32);
-        }
-        if attributes.contains(&VertexAttribute::Normal) {
-            gl_wrappers::configure_attribute(
-                VertexAttribute::Normal as u32,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                stride,
-                normal_ptr as *const _,
-            );
-            gl_wrappers::enable_attribute(VertexAttribute::Normal as u32);
-        }
-        if attributes.contains(&VertexAttribute::TexCoord) {
+        let mut offset = 0;
+        for attribute in attributes {
             gl_wrappers::configure_attribute(
-                VertexAttribute::TexCoord as u32,
-                2,
+                attribute as u32,
+                attribute.components() as i32,
                 gl::FLOAT,
                 gl::FALSE,
                 stride,
-                tex_coord_ptr as *const _,
+                offset as *const _,
             );
-            gl_wrappers::enable_attribute(VertexAttribute::TexCoord as u32);
+            gl_wrappers::enable_attribute(attribute as u32);
+            offset += attribute.components() * size_of::<f32>();
         }
     }
 
@@ -160,4 +399,30 @@ impl MeshData {
 pub struct Mesh {
     pub mesh_index: RangeIndex,
     pub material_index: RangeIndex,
+    // Blend shapes of the submeshes by name, submeshes that share a name share the weight
+    pub morph_weights: Vec<(String, f32)>,
+}
+
+impl Mesh {
+    pub fn morph_weight(&self, name: &str) -> Option<f32> {
+        self.morph_weights
+            .iter()
+            .find(|(target, _)| target == name)
+            .map(|(_, weight)| *weight)
+    }
+
+    // False when no submesh has the target
+    pub fn set_morph_weight(&mut self, name: &str, weight: f32) -> bool {
+        let target = self
+            .morph_weights
+            .iter_mut()
+            .find(|(target, _)| target == name);
+        match target {
+            Some((_, value)) => {
+                *value = weight;
+                true
+            }
+            None => false,
+        }
+    }
 }
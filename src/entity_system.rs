@@ -1,16 +1,19 @@
 use crate::{
+    animation::{AnimationPlayer, PlayMode, Skeleton},
+    asset_path::AssetPath,
     camera::Camera,
-    data3d::Mesh,
+    data3d::{Mesh, Vertex},
     lighting::LightSource,
-    linear::Transform,
-    resources::ResourceManager,
+    linear::{self, Transform},
+    resources::{LoadedMesh, ModelNode, ResourceManager},
     runtime::FramebufferSizeCallback,
     scripting::{ScriptObject, Scripting},
     serializable,
     utils::{self, Reallocated, TypelessVec},
 };
 use fxhash::FxHashMap;
-use std::{collections::VecDeque, ops::Mul};
+use glm::Mat4;
+use std::{collections::VecDeque, mem, ops::Mul};
 use strum::EnumCount;
 
 #[derive(Default, Debug)]
@@ -19,6 +22,17 @@ pub struct SceneManager {
     components: [TypelessVec; ComponentDataType::COUNT],
     available_ids: VecDeque<usize>,
     id_counter: usize,
+    generation_counter: usize, // tells apart entities that had the same id
+    // For cameras spawned after the scene is loaded
+    framebuffer_size: Option<(i32, i32)>,
+    // Meshes scripts asked for, (owner, model, material asset), handed to the resource
+    // manager on the next loading iteration
+    mesh_requests: Vec<(EntityRef, AssetPath, Option<String>)>,
+    // Geometry scripts wrote this frame, (owner id, vertices, indices)
+    geometry_updates: Vec<(usize, Vec<Vertex>, Vec<u32>)>,
+    // Players whose clips come from models that are still loading, (owner, clip sources,
+    // mode, speed, autoplay)
+    pending_players: Vec<(EntityRef, Vec<String>, PlayMode, f32, Option<String>)>,
     // mutated_transforms: Vec<usize>,
     // loaded_scenes: HashMap<SceneId, Vec<InstanceId>>
 }
@@ -30,9 +44,16 @@ impl SceneManager {
         resource_manager: &mut ResourceManager,
         scripting: &Scripting,
     ) {
+        // Frees whatever only the previous scene used
+        self.unload_all(resource_manager, scripting);
         let scene = resource_manager.scenes().get(index).unwrap();
-        let entities = scene.read_entities();
+        let entities = scene.read_entities(resource_manager.vfs());
         self.load_entities(None, entities, resource_manager, scripting);
+        self.attach_animation_players(resource_manager);
+        // Scenes loaded after the window was made
+        if let Some(size) = self.framebuffer_size {
+            self.framebuffer_size(size);
+        }
     }
 
     fn load_entities(
@@ -56,14 +77,9 @@ impl SceneManager {
                 id,
                 utils::convert_vec::<_, LightSource>(entity.light_sources),
             );
-            self.attach_components(
-                id,
-                entity
-                    .meshes
-                    .iter()
-                    .map(|item| resource_manager.mesh_manager_mut().get_mesh_lazily(&item))
-                    .collect::<Vec<Mesh>>(),
-            );
+            for item in &entity.meshes {
+                resource_manager.request_mesh(self.entity_ref(id), item);
+            }
             self.attach_components(
                 id,
                 entity
@@ -73,12 +89,267 @@ impl SceneManager {
                     .collect::<Vec<ScriptObject>>(),
             );
 
+            for item in entity.animation_players {
+                let sources = resource_manager.clip_sources(&item.clips, &entity.meshes);
+                let owner = self.entity_ref(id);
+                self.pending_players
+                    .push((owner, sources, item.mode, item.speed, item.autoplay));
+            }
+
             self.set_parent(id, parent_id);
 
             self.load_entities(Some(id), entity.children, resource_manager, scripting);
         }
     }
 
+    // Meshes finished by the background loader. Entities deleted in the meantime are
+    // skipped, also when their id has been given to another entity
+    pub fn attach_loaded_meshes(
+        &mut self,
+        meshes: Vec<(EntityRef, LoadedMesh)>,
+        scripting: &Scripting,
+    ) {
+        for (owner, loaded) in meshes {
+            if !self.is_alive(owner) {
+                continue;
+            }
+            let owner_id = owner.id;
+            match loaded {
+                LoadedMesh::Mesh(mesh) => self.attach_component(owner_id, mesh),
+                LoadedMesh::Nodes(nodes) => self.spawn_nodes(owner_id, nodes, scripting),
+                LoadedMesh::Skeleton(skeleton) => self.attach_component(owner_id, skeleton),
+            }
+        }
+    }
+
+    // Players are attached once the models their clips come from have loaded
+    pub fn attach_animation_players(&mut self, resource_manager: &mut ResourceManager) {
+        for (owner, sources, mode, speed, autoplay) in mem::take(&mut self.pending_players) {
+            if !self.is_alive(owner) {
+                continue;
+            }
+            let Some(clips) = resource_manager.get_animation_clips(&sources) else {
+                self.pending_players
+                    .push((owner, sources, mode, speed, autoplay));
+                continue;
+            };
+            let mut player = AnimationPlayer::new(clips, mode, speed);
+            if let Some(name) = &autoplay {
+                if !player.play(name) {
+                    let entity = &self.entities[&owner.id].name;
+                    println!("Unknown animation clip {name} of entity {entity}");
+                }
+            }
+            self.attach_component(owner.id, player);
+        }
+    }
+
+    // Entities follow only the position of their parent, so node transforms, which are
+    // relative to the whole parent transform, are turned into world orientation and scale
+    // (under the owner's ones) and an offset from the parent
+    fn spawn_nodes(
+        &mut self,
+        owner_id: usize,
+        nodes: Vec<(ModelNode, Vec<Mesh>)>,
+        scripting: &Scripting,
+    ) {
+        let owner = *self.get_transform(owner_id);
+        let base = glm::quat_to_mat4(&owner.orientation) * glm::scaling(&owner.scale);
+
+        let mut spawned = Vec::<(usize, Mat4)>::with_capacity(nodes.len());
+        for (node, meshes) in nodes {
+            let (parent_id, parent_matrix) = match node.parent {
+                Some(parent) => spawned[parent],
+                None => (owner_id, base),
+            };
+            let matrix = parent_matrix * glm::make_mat4(&node.transform);
+            let (position, orientation, scale) = linear::decompose(&matrix);
+            let (parent_position, _, _) = linear::decompose(&parent_matrix);
+
+            let id = self.create_entity(scripting);
+            self.entities.get_mut(&id).unwrap().name = node.name;
+            let transform = self.get_transform_mut(id);
+            transform.position = position - parent_position;
+            transform.orientation = orientation;
+            transform.scale = scale;
+            self.attach_components(id, meshes);
+            let mut cameras = utils::convert_vec::<_, Camera>(node.cameras);
+            if let Some(size) = self.framebuffer_size {
+                for camera in &mut cameras {
+                    camera.update_aspect(size);
+                }
+            }
+            self.attach_components(id, cameras);
+            self.attach_components(id, utils::convert_vec::<_, LightSource>(node.light_sources));
+            self.set_parent(id, Some(parent_id));
+
+            spawned.push((id, matrix));
+        }
+    }
+
+    // Entity with the skeleton the meshes of `id` are skinned to, itself or an ancestor
+    pub fn skeleton_owner(&self, id: usize) -> Option<usize> {
+        let mut current = Some(id);
+        while let Some(id) = current {
+            if self.get_component::<Skeleton>(id).is_some() {
+                return Some(id);
+            }
+            current = self.entities[&id].parent;
+        }
+        None
+    }
+
+    pub fn get_skeleton(&self, owner_id: usize) -> Option<&Skeleton> {
+        let record = self.get_component::<Skeleton>(owner_id)?;
+        Some(&self.component_slice::<Skeleton>()[record.array_index].data)
+    }
+
+    pub fn get_skeleton_mut(&mut self, owner_id: usize) -> Option<&mut Skeleton> {
+        let index = self.get_component::<Skeleton>(owner_id)?.array_index;
+        Some(&mut self.component_slice_mut::<Skeleton>()[index].data)
+    }
+
+    // Advances the clips of every skeleton, run once per frame before rendering
+    pub fn update_skeletons(&mut self, delta: f32) {
+        for skeleton in self.component_slice_mut::<Skeleton>() {
+            skeleton.data.update(delta);
+        }
+    }
+
+    // The `Mesh` is attached once the model has loaded, next to the meshes the entity has
+    pub fn request_mesh(&mut self, owner_id: usize, path: AssetPath, material: Option<String>) {
+        let owner = self.entity_ref(owner_id);
+        self.mesh_requests.push((owner, path, material));
+    }
+
+    pub fn take_mesh_requests(&mut self) -> Vec<(EntityRef, serializable::Mesh)> {
+        self.mesh_requests
+            .drain(..)
+            .map(|(owner, path, material)| {
+                let mesh = serializable::Mesh {
+                    path,
+                    material_info: Default::default(),
+                    material,
+                    import: Default::default(),
+                    hierarchy: false,
+                    cameras_and_lights: false,
+                    retain_geometry: false,
+                };
+                (owner, mesh)
+            })
+            .collect()
+    }
+
+    // Only the last geometry written in a frame is uploaded
+    pub fn update_geometry(&mut self, owner_id: usize, vertices: Vec<Vertex>, indices: Vec<u32>) {
+        self.geometry_updates.retain(|(id, _, _)| *id != owner_id);
+        self.geometry_updates.push((owner_id, vertices, indices));
+    }
+
+    // Entities without a mesh are given a dynamic one, otherwise their first mesh is
+    // rewritten, which fails for meshes imported from files
+    pub fn apply_geometry_updates(&mut self, resource_manager: &mut ResourceManager) {
+        let mesh_manager = resource_manager.mesh_manager_mut();
+        for (owner_id, vertices, indices) in mem::take(&mut self.geometry_updates) {
+            if !self.entities.contains_key(&owner_id) {
+                continue;
+            }
+            if self.get_component::<Mesh>(owner_id).is_none() {
+                let mesh = mesh_manager.add_dynamic_mesh(&Default::default());
+                self.attach_component(owner_id, mesh);
+            }
+            let mesh_index = &self.get_mesh_mut(owner_id).unwrap().mesh_index;
+            if !mesh_manager.update_dynamic_mesh(mesh_index, &vertices, &indices) {
+                println!("Mesh of entity {owner_id} is static, its geometry can't be changed");
+            }
+        }
+    }
+
+    // The first of the entity's meshes
+    pub fn get_mesh_mut(&mut self, owner_id: usize) -> Option<&mut Mesh> {
+        let index = self.get_component::<Mesh>(owner_id)?.array_index;
+        Some(&mut self.component_slice_mut::<Mesh>()[index].data)
+    }
+
+    pub fn get_animation_player(&self, owner_id: usize) -> Option<&AnimationPlayer> {
+        let record = self.get_component::<AnimationPlayer>(owner_id)?;
+        Some(&self.component_slice::<AnimationPlayer>()[record.array_index].data)
+    }
+
+    pub fn get_animation_player_mut(&mut self, owner_id: usize) -> Option<&mut AnimationPlayer> {
+        let index = self.get_component::<AnimationPlayer>(owner_id)?.array_index;
+        Some(&mut self.component_slice_mut::<AnimationPlayer>()[index].data)
+    }
+
+    // Advances every player and writes the sampled keys to the transforms of its entity
+    // and the descendants named by the tracks. Returns (owner id, clip, event) for each
+    // event passed this frame
+    pub fn update_animation_players(&mut self, delta: f32) -> Vec<(usize, String, String)> {
+        let mut events = Vec::new();
+        let mut samples = Vec::new();
+        for player in self.component_slice_mut::<AnimationPlayer>() {
+            let owner_id = player.owner_id();
+            let passed = player.data.advance(delta);
+            if let Some((clip, time)) = player.data.sample() {
+                for event in passed {
+                    events.push((owner_id, clip.name.clone(), event));
+                }
+                samples.push((owner_id, clip, time));
+            }
+        }
+
+        // Targets are looked up every frame as model nodes are spawned once loaded
+        let mut poses = Vec::new();
+        for (owner_id, clip, time) in &samples {
+            for track in &clip.tracks {
+                let target = match track.target.as_str() {
+                    "" => Some(*owner_id),
+                    name => self.find_descendant(*owner_id, name),
+                };
+                if let Some(target) = target {
+                    poses.push((self.depth(target), target, track, *time));
+                }
+            }
+        }
+        // Node keys are relative to the parent, which is posed first
+        poses.sort_by_key(|(depth, ..)| *depth);
+        for (_, target, track, time) in poses {
+            let parent = match self.entities[&target].parent {
+                Some(parent) => *self.get_transform(parent),
+                None => Transform::new(),
+            };
+            track.apply(time, self.get_transform_mut(target), &parent);
+            if let Some(mesh) = self.get_mesh_mut(target) {
+                track.apply_weights(time, mesh);
+            }
+        }
+        events
+    }
+
+    // Number of ancestors
+    fn depth(&self, id: usize) -> usize {
+        let mut depth = 0;
+        let mut current = self.entities[&id].parent;
+        while let Some(parent) = current {
+            depth += 1;
+            current = self.entities[&parent].parent;
+        }
+        depth
+    }
+
+    // Closest entity with the name in the subtree under `id`
+    pub fn find_descendant(&self, id: usize, name: &str) -> Option<usize> {
+        let mut queue = self.entities[&id].children.iter().copied().collect::<VecDeque<_>>();
+        while let Some(child) = queue.pop_front() {
+            let entity = &self.entities[&child];
+            if entity.name == name {
+                return Some(child);
+            }
+            queue.extend(entity.children.iter().copied());
+        }
+        None
+    }
+
     pub fn create_entity(&mut self, scripting: &Scripting) -> usize {
         let mut rewrite = true;
         let instance_id = self.available_ids.pop_front().unwrap_or_else(|| {
@@ -88,7 +359,8 @@ impl SceneManager {
             id
         });
 
-        let entity = Entity::new(instance_id);
+        let entity = Entity::new(instance_id, self.generation_counter);
+        self.generation_counter += 1;
 
         let transform = Transform::new();
         if rewrite {
@@ -106,6 +378,19 @@ impl SceneManager {
         instance_id
     }
 
+    pub fn entity_ref(&self, id: usize) -> EntityRef {
+        EntityRef {
+            id,
+            generation: self.entities[&id].generation,
+        }
+    }
+
+    pub fn is_alive(&self, entity: EntityRef) -> bool {
+        self.entities
+            .get(&entity.id)
+            .is_some_and(|item| item.generation == entity.generation)
+    }
+
     pub fn set_parent(&mut self, child_id: usize, parent_id: Option<usize>) {
         match parent_id {
             Some(parent_id) => {
@@ -251,7 +536,28 @@ impl SceneManager {
         &mut self.components[ComponentDataType::Transform.usize()]
     }
 
+    // Deletes every entity and frees resources that no remaining `Mesh` component uses
+    pub fn unload_all(&mut self, resource_manager: &mut ResourceManager, scripting: &Scripting) {
+        let roots = self
+            .entities
+            .values()
+            .filter(|entity| entity.parent.is_none())
+            .map(|entity| entity.instance_id)
+            .collect::<Vec<usize>>();
+        for id in roots {
+            self.delete_entity(id, scripting);
+        }
+        self.mesh_requests.clear();
+        self.geometry_updates.clear();
+        self.pending_players.clear();
+
+        resource_manager.cancel_pending_meshes();
+        resource_manager.collect_garbage(self.component_slice::<Mesh>());
+    }
+
     pub fn delete_entity(&mut self, target_id: usize, scripting: &Scripting) {
+        self.set_parent(target_id, None);
+
         let children = self.entities[&target_id].children.clone();
         children
             .iter()
@@ -272,9 +578,15 @@ impl SceneManager {
                     _ = self.delete_component::<LightSource>(target_id, record);
                 }
                 ComponentDataType::Mesh => _ = self.delete_component::<Mesh>(target_id, record),
+                ComponentDataType::Skeleton => {
+                    _ = self.delete_component::<Skeleton>(target_id, record);
+                }
+                ComponentDataType::AnimationPlayer => {
+                    _ = self.delete_component::<AnimationPlayer>(target_id, record);
+                }
                 ComponentDataType::ScriptObject => {
                     let data = self.delete_component::<ScriptObject>(target_id, record);
-                    Self::delete_managed_stuff(data);
+                    Self::delete_managed_stuff(data, scripting);
                 }
                 ComponentDataType::Transform => unreachable!(),
             }
@@ -311,15 +623,15 @@ impl SceneManager {
         let opt = self.find_by_index::<T>(target_id, index);
         if let Some(record) = opt {
             let data = self.delete_component::<T>(target_id, record.copy());
-            Self::delete_managed_stuff(data);
+            Self::delete_managed_stuff(data, scripting);
         }
     }
 
-    fn delete_managed_stuff<T>(data: T)
+    fn delete_managed_stuff<T>(data: T, scripting: &Scripting)
     where
         T: Managed,
     {
-        todo!()
+        data.delete(scripting);
     }
 
     fn find_by_index<T>(&self, target_id: usize, index: usize) -> Option<&ComponentRecord>
@@ -347,15 +659,20 @@ impl SceneManager {
             .unwrap();
         owner.components.remove(index);
 
+        // The last component takes the place of the deleted one, unless it is the deleted one
         let index_of_last = self.components::<T>().len::<Component<T>>() - 1;
-        let owner_id_of_last = self.component_slice::<T>().last().unwrap().owner_id;
-        let owner_of_last = self.entities.get_mut(&owner_id_of_last).unwrap();
-        let index = owner_of_last
-            .components
-            .iter()
-            .position(|item| item.data_type == T::data_type() && item.array_index == index_of_last)
-            .unwrap();
-        owner_of_last.components[index].array_index = index_of_deleting;
+        if index_of_last != index_of_deleting {
+            let owner_id_of_last = self.component_slice::<T>().last().unwrap().owner_id;
+            let owner_of_last = self.entities.get_mut(&owner_id_of_last).unwrap();
+            let index = owner_of_last
+                .components
+                .iter()
+                .position(|item| {
+                    item.data_type == T::data_type() && item.array_index == index_of_last
+                })
+                .unwrap();
+            owner_of_last.components[index].array_index = index_of_deleting;
+        }
 
         self.components_mut::<T>()
             .swap_take::<Component<T>>(index_of_deleting)
@@ -365,15 +682,24 @@ impl SceneManager {
 
 impl FramebufferSizeCallback for SceneManager {
     fn framebuffer_size(&mut self, size: (i32, i32)) {
+        self.framebuffer_size = Some(size);
         for camera in self.component_slice_mut::<Camera>() {
             camera.data.update_aspect(size);
         }
     }
 }
 
+// An entity as it was when something was requested for it, ids are reused after deletion
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EntityRef {
+    pub id: usize,
+    generation: usize,
+}
+
 #[derive(Debug)]
 pub struct Entity {
     instance_id: usize,
+    generation: usize,
     pub name: String,
     pub components: Vec<ComponentRecord>,
     children: Vec<usize>,
@@ -383,9 +709,10 @@ pub struct Entity {
 }
 
 impl Entity {
-    fn new(instance_id: usize) -> Self {
+    fn new(instance_id: usize, generation: usize) -> Self {
         Self {
             instance_id,
+            generation,
             name: "".to_string(),
             components: vec![],
             children: vec![],
@@ -432,6 +759,8 @@ pub enum ComponentDataType {
     LightSource,
     Mesh,
     ScriptObject,
+    Skeleton,
+    AnimationPlayer,
 }
 
 impl ComponentDataType {
@@ -469,15 +798,35 @@ impl ComponentData for ScriptObject {
     }
 }
 
+impl ComponentData for Skeleton {
+    fn data_type() -> ComponentDataType {
+        ComponentDataType::Skeleton
+    }
+}
+
+impl ComponentData for AnimationPlayer {
+    fn data_type() -> ComponentDataType {
+        ComponentDataType::AnimationPlayer
+    }
+}
+
 trait Unmanaged: ComponentData {}
 
 impl Unmanaged for Mesh {}
 impl Unmanaged for Camera {}
 impl Unmanaged for LightSource {}
+impl Unmanaged for Skeleton {}
+impl Unmanaged for AnimationPlayer {}
 
-trait Managed: ComponentData {}
+trait Managed: ComponentData {
+    fn delete(self, scripting: &Scripting);
+}
 
-impl Managed for ScriptObject {}
+impl Managed for ScriptObject {
+    fn delete(self, scripting: &Scripting) {
+        scripting.delete_script_object(self);
+    }
+}
 
 #[allow(private_bounds)]
 pub struct Component<T: ComponentData> {
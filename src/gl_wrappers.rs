@@ -31,6 +31,21 @@ pub fn clear(mask: GLbitfield) {
     }
 }
 
+pub fn pixel_store(pname: GLenum, param: GLint) {
+    unsafe {
+        gl::PixelStorei(pname, param);
+    }
+}
+
+// `default` is returned as is when the driver doesn't know `pname`
+pub fn get_float(pname: GLenum, default: f32) -> f32 {
+    let mut value = default;
+    unsafe {
+        gl::GetFloatv(pname, &mut value);
+    }
+    value
+}
+
 pub struct Gl;
 
 impl Gl {
@@ -349,14 +364,76 @@ impl Texture {
         type_: GLenum,
         format: GLenum,
         internal_format: GLenum,
+    ) {
+        self.texture_level_data(0, size, data, type_, format, internal_format);
+    }
+
+    pub fn texture_level_data(
+        &self,
+        level: GLint,
+        size: (i32, i32),
+        data: *const c_void,
+        type_: GLenum,
+        format: GLenum,
+        internal_format: GLenum,
     ) {
         unsafe {
             gl::TexImage2D(
+                self.target,
+                level,
+                internal_format as i32,
+                size.0,
+                size.1,
+                0,
+                format,
+                type_,
+                data,
+            );
+        }
+    }
+
+    // Faces are numbered in the order of their targets, from TEXTURE_CUBE_MAP_POSITIVE_X
+    pub fn cube_face_data(
+        &self,
+        face: u32,
+        size: (i32, i32),
+        data: *const c_void,
+        type_: GLenum,
+        format: GLenum,
+        internal_format: GLenum,
+    ) {
+        unsafe {
+            gl::TexImage2D(
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                0,
+                internal_format as i32,
+                size.0,
+                size.1,
+                0,
+                format,
+                type_,
+                data,
+            );
+        }
+    }
+
+    pub fn texture_array_data(
+        &self,
+        size: (i32, i32),
+        layers: i32,
+        data: *const c_void,
+        type_: GLenum,
+        format: GLenum,
+        internal_format: GLenum,
+    ) {
+        unsafe {
+            gl::TexImage3D(
                 self.target,
                 0,
                 internal_format as i32,
                 size.0,
                 size.1,
+                layers,
                 0,
                 format,
                 type_,
@@ -365,6 +442,27 @@ impl Texture {
         }
     }
 
+    pub fn compressed_texture_level_data(
+        &self,
+        level: GLint,
+        size: (i32, i32),
+        data: &[u8],
+        internal_format: GLenum,
+    ) {
+        unsafe {
+            gl::CompressedTexImage2D(
+                self.target,
+                level,
+                internal_format,
+                size.0,
+                size.1,
+                0,
+                data.len() as GLsizei,
+                data.as_ptr().cast(),
+            );
+        }
+    }
+
     pub fn generate_mipmaps(&self) {
         unsafe {
             gl::GenerateMipmap(self.target);
@@ -397,6 +495,52 @@ impl Texture {
         }
     }
 
+    pub fn parameter_f(&self, pname: GLenum, param: f32) {
+        unsafe {
+            gl::TexParameterf(self.target, pname, param);
+        }
+    }
+
+    pub fn level_parameter(&self, level: GLint, pname: GLenum) -> GLint {
+        let mut value = 0;
+        unsafe {
+            gl::GetTexLevelParameteriv(self.target, level, pname, &mut value);
+        }
+        value
+    }
+
+    // Sums up every mip level, uncompressed sizes are estimated from the component sizes
+    pub fn memory_size(&self) -> usize {
+        self.bind();
+        let mut size = 0;
+        let mut level = 0;
+        loop {
+            let width = self.level_parameter(level, gl::TEXTURE_WIDTH) as usize;
+            let height = self.level_parameter(level, gl::TEXTURE_HEIGHT) as usize;
+            if width == 0 || height == 0 {
+                break;
+            }
+            if self.level_parameter(level, gl::TEXTURE_COMPRESSED) == gl::TRUE as GLint {
+                size += self.level_parameter(level, gl::TEXTURE_COMPRESSED_IMAGE_SIZE) as usize;
+            } else {
+                let bits = [
+                    gl::TEXTURE_RED_SIZE,
+                    gl::TEXTURE_GREEN_SIZE,
+                    gl::TEXTURE_BLUE_SIZE,
+                    gl::TEXTURE_ALPHA_SIZE,
+                    gl::TEXTURE_DEPTH_SIZE,
+                    gl::TEXTURE_STENCIL_SIZE,
+                ]
+                .iter()
+                .map(|pname| self.level_parameter(level, *pname) as usize)
+                .sum::<usize>();
+                size += width * height * bits / 8;
+            }
+            level += 1;
+        }
+        size
+    }
+
     // pub fn from_file(path: &str) -> Option<Self> {
     //     let image = image::open(path).unwrap();
     //     let bytes = image.as_bytes();
@@ -444,6 +588,24 @@ impl Framebuffer {
         }
     }
 
+    pub fn attach_cube_face(&self, texture: &Texture, attachment: GLenum, face: u32) {
+        unsafe {
+            gl::FramebufferTexture2D(
+                self.target,
+                attachment,
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                texture.id,
+                0,
+            );
+        }
+    }
+
+    pub fn attach_texture_layer(&self, texture: &Texture, attachment: GLenum, layer: i32) {
+        unsafe {
+            gl::FramebufferTextureLayer(self.target, attachment, texture.id, 0, layer);
+        }
+    }
+
     pub fn attach_renderbuffer(&self, renderbuffer: &Renderbuffer, attachment: GLenum) {
         unsafe {
             gl::FramebufferRenderbuffer(
@@ -0,0 +1,83 @@
+use fxhash::FxHashMap;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+// Polls modification times instead of relying on OS notifications, asset folders are
+// small enough for a full walk a couple of times per second
+pub struct AssetWatcher {
+    root: PathBuf,
+    interval: Duration,
+    last_poll: Instant,
+    modified: FxHashMap<PathBuf, SystemTime>,
+}
+
+impl AssetWatcher {
+    pub fn new(root: &str, interval: Duration) -> Self {
+        let root = PathBuf::from(root);
+        let mut modified = FxHashMap::default();
+        Self::scan(&root, &mut modified);
+
+        Self {
+            root,
+            interval,
+            last_poll: Instant::now(),
+            modified,
+        }
+    }
+
+    // Returns files that were created or modified since the previous poll
+    pub fn poll(&mut self) -> Vec<String> {
+        if self.last_poll.elapsed() < self.interval {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+
+        let mut current = FxHashMap::default();
+        Self::scan(&self.root, &mut current);
+
+        let mut changed = current
+            .iter()
+            .filter(|(path, time)| self.modified.get(*path) != Some(time))
+            .map(|(path, _)| path.to_str().unwrap().to_string())
+            .collect::<Vec<String>>();
+        changed.sort();
+
+        self.modified = current;
+        changed
+    }
+
+    fn scan(dir: &Path, out: &mut FxHashMap<PathBuf, SystemTime>) {
+        // Files can disappear mid-walk while an editor is saving, those are picked up next time
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if metadata.is_dir() {
+                Self::scan(&path, out);
+            } else if let Ok(time) = metadata.modified() {
+                _ = out.insert(path, time);
+            }
+        }
+    }
+}
+
+// Scene files, model files and the watcher spell the same path differently
+// ("assets\\meshes/../textures/a.png" and "assets/textures/a.png")
+pub fn same_file(a: &str, b: &str) -> bool {
+    if Path::new(a) == Path::new(b) {
+        return true;
+    }
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
@@ -1,9 +1,14 @@
 use crate::{
+    camera::Camera,
     linear::{self, Projection, Transform},
-    utils::ArrayVec,
+    serializable::ShadowSettings,
 };
 use nalgebra_glm::{vec3_to_vec4, Mat4, Vec3, Vec4};
 use serde::{Deserialize, Serialize};
+use std::f32::consts::FRAC_PI_2;
+
+// Shadow map layers of a directional light
+pub const MAX_CASCADES: usize = 4;
 
 #[derive(Serialize, Deserialize, Clone, Copy, Default)]
 #[repr(u32)]
@@ -24,6 +29,15 @@ pub struct LightData {
     cos_inner: f32,
     direction: Vec3,
     cos_outer: f32,
+    light_space: [Mat4; MAX_CASCADES], // spot lights have a single layer
+    cascade_splits: Vec4,              // view depth each cascade reaches
+    shadow_map: i32,                   // -1 without shadows, cube maps for point lights
+    shadow_bias: f32,
+    normal_bias: f32,
+    shadow_far: f32, // what the distances in cube maps are divided by
+    filter_radius: f32,
+    cascade_count: u32,
+    cascade_blend: f32,
 }
 
 impl LightData {
@@ -49,55 +63,185 @@ impl LightData {
         source.cos_outer = outer.to_radians().cos();
         source
     }
+
+    pub fn set_shadow(
+        &mut self,
+        map: usize,
+        cascades: &[Cascade],
+        settings: &ShadowSettings,
+        far: f32,
+    ) {
+        for (i, cascade) in cascades.iter().enumerate() {
+            self.light_space[i] = cascade.light_space;
+            self.cascade_splits[i] = cascade.far;
+        }
+        self.cascade_count = cascades.len() as u32;
+        self.cascade_blend = settings.cascade_blend;
+        self.shadow_map = map as i32;
+        self.shadow_bias = settings.bias;
+        self.normal_bias = settings.normal_bias;
+        self.shadow_far = far;
+        self.filter_radius = settings.filter_radius as f32;
+    }
+}
+
+// A layer of a shadow map, drawn for the part of the view up to `far`
+pub struct Cascade {
+    pub light_space: Mat4,
+    pub far: f32,
 }
 
 pub struct LightSource {
     light_data: LightData,
-    pub shadow_distance: f32,
-    pub projections: ArrayVec<Mat4, 4>,
+    pub shadow_distance: f32, // how far from the light shadows are cast
+    pub shadows: ShadowSettings,
 }
 
 impl LightSource {
-    const DEFAULT_SHADOW_DISTANCE: f32 = 100.0;
+    pub const DEFAULT_SHADOW_DISTANCE: f32 = 100.0;
+    pub const SHADOW_NEAR: f32 = 0.05;
+
+    // Directions and up vectors of the cube map faces, in the order of their targets
+    const CUBE_FACES: [([f32; 3], [f32; 3]); 6] = [
+        ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+        ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+        ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+    ];
 
-    pub fn new(light_data: LightData, shadow_distance: f32) -> Self {
+    pub fn new(light_data: LightData, shadow_distance: f32, shadows: ShadowSettings) -> Self {
         Self {
-            projections: ArrayVec::new(),
             light_data,
             shadow_distance,
+            shadows,
         }
     }
 
+    pub fn type_(&self) -> LightType {
+        self.light_data.type_
+    }
+
     pub fn get_data(&self, transform: &Transform) -> LightData {
         let mut data = self.light_data;
         data.position = transform.global_position();
         data.direction = glm::quat_rotate_vec3(&transform.orientation, &Vec3::z_axis());
+        data.shadow_map = -1;
         data
     }
-}
 
-// pub fn foo(camera: &Camera, light_obj: &LightSource) {
-//     let corners = linear::frustum_corners_worldspace(&camera.projection_view());
-//     let center = linear::frustum_center(&corners);
-//     let mut tr = Transform::new();
-//     tr.position = center;
-//     tr.orientation = unsafe { (*light_obj.transform).orientation };
-//     let light_view = linear::view_matrix(&tr); // might cause a bug
-//                                                //or not
-//     let mut minx = f32::MAX;
-//     let mut maxx = f32::MIN;
-//     let mut miny = f32::MAX;
-//     let mut maxy = f32::MIN;
-//     let mut minz = f32::MAX;
-//     let mut maxz = f32::MIN;
-
-//     for corner in &corners {
-//         let corner = light_view * corner;
-//         minx = minx.min(corner.x);
-//         maxx = maxx.max(corner.x);
-//         miny = miny.min(corner.y);
-//         maxy = maxy.max(corner.y);
-//         minz = minz.min(corner.z);
-//         maxz = maxz.max(corner.z);
-//     }
-// }
+    // Layers of the shadow map. Directional lights split the camera's view up to
+    // `shadow_distance` into cascades, spot lights cover their cone. Point lights use
+    // `cube_light_space` instead
+    pub fn cascades(
+        &self,
+        transform: &Transform,
+        camera: &Camera,
+        camera_transform: &Transform,
+    ) -> Vec<Cascade> {
+        let position = transform.global_position();
+        let direction = glm::quat_rotate_vec3(&transform.orientation, &Vec3::z_axis());
+        match self.light_data.type_ {
+            LightType::Directional => {
+                self.directional_cascades(&direction, camera, camera_transform)
+            }
+            LightType::Spot => {
+                // The cone is kept narrower than a half-space for the projection to work
+                let fov = (2.0 * self.light_data.cos_outer.acos()).min(170f32.to_radians());
+                let view = glm::look_at(&position, &(position + direction), &Self::up(&direction));
+                let projection =
+                    glm::perspective(1.0, fov, Self::SHADOW_NEAR, self.shadow_distance);
+                vec![Cascade {
+                    light_space: projection * view,
+                    far: self.shadow_distance,
+                }]
+            }
+            LightType::Point => Vec::new(),
+        }
+    }
+
+    // Each cascade is a box around a bounding sphere of its slice of the view, so its size
+    // doesn't change as the camera turns, and it moves in whole texels of the map. Together
+    // they keep the edges of shadows from shimmering
+    fn directional_cascades(
+        &self,
+        direction: &Vec3,
+        camera: &Camera,
+        camera_transform: &Transform,
+    ) -> Vec<Cascade> {
+        let (near, far) = camera.projection().range();
+        let near = near.max(Self::SHADOW_NEAR);
+        let far = far.min(self.shadow_distance).max(near);
+        let count = (self.shadows.cascades as usize).clamp(1, MAX_CASCADES);
+        let view = linear::view_matrix(camera_transform);
+        let up = Self::up(direction);
+        let rotation = glm::look_at(&Vec3::zeros(), direction, &up);
+        let texels = self.shadows.resolution.max(1) as f32;
+
+        let mut cascades = Vec::with_capacity(count);
+        let mut cascade_near = near;
+        for i in 1..=count {
+            let fraction = i as f32 / count as f32;
+            let cascade_far = Self::split(near, far, fraction, self.shadows.cascade_split);
+            let projection = camera.projection().with_range(cascade_near, cascade_far);
+            let corners = linear::frustum_corners_worldspace(&(projection.matrix() * view));
+            let center = linear::frustum_center(&corners);
+            let radius = corners
+                .iter()
+                .map(|corner| glm::distance(&corner.xyz(), &center))
+                .fold(0.0, f32::max);
+            // Rounded so float noise doesn't resize the box from frame to frame
+            let radius = (radius * 16.0).ceil() / 16.0;
+
+            let texel = 2.0 * radius / texels;
+            let mut snapped = (rotation * center.push(1.0)).xyz();
+            snapped.x = (snapped.x / texel).floor() * texel;
+            snapped.y = (snapped.y / texel).floor() * texel;
+            let center = (glm::inverse(&rotation) * snapped.push(1.0)).xyz();
+
+            // Casters up to `shadow_distance` in front of the slice are kept
+            let eye = center - direction * self.shadow_distance;
+            let light_view = glm::look_at(&eye, &center, &up);
+            let light_projection = glm::ortho(
+                -radius,
+                radius,
+                -radius,
+                radius,
+                0.0,
+                self.shadow_distance + radius,
+            );
+            cascades.push(Cascade {
+                light_space: light_projection * light_view,
+                far: cascade_far,
+            });
+            cascade_near = cascade_far;
+        }
+        cascades
+    }
+
+    // Practical split scheme, `lambda` 0 spaces the cascades evenly and 1 logarithmically
+    fn split(near: f32, far: f32, fraction: f32, lambda: f32) -> f32 {
+        let uniform = near + (far - near) * fraction;
+        let logarithmic = near * (far / near).powf(fraction);
+        uniform + (logarithmic - uniform) * lambda
+    }
+
+    pub fn cube_light_space(&self, transform: &Transform) -> [Mat4; 6] {
+        let position = transform.global_position();
+        let projection = glm::perspective(1.0, FRAC_PI_2, Self::SHADOW_NEAR, self.shadow_distance);
+        Self::CUBE_FACES.map(|(direction, up)| {
+            let target = position + Vec3::from(direction);
+            projection * glm::look_at(&position, &target, &Vec3::from(up))
+        })
+    }
+
+    // Any vector that isn't parallel to the direction
+    fn up(direction: &Vec3) -> Vec3 {
+        if direction.y.abs() > 0.99 {
+            Vec3::z()
+        } else {
+            Vec3::y()
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use glm::{Mat4, Quat, Vec3, Vec4};
+use glm::{Mat3, Mat4, Quat, Vec3, Vec4};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug)]
@@ -81,6 +81,62 @@ impl Transform {
     }
 }
 
+// Extrinsic rotation order, XYZ rotates around the world X axis first and around Z last
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub enum RotationOrder {
+    #[default]
+    XYZ,
+    XZY,
+    YXZ,
+    YZX,
+    ZXY,
+    ZYX,
+}
+
+impl RotationOrder {
+    fn axes(&self) -> [usize; 3] {
+        match self {
+            RotationOrder::XYZ => [0, 1, 2],
+            RotationOrder::XZY => [0, 2, 1],
+            RotationOrder::YXZ => [1, 0, 2],
+            RotationOrder::YZX => [1, 2, 0],
+            RotationOrder::ZXY => [2, 0, 1],
+            RotationOrder::ZYX => [2, 1, 0],
+        }
+    }
+}
+
+// Same convention as `Transform::rotate` when the order is XYZ
+pub fn euler_to_quat(euler: &Vec3, order: RotationOrder) -> Quat {
+    let radians = glm::radians(euler);
+    let mut result = glm::quat_identity();
+    for axis in order.axes() {
+        let mut unit = Vec3::zeros();
+        unit[axis] = 1.0;
+        result = glm::quat_angle_axis(radians[axis], &unit) * result;
+    }
+    result
+}
+
+/// Splits an affine matrix into translation, rotation and scale, shear is lost
+pub fn decompose(matrix: &Mat4) -> (Vec3, Quat, Vec3) {
+    let position = glm::vec3(matrix[(0, 3)], matrix[(1, 3)], matrix[(2, 3)]);
+
+    let x = glm::vec3(matrix[(0, 0)], matrix[(1, 0)], matrix[(2, 0)]);
+    let y = glm::vec3(matrix[(0, 1)], matrix[(1, 1)], matrix[(2, 1)]);
+    let z = glm::vec3(matrix[(0, 2)], matrix[(1, 2)], matrix[(2, 2)]);
+
+    let mut scale = glm::vec3(glm::length(&x), glm::length(&y), glm::length(&z));
+    if glm::dot(&glm::cross(&x, &y), &z) < 0.0 {
+        scale.x = -scale.x; // mirrored basis
+    }
+
+    let rotation = Mat3::from_columns(&[x / scale.x, y / scale.y, z / scale.z]);
+    let orientation = glm::quat_normalize(&glm::mat3_to_quat(&rotation));
+
+    (position, orientation, scale)
+}
+
 #[derive(Clone, Copy, Serialize, Deserialize, Debug)]
 pub enum Projection {
     Orthographic {
@@ -145,6 +201,25 @@ impl Projection {
             } => glm::perspective(aspect, fovy.to_radians(), near, far),
         }
     }
+
+    pub fn range(&self) -> (f32, f32) {
+        match *self {
+            Self::Orthographic { znear, zfar, .. } => (znear, zfar),
+            Self::Perspective { near, far, .. } => (near, far),
+        }
+    }
+
+    // Same projection between other clipping planes
+    pub fn with_range(&self, near: f32, far: f32) -> Self {
+        let mut projection = *self;
+        match &mut projection {
+            Self::Orthographic { znear, zfar, .. } => (*znear, *zfar) = (near, far),
+            Self::Perspective {
+                near: n, far: f, ..
+            } => (*n, *f) = (near, far),
+        }
+        projection
+    }
 }
 
 impl Default for Projection {
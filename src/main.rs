@@ -1,29 +1,52 @@
 #![allow(unused)]
 // #![windows_subsystem = "windows"]
 
-use std::io;
+use std::{env, io};
 
 extern crate nalgebra_glm as glm;
 
+mod animation;
+mod asset_loader;
+mod asset_path;
 mod camera;
+mod cook;
 mod data3d;
 mod entity_system;
 mod gl_wrappers;
+mod hot_reload;
 mod lighting;
 mod linear;
 mod material;
+mod procedural;
 mod rendering;
 mod resources;
 mod runtime;
 mod scene;
+mod scene_tool;
 mod scripting;
 mod serializable;
 mod shader;
 mod some_idea;
+mod texture_formats;
 mod utils;
+mod vfs;
 mod idea2;
 
 fn main() {
+    let args = env::args().collect::<Vec<String>>();
+    if args.get(1).is_some_and(|arg| arg == "scene") {
+        scene_tool::run(&args[2..]);
+        return;
+    }
+    if args.get(1).is_some_and(|arg| arg == "cook") {
+        cook::run(&args[2..]);
+        return;
+    }
+    if args.get(1).is_some_and(|arg| arg == "pack") {
+        vfs::pack(&args[2..]);
+        return;
+    }
+
     let mm = some_idea::MemoryManager::new().unwrap();
     io::stdin().read_line(&mut String::new());
     // scene::Scene::sample();
@@ -1,4 +1,5 @@
 use crate::gl_wrappers::Texture;
+use serde::{Deserialize, Serialize};
 
 #[rustfmt::skip]
 pub struct Material {
@@ -8,4 +9,72 @@ pub struct Material {
     pub ao:             usize,
     pub normals:        usize,
     pub displacement:   usize,
+    pub emissive:       usize,
+    pub factors:        MaterialFactors,
+    pub alpha_mode:     AlphaMode,
+    pub two_sided:      bool,
+}
+
+impl Material {
+    pub fn textures(&self) -> [usize; 7] {
+        [
+            self.base_color,
+            self.metalness,
+            self.roughness,
+            self.ao,
+            self.normals,
+            self.displacement,
+            self.emissive,
+        ]
+    }
+}
+
+// Multiplied with the sampled texture values, so a material without textures is fully
+// described by its factors
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct MaterialFactors {
+    pub base_color: [f32; 4],
+    pub metalness: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    pub normal_scale: f32,
+}
+
+impl Default for MaterialFactors {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0; 4],
+            metalness: 1.0,
+            roughness: 1.0,
+            emissive: [0.0; 3],
+            normal_scale: 1.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    Mask(f32), // alpha cutoff
+    Blend,
+}
+
+impl AlphaMode {
+    // Matches the `alpha_mode` values expected by the fragment shader
+    pub fn shader_value(&self) -> u32 {
+        match self {
+            AlphaMode::Opaque => 0,
+            AlphaMode::Mask(_) => 1,
+            AlphaMode::Blend => 2,
+        }
+    }
+
+    pub fn cutoff(&self) -> f32 {
+        match self {
+            AlphaMode::Mask(cutoff) => *cutoff,
+            _ => 0.0,
+        }
+    }
 }
@@ -0,0 +1,429 @@
+use crate::{asset_path::BUILTIN_SCHEME, data3d::Vertex};
+use glm::Vec3;
+use russimp::{Color4D, Vector2D, Vector3D};
+use std::{
+    collections::HashMap,
+    f32::consts::{FRAC_PI_2, PI, TAU},
+    str::FromStr,
+};
+
+// Meshes generated instead of read from a file, named like "builtin://uv_sphere?rings=24".
+// Every parameter has a default, shapes are centered on the origin with Y up
+pub const SHAPES: [&str; 8] = [
+    "cube",
+    "plane",
+    "uv_sphere",
+    "ico_sphere",
+    "cylinder",
+    "cone",
+    "torus",
+    "capsule",
+];
+
+pub fn is_builtin(path: &str) -> bool {
+    path.starts_with(BUILTIN_SCHEME)
+}
+
+pub fn generate(path: &str) -> Result<(Vec<Vertex>, Vec<u32>), String> {
+    let name = path.strip_prefix(BUILTIN_SCHEME).unwrap_or(path);
+    let (shape, query) = name.split_once('?').unwrap_or((name, ""));
+    let mut params = Params::parse(query)?;
+
+    let geometry = match shape {
+        "cube" => cube(params.get("size", 1.0)?),
+        "plane" => plane(params.get("size", 1.0)?, params.get("subdivisions", 0)?),
+        "uv_sphere" => uv_sphere(
+            params.get("radius", 0.5)?,
+            params.get("segments", 32)?,
+            params.get("rings", 16)?,
+        ),
+        "ico_sphere" => ico_sphere(params.get("radius", 0.5)?, params.get("subdivisions", 2)?),
+        "cylinder" => cylinder(
+            params.get("radius", 0.5)?,
+            params.get("height", 1.0)?,
+            params.get("segments", 32)?,
+        ),
+        "cone" => cone(
+            params.get("radius", 0.5)?,
+            params.get("height", 1.0)?,
+            params.get("segments", 32)?,
+        ),
+        "torus" => torus(
+            params.get("radius", 0.5)?,
+            params.get("tube_radius", 0.2)?,
+            params.get("segments", 32)?,
+            params.get("tube_segments", 16)?,
+        ),
+        "capsule" => capsule(
+            params.get("radius", 0.5)?,
+            params.get("height", 2.0)?,
+            params.get("segments", 32)?,
+            params.get("rings", 8)?,
+        ),
+        _ => {
+            return Err(format!(
+                "unknown builtin mesh {shape}, the shapes are {}",
+                SHAPES.join(", ")
+            ))
+        }
+    };
+    params.finish()?;
+    Ok(geometry)
+}
+
+struct Params(HashMap<String, String>);
+
+impl Params {
+    fn parse(query: &str) -> Result<Self, String> {
+        let mut params = HashMap::new();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("parameter {pair} has no value"))?;
+            _ = params.insert(key.to_string(), value.to_string());
+        }
+        Ok(Self(params))
+    }
+
+    fn get<T: FromStr>(&mut self, key: &str, default: T) -> Result<T, String> {
+        match self.0.remove(key) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("invalid value {value} of {key}")),
+            None => Ok(default),
+        }
+    }
+
+    // Typos would otherwise go unnoticed
+    fn finish(self) -> Result<(), String> {
+        match self.0.keys().next() {
+            Some(key) => Err(format!("unknown parameter {key}")),
+            None => Ok(()),
+        }
+    }
+}
+
+type Geometry = (Vec<Vertex>, Vec<u32>);
+
+pub fn vertex(position: Vec3, normal: Vec3, tangent: Vec3, tex_coord: (f32, f32)) -> Vertex {
+    let vector = |v: Vec3| Vector3D {
+        x: v.x,
+        y: v.y,
+        z: v.z,
+    };
+    Vertex {
+        position: vector(position),
+        normal: vector(normal),
+        tex_coord: Vector2D {
+            x: tex_coord.0,
+            y: tex_coord.1,
+        },
+        tangent: vector(tangent),
+        bitangent: vector(normal.cross(&tangent)),
+        color: Color4D {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+            a: 1.0,
+        },
+        tex_coord2: Vector2D::default(),
+        bone_indices: [0.0; 4],
+        bone_weights: [0.0; 4],
+    }
+}
+
+// Normals averaged from the triangles around each vertex, weighted by their area
+pub fn smooth_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let position =
+        |vertex: &Vertex| glm::vec3(vertex.position.x, vertex.position.y, vertex.position.z);
+    let mut normals = vec![Vec3::zeros(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+        let normal = (position(&vertices[b]) - position(&vertices[a]))
+            .cross(&(position(&vertices[c]) - position(&vertices[a])));
+        for i in [a, b, c] {
+            normals[i] += normal;
+        }
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        let normal = normal.try_normalize(f32::EPSILON).unwrap_or(Vec3::y());
+        vertex.normal = Vector3D {
+            x: normal.x,
+            y: normal.y,
+            z: normal.z,
+        };
+    }
+}
+
+// Grid of vertices over (u, v), `point` gives the position, normal and tangent at each.
+// u runs along the columns and v along `rows`, which are sorted values from 0 to 1. The
+// surface must face the side du x dv points to for the triangles to wind counterclockwise
+fn surface(
+    columns: usize,
+    rows: &[f32],
+    point: impl Fn(f32, f32) -> (Vec3, Vec3, Vec3),
+) -> Geometry {
+    let mut vertices = Vec::with_capacity((columns + 1) * rows.len());
+    for v in rows {
+        for i in 0..=columns {
+            let u = i as f32 / columns as f32;
+            let (position, normal, tangent) = point(u, *v);
+            vertices.push(vertex(position, normal, tangent, (u, *v)));
+        }
+    }
+
+    let mut indices = Vec::with_capacity(columns * (rows.len() - 1) * 6);
+    let stride = columns as u32 + 1;
+    for j in 0..rows.len() as u32 - 1 {
+        for i in 0..columns as u32 {
+            let a = j * stride + i;
+            let b = a + 1;
+            let c = b + stride;
+            let d = a + stride;
+            indices.extend_from_slice(&[a, b, c, a, c, d]);
+        }
+    }
+    (vertices, indices)
+}
+
+fn steps(count: usize) -> Vec<f32> {
+    (0..=count).map(|i| i as f32 / count as f32).collect()
+}
+
+fn append(geometry: &mut Geometry, other: Geometry) {
+    let offset = geometry.0.len() as u32;
+    geometry.0.extend(other.0);
+    geometry
+        .1
+        .extend(other.1.iter().map(|index| index + offset));
+}
+
+// Square of `size` facing `normal`, u runs along `tangent`
+fn face(normal: Vec3, tangent: Vec3, size: f32, subdivisions: usize) -> Geometry {
+    let bitangent = normal.cross(&tangent);
+    let segments = subdivisions + 1;
+    surface(segments, &steps(segments), |u, v| {
+        let position = (normal * 0.5 + tangent * (u - 0.5) + bitangent * (v - 0.5)) * size;
+        (position, normal, tangent)
+    })
+}
+
+fn cube(size: f32) -> Geometry {
+    let mut geometry = (Vec::new(), Vec::new());
+    for (normal, tangent) in [
+        (Vec3::x(), -Vec3::z()),
+        (-Vec3::x(), Vec3::z()),
+        (Vec3::y(), Vec3::x()),
+        (-Vec3::y(), Vec3::x()),
+        (Vec3::z(), Vec3::x()),
+        (-Vec3::z(), -Vec3::x()),
+    ] {
+        append(&mut geometry, face(normal, tangent, size, 0));
+    }
+    geometry
+}
+
+// Lies in the XZ plane facing up
+fn plane(size: f32, subdivisions: usize) -> Geometry {
+    let (mut vertices, indices) = face(Vec3::y(), Vec3::x(), size, subdivisions);
+    for vertex in &mut vertices {
+        vertex.position.y = 0.0;
+    }
+    (vertices, indices)
+}
+
+// Point on the unit sphere, `theta` goes from the bottom pole to the top one and `phi`
+// around Y starting at +Z
+fn sphere_point(theta: f32, phi: f32) -> (Vec3, Vec3) {
+    let normal = Vec3::new(
+        theta.sin() * phi.sin(),
+        -theta.cos(),
+        theta.sin() * phi.cos(),
+    );
+    let tangent = Vec3::new(phi.cos(), 0.0, -phi.sin());
+    (normal, tangent)
+}
+
+fn uv_sphere(radius: f32, segments: usize, rings: usize) -> Geometry {
+    surface(segments.max(3), &steps(rings.max(2)), |u, v| {
+        let (normal, tangent) = sphere_point(v * PI, u * TAU);
+        (normal * radius, normal, tangent)
+    })
+}
+
+fn ico_sphere(radius: f32, subdivisions: usize) -> Geometry {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut positions = [
+        (-1.0, t, 0.0),
+        (1.0, t, 0.0),
+        (-1.0, -t, 0.0),
+        (1.0, -t, 0.0),
+        (0.0, -1.0, t),
+        (0.0, 1.0, t),
+        (0.0, -1.0, -t),
+        (0.0, 1.0, -t),
+        (t, 0.0, -1.0),
+        (t, 0.0, 1.0),
+        (-t, 0.0, -1.0),
+        (-t, 0.0, 1.0),
+    ]
+    .map(|(x, y, z)| Vec3::new(x, y, z).normalize())
+    .to_vec();
+    #[rustfmt::skip]
+    let mut triangles = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    // Every triangle is split in four, edges shared by two triangles share the midpoint
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::<(u32, u32), u32>::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let point = (positions[a as usize] + positions[b as usize]).normalize();
+                positions.push(point);
+                positions.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let ab = midpoint(a, b);
+                let bc = midpoint(b, c);
+                let ca = midpoint(c, a);
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    // Mapped the same way as the uv sphere
+    let tex_coord = |normal: &Vec3| {
+        let u = (normal.x.atan2(normal.z) / TAU).rem_euclid(1.0);
+        let v = (-normal.y).clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    };
+    let mut vertices = positions
+        .iter()
+        .map(|normal| {
+            let (u, v) = tex_coord(normal);
+            let (_, tangent) = sphere_point(v * PI, u * TAU);
+            vertex(normal * radius, *normal, tangent, (u, v))
+        })
+        .collect::<Vec<Vertex>>();
+
+    // Triangles across the seam would stretch over the whole texture, their vertices on the
+    // near side get copies past u = 1
+    let mut indices = Vec::with_capacity(triangles.len() * 3);
+    for triangle in &triangles {
+        let us = triangle.map(|index| vertices[index as usize].tex_coord.x);
+        let wraps = us.iter().any(|u| *u > 0.75) && us.iter().any(|u| *u < 0.25);
+        for (index, u) in triangle.iter().zip(us) {
+            if wraps && u < 0.5 {
+                let mut copy = vertices[*index as usize];
+                copy.tex_coord.x += 1.0;
+                vertices.push(copy);
+                indices.push(vertices.len() as u32 - 1);
+            } else {
+                indices.push(*index);
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+// Flat disc at `y` facing up or down
+fn disc(radius: f32, y: f32, segments: usize, up: bool) -> Geometry {
+    let normal = if up { Vec3::y() } else { -Vec3::y() };
+    let tangent = Vec3::x();
+    let bitangent = normal.cross(&tangent);
+    let tex_coord = |position: &Vec3| {
+        let u = 0.5 + position.dot(&tangent) / (2.0 * radius);
+        let v = 0.5 + position.dot(&bitangent) / (2.0 * radius);
+        (u, v)
+    };
+
+    let center = Vec3::new(0.0, y, 0.0);
+    let mut vertices = vec![vertex(center, normal, tangent, (0.5, 0.5))];
+    for i in 0..=segments {
+        let phi = i as f32 / segments as f32 * TAU;
+        let position = Vec3::new(radius * phi.sin(), y, radius * phi.cos());
+        vertices.push(vertex(position, normal, tangent, tex_coord(&position)));
+    }
+
+    let mut indices = Vec::with_capacity(segments * 3);
+    for i in 1..=segments as u32 {
+        if up {
+            indices.extend_from_slice(&[0, i, i + 1]);
+        } else {
+            indices.extend_from_slice(&[0, i + 1, i]);
+        }
+    }
+    (vertices, indices)
+}
+
+fn cylinder(radius: f32, height: f32, segments: usize) -> Geometry {
+    let segments = segments.max(3);
+    let mut geometry = surface(segments, &steps(1), |u, v| {
+        let (normal, tangent) = sphere_point(FRAC_PI_2, u * TAU);
+        let position = normal * radius + Vec3::y() * (v - 0.5) * height;
+        (position, normal, tangent)
+    });
+    append(&mut geometry, disc(radius, height / 2.0, segments, true));
+    append(&mut geometry, disc(radius, -height / 2.0, segments, false));
+    geometry
+}
+
+// The apex is a ring of vertices so each side gets its own normal
+fn cone(radius: f32, height: f32, segments: usize) -> Geometry {
+    let segments = segments.max(3);
+    let mut geometry = surface(segments, &steps(1), |u, v| {
+        let (outward, tangent) = sphere_point(FRAC_PI_2, u * TAU);
+        let position = outward * radius * (1.0 - v) + Vec3::y() * (v - 0.5) * height;
+        let normal = (outward * height + Vec3::y() * radius).normalize();
+        (position, normal, tangent)
+    });
+    append(&mut geometry, disc(radius, -height / 2.0, segments, false));
+    geometry
+}
+
+// Lies in the XZ plane, `radius` is measured to the middle of the tube
+fn torus(radius: f32, tube_radius: f32, segments: usize, tube_segments: usize) -> Geometry {
+    let tube_segments = tube_segments.max(3);
+    surface(segments.max(3), &steps(tube_segments), |u, v| {
+        let (outward, tangent) = sphere_point(FRAC_PI_2, u * TAU);
+        let theta = v * TAU;
+        let normal = outward * theta.cos() + Vec3::y() * theta.sin();
+        let position = outward * radius + normal * tube_radius;
+        (position, normal, tangent)
+    })
+}
+
+// `height` includes the caps, v is spread by length so the texture isn't stretched
+fn capsule(radius: f32, height: f32, segments: usize, rings: usize) -> Geometry {
+    let rings = rings.max(1);
+    let body = (height - 2.0 * radius).max(0.0);
+    let quarter = FRAC_PI_2 * radius; // length of a cap from the pole to the body
+    let length = 2.0 * quarter + body;
+
+    let mut rows = Vec::with_capacity(2 * rings + 2);
+    for i in 0..=rings {
+        rows.push(quarter * i as f32 / rings as f32 / length);
+    }
+    for i in 0..=rings {
+        rows.push((quarter + body + quarter * i as f32 / rings as f32) / length);
+    }
+    surface(segments.max(3), &rows, |u, v| {
+        let s = v * length;
+        let (theta, offset) = if s <= quarter {
+            (s / radius, -body / 2.0)
+        } else if s <= quarter + body {
+            (FRAC_PI_2, s - quarter - body / 2.0)
+        } else {
+            ((s - body) / radius, body / 2.0)
+        };
+        let (normal, tangent) = sphere_point(theta, u * TAU);
+        (normal * radius + Vec3::y() * offset, normal, tangent)
+    })
+}
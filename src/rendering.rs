@@ -1,29 +1,34 @@
 use crate::{
+    animation::{self, Skeleton},
     camera::Camera,
     data3d::{self, Mesh, MeshData, VertexAttribute},
     entity_system::SceneManager,
     gl_wrappers::{self, BufferObject, Gl, Renderbuffer, ShaderProgram, Texture},
-    lighting::{LightData, LightSource},
+    lighting::{LightData, LightSource, LightType},
+    material::{AlphaMode, Material},
     resources::MeshManager,
     runtime::FramebufferSizeCallback,
     shader::{
-        self, DirectPBR, FragShader, MainShader, ScreenShaderFrag, ScreenShaderVert, VertShader,
+        self, DirectPBR, FragShader, MainShader, ScreenShaderFrag, ScreenShaderVert,
+        ShaderDataSource, ShadowShaderFrag, VertShader,
     },
+    vfs::Vfs,
 };
 use gl::types::GLenum;
 use glfw::Version;
 use nalgebra_glm::{Mat4, Vec3};
 use std::{
     marker::PhantomData,
-    mem::{size_of, size_of_val, MaybeUninit},
+    mem::{offset_of, size_of, size_of_val, MaybeUninit},
     ptr,
 };
 
-// Добавить сущности ShadowMapping / CascadingShadowMapping ?
-
 pub enum BindingPoints {
     MatrixData = 0,
     LightingData = 1,
+    BoneData = 2,
+    MorphData = 3,
+    MorphDeltas = 4, // shader storage buffer
 }
 
 #[repr(C)]
@@ -34,7 +39,28 @@ pub struct MatrixData {
     pub light_space: Mat4,
 }
 
+// Only the bones the skeleton has are written
+#[repr(C)]
+pub struct BoneData {
+    pub skinned: u32,
+    _padding: [u32; 3],
+    pub bones: [Mat4; animation::MAX_BONES],
+}
+
+#[repr(C)]
+pub struct MorphData {
+    pub target_count: u32,
+    pub vertex_count: u32,
+    _padding: [u32; 2],
+    pub weights: [f32; data3d::MAX_MORPH_TARGETS],
+}
+
 pub const MAX_LIGHT_SOURCES_PER_FRAME: usize = 16;
+// Of each kind, lights past them are drawn without shadows
+pub const MAX_SHADOW_MAPS: usize = 4;
+// Texture units past the material's ones
+pub const SHADOW_MAP_UNIT: u32 = 8;
+pub const SHADOW_CUBE_MAP_UNIT: u32 = SHADOW_MAP_UNIT + MAX_SHADOW_MAPS as u32;
 
 #[derive(Debug)]
 #[repr(C)]
@@ -42,6 +68,8 @@ pub struct LightingData {
     pub light_sources: [MaybeUninit<LightData>; MAX_LIGHT_SOURCES_PER_FRAME],
     pub viewer_position: Vec3,
     pub source_count: u32,
+    pub viewer_direction: Vec3, // cascades are picked by the depth along it
+    _padding: f32,
 }
 
 impl LightingData {
@@ -50,6 +78,8 @@ impl LightingData {
             light_sources: [MaybeUninit::zeroed(); MAX_LIGHT_SOURCES_PER_FRAME],
             viewer_position: Default::default(),
             source_count: Default::default(),
+            viewer_direction: Default::default(),
+            _padding: Default::default(),
         }
     }
 }
@@ -62,6 +92,22 @@ fn matrix_data_buffer() -> BufferObject {
     buffer
 }
 
+fn bone_data_buffer() -> BufferObject {
+    let buffer = BufferObject::new(gl::UNIFORM_BUFFER).unwrap();
+    buffer.bind();
+    buffer.buffer_data(size_of::<BoneData>(), ptr::null(), gl::DYNAMIC_DRAW);
+    buffer.bind_buffer_base(BindingPoints::BoneData as u32);
+    buffer
+}
+
+fn morph_data_buffer() -> BufferObject {
+    let buffer = BufferObject::new(gl::UNIFORM_BUFFER).unwrap();
+    buffer.bind();
+    buffer.buffer_data(size_of::<MorphData>(), ptr::null(), gl::DYNAMIC_DRAW);
+    buffer.bind_buffer_base(BindingPoints::MorphData as u32);
+    buffer
+}
+
 fn lighting_data_buffer() -> BufferObject {
     let buffer = BufferObject::new(gl::UNIFORM_BUFFER).unwrap();
     buffer.bind();
@@ -75,39 +121,87 @@ pub struct Renderer<'a> {
     pd: PhantomData<&'a ()>,
     framebuffer: Framebuffer,
     shader_program: ShaderProgram,
+    shadow_program: ShaderProgram,
+    shadow_maps: Vec<Framebuffer>, // of directional and spot lights
+    shadow_cube_maps: Vec<Framebuffer>, // of point lights
+    debug_cascades: bool,
     matrix_buffer: BufferObject,
     lighting_buffer: BufferObject,
+    bone_buffer: BufferObject,
+    morph_buffer: BufferObject,
 }
 
 impl<'a> Renderer<'a> {
-    pub fn new(size: (i32, i32), context_version: Version, _: &'a Gl) -> Self {
+    pub fn new(size: (i32, i32), context_version: Version, vfs: &Vfs, _: &'a Gl) -> Self {
         // let size = (size.0 / 4, size.1 / 4);
         let framebuffer = Framebuffer::new(size, gl::NEAREST, gl::NEAREST);
 
+        let program = Self::build_program(context_version, vfs).unwrap();
+        let shadow_program = Self::build_shadow_program(context_version, vfs).unwrap();
+        let matrix_buffer = matrix_data_buffer();
+        let lighting_buffer = lighting_data_buffer();
+        let bone_buffer = bone_data_buffer();
+        let morph_buffer = morph_data_buffer();
+
+        Self {
+            framebuffer,
+            shader_program: program,
+            shadow_program,
+            shadow_maps: Vec::new(),
+            shadow_cube_maps: Vec::new(),
+            debug_cascades: false,
+            matrix_buffer,
+            lighting_buffer,
+            bone_buffer,
+            morph_buffer,
+            pd: PhantomData::default(),
+        }
+    }
+
+    fn build_program(context_version: Version, vfs: &Vfs) -> Result<ShaderProgram, String> {
         let main_vert = MainShader::<VertShader>::new();
         let mut main_frag = MainShader::<FragShader>::new();
         let lighting_shader = DirectPBR::new();
         main_frag.attach_shader(&lighting_shader);
 
-        let main_vert = shader::build_shader(&main_vert, context_version);
-        let main_frag = shader::build_shader(&main_frag, context_version);
-        let lighting_shader = shader::build_shader(&lighting_shader, context_version);
+        let main_vert = shader::try_build_shader(&main_vert, context_version, vfs)?;
+        let main_frag = shader::try_build_shader(&main_frag, context_version, vfs)?;
+        let lighting_shader = shader::try_build_shader(&lighting_shader, context_version, vfs)?;
 
         let program = ShaderProgram::new().unwrap();
         program.attach_shader(&main_vert);
         program.attach_shader(&main_frag);
         program.attach_shader(&lighting_shader);
         program.link();
-        assert!(program.link_success());
-        let matrix_buffer = matrix_data_buffer();
-        let lighting_buffer = lighting_data_buffer();
+        if !program.link_success() {
+            return Err(program.info_log());
+        }
+        Ok(program)
+    }
 
-        Self {
-            framebuffer,
-            shader_program: program,
-            matrix_buffer,
-            lighting_buffer,
-            pd: PhantomData::default(),
+    fn build_shadow_program(context_version: Version, vfs: &Vfs) -> Result<ShaderProgram, String> {
+        let vert =
+            shader::try_build_shader(&MainShader::<VertShader>::new(), context_version, vfs)?;
+        let frag = shader::try_build_shader(&ShadowShaderFrag::new(), context_version, vfs)?;
+        let program = ShaderProgram::new().unwrap();
+        program.attach_shader(&vert);
+        program.attach_shader(&frag);
+        program.link();
+        if !program.link_success() {
+            return Err(program.info_log());
+        }
+        Ok(program)
+    }
+
+    // Keeps the current program if the edited sources don't compile
+    pub fn reload_shaders(&mut self, context_version: Version, vfs: &Vfs) {
+        match Self::build_program(context_version, vfs) {
+            Ok(program) => self.shader_program = program,
+            Err(log) => println!("Main shader reload failed: {log}"),
+        }
+        match Self::build_shadow_program(context_version, vfs) {
+            Ok(program) => self.shadow_program = program,
+            Err(log) => println!("Shadow shader reload failed: {log}"),
         }
     }
 
@@ -115,7 +209,16 @@ impl<'a> Renderer<'a> {
         &self.framebuffer
     }
 
-    pub fn render(&self, scene_manager: &SceneManager, mesh_manager: &MeshManager) {
+    pub fn debug_cascades(&self) -> bool {
+        self.debug_cascades
+    }
+
+    // Tints what each cascade of the first shadowed directional light covers
+    pub fn set_debug_cascades(&mut self, debug_cascades: bool) {
+        self.debug_cascades = debug_cascades;
+    }
+
+    pub fn render(&mut self, scene_manager: &SceneManager, mesh_manager: &MeshManager) {
         let camera = match scene_manager.component_slice::<Camera>().first() {
             Some(cam) => cam,
             None => return,
@@ -125,20 +228,85 @@ impl<'a> Renderer<'a> {
         }
 
         let camera_transform = scene_manager.get_transform(camera.owner_id());
+        let viewer_position = camera_transform.global_position();
+        Self::gl_enable();
 
         let mut lighting_data = LightingData::new();
         let lights = scene_manager.component_slice::<LightSource>();
+        let (mut maps, mut cube_maps) = (0, 0);
+        let mut main_light_space = Mat4::identity(); // of the first map
         let mut i = 0;
         for light_source in lights {
             if i == MAX_LIGHT_SOURCES_PER_FRAME {
                 break;
             }
+            let light = &light_source.data;
             let light_transform = scene_manager.get_transform(light_source.owner_id());
-            lighting_data.light_sources[i].write(light_source.data.get_data(&light_transform));
+            let mut data = light.get_data(&light_transform);
+            if light.shadows.enabled {
+                let resolution = light.shadows.resolution.max(1) as i32;
+                match light.type_() {
+                    LightType::Point if cube_maps < MAX_SHADOW_MAPS => {
+                        Self::prepare_shadow_map(
+                            &mut self.shadow_cube_maps,
+                            cube_maps,
+                            resolution,
+                            6,
+                            true,
+                        );
+                        let position = light_transform.global_position();
+                        let far = light.shadow_distance;
+                        let faces = light.cube_light_space(light_transform);
+                        for (face, light_space) in faces.iter().enumerate() {
+                            self.shadow_cube_maps[cube_maps].bind_cube_face(face as u32);
+                            self.render_depth(
+                                light_space,
+                                &position,
+                                far,
+                                scene_manager,
+                                mesh_manager,
+                            );
+                        }
+                        data.set_shadow(cube_maps, &[], &light.shadows, far);
+                        cube_maps += 1;
+                    }
+                    LightType::Directional | LightType::Spot if maps < MAX_SHADOW_MAPS => {
+                        let cascades =
+                            light.cascades(light_transform, &camera.data, camera_transform);
+                        let layers = cascades.len() as i32;
+                        Self::prepare_shadow_map(
+                            &mut self.shadow_maps,
+                            maps,
+                            resolution,
+                            layers,
+                            false,
+                        );
+                        if maps == 0 {
+                            main_light_space = cascades[0].light_space;
+                        }
+                        for (layer, cascade) in cascades.iter().enumerate() {
+                            self.shadow_maps[maps].bind_layer(layer as i32);
+                            self.render_depth(
+                                &cascade.light_space,
+                                &Vec3::zeros(),
+                                0.0,
+                                scene_manager,
+                                mesh_manager,
+                            );
+                        }
+                        data.set_shadow(maps, &cascades, &light.shadows, 0.0);
+                        maps += 1;
+                    }
+                    _ => {}
+                }
+            }
+            lighting_data.light_sources[i].write(data);
             i += 1;
         }
         lighting_data.source_count = i as u32;
-        lighting_data.viewer_position = camera_transform.global_position();
+        lighting_data.viewer_position = viewer_position;
+        lighting_data.viewer_direction =
+            glm::quat_rotate_vec3(&camera_transform.orientation, &-Vec3::z());
 
         self.lighting_buffer.bind();
         self.lighting_buffer.buffer_subdata(
@@ -149,49 +317,107 @@ impl<'a> Renderer<'a> {
 
         self.framebuffer.bind();
         self.shader_program.use_();
-        Self::gl_enable();
+        unsafe {
+            gl::Uniform1i(
+                ShaderDataSource::DEBUG_CASCADES_LOCATION,
+                self.debug_cascades as i32,
+            );
+        }
         gl_wrappers::clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        for (k, map) in self.shadow_maps.iter().enumerate() {
+            map.color_buffer
+                .bind_to_unit(gl::TEXTURE0 + SHADOW_MAP_UNIT + k as u32);
+        }
+        for (k, map) in self.shadow_cube_maps.iter().enumerate() {
+            map.color_buffer
+                .bind_to_unit(gl::TEXTURE0 + SHADOW_CUBE_MAP_UNIT + k as u32);
+        }
 
-        for mesh_comp in scene_manager.component_slice::<Mesh>() {
-            let mesh_transform = scene_manager.get_transform(mesh_comp.owner_id());
-            let matrix_data = MatrixData {
-                mvp: camera.data.projection_view(camera_transform) * mesh_transform.model(),
-                model: mesh_transform.model(),
-                orientation: glm::quat_to_mat4(&mesh_transform.orientation),
-                light_space: glm::Mat4::identity(),
-            };
-            self.matrix_buffer.bind();
-            self.matrix_buffer.buffer_subdata(
-                size_of::<MatrixData>(),
-                (&matrix_data as *const MatrixData).cast(),
-                0,
+        let view_projection = camera.data.projection_view(camera_transform);
+        self.draw_meshes(
+            &view_projection,
+            &main_light_space,
+            scene_manager,
+            mesh_manager,
+        );
+    }
+
+    // Maps are kept between frames and made again when the light's resolution or cascade
+    // count changes
+    fn prepare_shadow_map(
+        maps: &mut Vec<Framebuffer>,
+        index: usize,
+        resolution: i32,
+        layers: i32,
+        cube: bool,
+    ) {
+        let size = (resolution, resolution);
+        let new = || {
+            if cube {
+                Framebuffer::new_shadow_cubemap(size)
+            } else {
+                Framebuffer::new_shadowmap(size, layers)
+            }
+        };
+        if index == maps.len() {
+            maps.push(new());
+        } else if maps[index].size != size || maps[index].layers != layers {
+            maps[index] = new();
+        }
+    }
+
+    // Into the bound shadow map, `far` is only given for cube maps
+    fn render_depth(
+        &self,
+        light_space: &Mat4,
+        light_position: &Vec3,
+        far: f32,
+        scene_manager: &SceneManager,
+        mesh_manager: &MeshManager,
+    ) {
+        gl_wrappers::clear(gl::DEPTH_BUFFER_BIT);
+        self.shadow_program.use_();
+        unsafe {
+            gl::Uniform3fv(
+                ShadowShaderFrag::LIGHT_POSITION_LOCATION,
+                1,
+                light_position.as_ptr(),
             );
+            gl::Uniform1f(ShadowShaderFrag::FAR_LOCATION, far);
+        }
+        self.draw_meshes(light_space, light_space, scene_manager, mesh_manager);
+    }
 
+    fn draw_meshes(
+        &self,
+        view_projection: &Mat4,
+        light_space: &Mat4,
+        scene_manager: &SceneManager,
+        mesh_manager: &MeshManager,
+    ) {
+        for mesh_comp in scene_manager.component_slice::<Mesh>() {
+            let skeleton_owner = scene_manager.skeleton_owner(mesh_comp.owner_id());
             for (mesh_data, material) in mesh_manager.mesh_n_material(&mesh_comp.data) {
-                mesh_manager
-                    .textures()
-                    .get(material.base_color)
-                    .bind_to_unit(gl::TEXTURE0);
-                mesh_manager
-                    .textures()
-                    .get(material.metalness)
-                    .bind_to_unit(gl::TEXTURE1);
-                mesh_manager
-                    .textures()
-                    .get(material.roughness)
-                    .bind_to_unit(gl::TEXTURE2);
-                mesh_manager
-                    .textures()
-                    .get(material.ao)
-                    .bind_to_unit(gl::TEXTURE3);
-                mesh_manager
-                    .textures()
-                    .get(material.normals)
-                    .bind_to_unit(gl::TEXTURE4);
-                mesh_manager
-                    .textures()
-                    .get(material.displacement)
-                    .bind_to_unit(gl::TEXTURE5);
+                // Skinned submeshes are placed by the bones, relative to the skeleton's entity
+                let skeleton = skeleton_owner.filter(|_| mesh_data.skinned);
+                let owner_id = skeleton.unwrap_or(mesh_comp.owner_id());
+                let mesh_transform = scene_manager.get_transform(owner_id);
+                let matrix_data = MatrixData {
+                    mvp: view_projection * mesh_transform.model(),
+                    model: mesh_transform.model(),
+                    orientation: glm::quat_to_mat4(&mesh_transform.orientation),
+                    light_space: *light_space,
+                };
+                self.matrix_buffer.bind();
+                self.matrix_buffer.buffer_subdata(
+                    size_of::<MatrixData>(),
+                    (&matrix_data as *const MatrixData).cast(),
+                    0,
+                );
+                self.upload_bones(skeleton.and_then(|id| scene_manager.get_skeleton(id)));
+                self.upload_morph_weights(mesh_data, &mesh_comp.data);
+
+                Self::bind_material(material, mesh_manager);
                 mesh_data.bind();
                 unsafe {
                     gl::DrawElements(
@@ -205,6 +431,90 @@ impl<'a> Renderer<'a> {
         }
     }
 
+    fn upload_bones(&self, skeleton: Option<&Skeleton>) {
+        let palette = skeleton.map_or(&[][..], |skeleton| skeleton.palette());
+        let palette = &palette[..palette.len().min(animation::MAX_BONES)];
+        let skinned = skeleton.is_some() as u32;
+        self.bone_buffer.bind();
+        self.bone_buffer.buffer_subdata(
+            size_of::<u32>(),
+            (&skinned as *const u32).cast(),
+            offset_of!(BoneData, skinned) as u32,
+        );
+        self.bone_buffer.buffer_subdata(
+            size_of_val(palette),
+            palette.as_ptr().cast(),
+            offset_of!(BoneData, bones) as u32,
+        );
+    }
+
+    // Weights of the submesh's targets in the order of its deltas
+    fn upload_morph_weights(&self, mesh_data: &MeshData, mesh: &Mesh) {
+        let mut morph_data = MorphData {
+            target_count: mesh_data.morph_targets.len() as u32,
+            vertex_count: mesh_data.vertex_count as u32,
+            _padding: [0; 2],
+            weights: [0.0; data3d::MAX_MORPH_TARGETS],
+        };
+        for (weight, name) in morph_data.weights.iter_mut().zip(&mesh_data.morph_targets) {
+            *weight = mesh.morph_weight(name).unwrap_or(0.0);
+        }
+        let size =
+            offset_of!(MorphData, weights) + size_of::<f32>() * mesh_data.morph_targets.len();
+        self.morph_buffer.bind();
+        self.morph_buffer
+            .buffer_subdata(size, (&morph_data as *const MorphData).cast(), 0);
+        mesh_data.bind_morph_targets(BindingPoints::MorphDeltas as u32);
+    }
+
+    fn bind_material(material: &Material, mesh_manager: &MeshManager) {
+        let textures = mesh_manager.textures();
+        textures.get(material.base_color).bind_to_unit(gl::TEXTURE0);
+        textures.get(material.metalness).bind_to_unit(gl::TEXTURE1);
+        textures.get(material.roughness).bind_to_unit(gl::TEXTURE2);
+        textures.get(material.ao).bind_to_unit(gl::TEXTURE3);
+        textures.get(material.normals).bind_to_unit(gl::TEXTURE4);
+        textures.get(material.displacement).bind_to_unit(gl::TEXTURE5);
+        textures.get(material.emissive).bind_to_unit(gl::TEXTURE6);
+
+        let factors = &material.factors;
+        unsafe {
+            gl::Uniform4fv(
+                ShaderDataSource::BASE_COLOR_FACTOR_LOCATION,
+                1,
+                factors.base_color.as_ptr(),
+            );
+            gl::Uniform1f(ShaderDataSource::METALNESS_FACTOR_LOCATION, factors.metalness);
+            gl::Uniform1f(ShaderDataSource::ROUGHNESS_FACTOR_LOCATION, factors.roughness);
+            gl::Uniform3fv(
+                ShaderDataSource::EMISSIVE_FACTOR_LOCATION,
+                1,
+                factors.emissive.as_ptr(),
+            );
+            gl::Uniform1ui(
+                ShaderDataSource::ALPHA_MODE_LOCATION,
+                material.alpha_mode.shader_value(),
+            );
+            gl::Uniform1f(
+                ShaderDataSource::ALPHA_CUTOFF_LOCATION,
+                material.alpha_mode.cutoff(),
+            );
+
+            if material.two_sided {
+                gl::Disable(gl::CULL_FACE);
+            } else {
+                gl::Enable(gl::CULL_FACE);
+            }
+            // Blended materials are drawn in submission order, no sorting is done yet
+            if material.alpha_mode == AlphaMode::Blend {
+                gl::Enable(gl::BLEND);
+                gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            } else {
+                gl::Disable(gl::BLEND);
+            }
+        }
+    }
+
     fn gl_enable() {
         unsafe {
             gl::Enable(gl::DEPTH_TEST);
@@ -228,23 +538,15 @@ pub struct Screen<'a> {
     program: ShaderProgram,
     quad: MeshData,
     gamma: f32,
+    exposure: f32,
 }
 
 impl<'a> Screen<'a> {
-    pub fn new(size: (i32, i32), context_version: Version, _: &'a Gl) -> Self {
-        let vert = shader::build_shader(&ScreenShaderVert::new(), context_version);
-        let frag = shader::build_shader(&ScreenShaderFrag::new(), context_version);
-        let program = ShaderProgram::new().unwrap();
-        program.attach_shader(&vert);
-        program.attach_shader(&frag);
-        program.link();
-        assert!(program.link_success());
-        program.use_();
+    pub fn new(size: (i32, i32), context_version: Version, vfs: &Vfs, _: &'a Gl) -> Self {
+        let program = Self::build_program(context_version, vfs).unwrap();
         let gamma = 2.2f32;
-        unsafe {
-            gl::Uniform1f(ScreenShaderFrag::GAMMA_LOCATION, 1.0 / gamma);
-            gl::Uniform1f(ScreenShaderFrag::EXPOSURE_LOCATION, 1.0);
-        }
+        let exposure = 1.0f32;
+        Self::set_uniforms(&program, gamma, exposure);
         let quad = MeshData::new(
             6,
             size_of_val(data3d::QUAD_VERTICES_TEX_COORDS),
@@ -260,10 +562,42 @@ impl<'a> Screen<'a> {
             program,
             quad,
             gamma,
+            exposure,
             pd: PhantomData::default(),
         }
     }
 
+    fn build_program(context_version: Version, vfs: &Vfs) -> Result<ShaderProgram, String> {
+        let vert = shader::try_build_shader(&ScreenShaderVert::new(), context_version, vfs)?;
+        let frag = shader::try_build_shader(&ScreenShaderFrag::new(), context_version, vfs)?;
+        let program = ShaderProgram::new().unwrap();
+        program.attach_shader(&vert);
+        program.attach_shader(&frag);
+        program.link();
+        if !program.link_success() {
+            return Err(program.info_log());
+        }
+        Ok(program)
+    }
+
+    fn set_uniforms(program: &ShaderProgram, gamma: f32, exposure: f32) {
+        program.use_();
+        unsafe {
+            gl::Uniform1f(ScreenShaderFrag::GAMMA_LOCATION, 1.0 / gamma);
+            gl::Uniform1f(ScreenShaderFrag::EXPOSURE_LOCATION, exposure);
+        }
+    }
+
+    pub fn reload_shaders(&mut self, context_version: Version, vfs: &Vfs) {
+        match Self::build_program(context_version, vfs) {
+            Ok(program) => {
+                Self::set_uniforms(&program, self.gamma, self.exposure);
+                self.program = program;
+            }
+            Err(log) => println!("Screen shader reload failed: {log}"),
+        }
+    }
+
     pub fn render_offscreen(&self, offscreen: &Framebuffer) {
         Framebuffer::bind_default(self.size);
         self.program.use_();
@@ -284,7 +618,8 @@ impl<'a> Screen<'a> {
         }
     }
 
-    pub fn set_exposure(&self, exposure: f32) {
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure;
         self.program.use_();
         unsafe {
             gl::Uniform1f(ScreenShaderFrag::EXPOSURE_LOCATION, exposure);
@@ -310,6 +645,7 @@ pub struct Framebuffer {
     pub color_buffer: Texture,
     depth_stencil_buffer: Renderbuffer,
     pub size: (i32, i32),
+    pub layers: i32, // of shadow maps, faces count as layers of cube maps
 }
 
 impl Framebuffer {
@@ -337,25 +673,70 @@ impl Framebuffer {
             color_buffer,
             depth_stencil_buffer,
             size,
+            layers: 1,
         }
     }
 
-    pub fn new_shadowmap(size: (i32, i32), mag: GLenum, min: GLenum) -> Self {
-        let sampler_buffer = Texture::new(gl::TEXTURE_2D).unwrap();
+    // Depth only, sampled with comparison, a layer is drawn at a time after `bind_layer`
+    pub fn new_shadowmap(size: (i32, i32), layers: i32) -> Self {
+        let sampler_buffer = Texture::new(gl::TEXTURE_2D_ARRAY).unwrap();
         sampler_buffer.bind();
-        sampler_buffer.texture_data(
+        sampler_buffer.texture_array_data(
             size,
+            layers,
             ptr::null(),
             gl::FLOAT,
             gl::DEPTH_COMPONENT,
-            gl::DEPTH_COMPONENT,
+            gl::DEPTH_COMPONENT24,
         );
-        sampler_buffer.parameter(gl::TEXTURE_MIN_FILTER, min);
-        sampler_buffer.parameter(gl::TEXTURE_MAG_FILTER, mag);
+        Self::shadow_parameters(&sampler_buffer);
 
         let framebuffer = gl_wrappers::Framebuffer::new(gl::FRAMEBUFFER).unwrap();
         framebuffer.bind();
-        framebuffer.attach_texture2d(&sampler_buffer, gl::DEPTH_ATTACHMENT);
+        framebuffer.attach_texture_layer(&sampler_buffer, gl::DEPTH_ATTACHMENT, 0);
+        Self::depth_only(framebuffer, sampler_buffer, size, layers)
+    }
+
+    // Same as `new_shadowmap`, faces are drawn a face at a time after `bind_cube_face`
+    pub fn new_shadow_cubemap(size: (i32, i32)) -> Self {
+        let sampler_buffer = Texture::new(gl::TEXTURE_CUBE_MAP).unwrap();
+        sampler_buffer.bind();
+        for face in 0..6 {
+            sampler_buffer.cube_face_data(
+                face,
+                size,
+                ptr::null(),
+                gl::FLOAT,
+                gl::DEPTH_COMPONENT,
+                gl::DEPTH_COMPONENT24,
+            );
+        }
+        Self::shadow_parameters(&sampler_buffer);
+
+        let framebuffer = gl_wrappers::Framebuffer::new(gl::FRAMEBUFFER).unwrap();
+        framebuffer.bind();
+        framebuffer.attach_cube_face(&sampler_buffer, gl::DEPTH_ATTACHMENT, 0);
+        Self::depth_only(framebuffer, sampler_buffer, size, 6)
+    }
+
+    fn shadow_parameters(sampler_buffer: &Texture) {
+        // Linear filtering of a comparison gives 2x2 PCF for free
+        sampler_buffer.parameter(gl::TEXTURE_MIN_FILTER, gl::LINEAR);
+        sampler_buffer.parameter(gl::TEXTURE_MAG_FILTER, gl::LINEAR);
+        sampler_buffer.parameter(gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE);
+        sampler_buffer.parameter(gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE);
+        sampler_buffer.parameter(gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE);
+        sampler_buffer.parameter(gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE);
+        sampler_buffer.parameter(gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL);
+    }
+
+    // Expects the framebuffer to be bound with the depth texture attached
+    fn depth_only(
+        framebuffer: gl_wrappers::Framebuffer,
+        sampler_buffer: Texture,
+        size: (i32, i32),
+        layers: i32,
+    ) -> Self {
         unsafe {
             gl::DrawBuffer(gl::NONE);
             gl::ReadBuffer(gl::NONE);
@@ -369,6 +750,7 @@ impl Framebuffer {
             color_buffer: sampler_buffer,
             depth_stencil_buffer: Renderbuffer::new(gl::RENDERBUFFER).unwrap(),
             size,
+            layers,
         }
     }
 
@@ -377,6 +759,18 @@ impl Framebuffer {
         unsafe { gl::Viewport(0, 0, self.size.0, self.size.1) }
     }
 
+    pub fn bind_layer(&self, layer: i32) {
+        self.bind();
+        self.framebuffer
+            .attach_texture_layer(&self.color_buffer, gl::DEPTH_ATTACHMENT, layer);
+    }
+
+    pub fn bind_cube_face(&self, face: u32) {
+        self.bind();
+        self.framebuffer
+            .attach_cube_face(&self.color_buffer, gl::DEPTH_ATTACHMENT, face);
+    }
+
     pub fn bind_default(size: (i32, i32)) {
         gl_wrappers::Framebuffer::bind_default();
         unsafe {
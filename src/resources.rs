@@ -1,56 +1,74 @@
 use crate::{
-    data3d::{Mesh, MeshData, Vertex},
-    gl_wrappers::{Gl, Texture},
-    material::Material,
+    animation::{
+        self, AnimationClip, Bone, Channel, Key, MorphChannel, MorphKeys, Skeleton, SkeletonData,
+        TransformClip, TransformKeys,
+    },
+    asset_loader::{AssetLoader, DecodedImage, ImageTarget, Job, Loaded, PixelType},
+    asset_path::{self, AssetPath},
+    cook::{self, BlobReader, BlobWriter, Manifest},
+    data3d::{self, Bounds, Mesh, MeshData, MorphDelta, MorphTarget, Vertex},
+    entity_system::{Component, EntityRef},
+    gl_wrappers::{self, Gl, Texture},
+    hot_reload,
+    lighting::{self, LightType},
+    linear::Projection,
+    material::{Material, MaterialFactors},
+    procedural,
     scene::Scene,
     scripting::CompiledScript,
-    serializable::{self, PBRTextures, ScriptObject},
-    utils::StbImage,
+    serializable::{
+        self, FilterMode, ImportOptions, MaterialAsset, MaterialTextureSettings, MaterialTextures,
+        PBRTextures, SamplerInfo, ScriptObject, TextureSettings, UpAxis, WrapMode,
+    },
+    vfs::{self, ModelFiles, Vfs},
 };
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHasher64};
 use gl::types::GLenum;
+use glm::Mat4;
 use russimp::{
-    material::{MaterialProperty, PropertyTypeInfo, TextureType},
+    animation::{MeshMorphAnim, NodeAnim, Quaternion},
+    camera::Camera,
+    light::{Light, LightSourceType},
+    material::{DataContent, MaterialProperty, PropertyTypeInfo, TextureType},
+    node::Node,
+    property::PropertyStore,
     scene::{PostProcess, PostProcessSteps},
-    Vector2D,
+    sys::{self, aiMesh, aiVector3D},
+    Color4D, Matrix4x4, RussimpError, Vector2D, Vector3D,
 };
+use serde::{Deserialize, Serialize};
 use std::{
-    fs, iter::Zip, marker::PhantomData, ops::Range, path::{Path, PathBuf}, ptr, slice::Iter, str::FromStr as _
+    f32::consts::FRAC_PI_2,
+    ffi::{CStr, CString},
+    fmt, fs,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    mem,
+    ops::Range,
+    path::Path,
+    ptr,
+    rc::Rc,
+    slice,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
-pub fn get_paths<T>() -> Vec<String>
+pub fn get_paths<T>(vfs: &Vfs) -> Vec<String>
 where
     T: Resource,
 {
-    const ASSETS_DIR: &str = "assets";
-    fs::create_dir_all(ASSETS_DIR).unwrap();
-    let mut path = PathBuf::from_str(ASSETS_DIR).unwrap();
-    path.push(T::folder_name());
-
-    fn search<T>(path: &Path) -> Vec<String>
-    where
-        T: Resource,
-    {
-        let mut result = vec![];
-        let entries = fs::read_dir(path).unwrap();
-
-        for entry in entries {
-            let entry = entry.unwrap();
-
-            if let Some(extension) = entry.path().extension() {
-                if T::acceptable_extensions().contains(&extension.to_str().unwrap().to_string()) {
-                    result.push(entry.path().into_os_string().into_string().unwrap());
-                    continue;
-                }
-            }
-            if entry.file_type().unwrap().is_dir() {
-                result.append(&mut search::<T>(&entry.path()));
-            }
-        }
-        result
-    }
-
-    search::<T>(&path)
+    let dir = AssetPath::new(T::folder_name().to_str().unwrap());
+
+    let acceptable_extensions = T::acceptable_extensions();
+    vfs.list(&dir.resolve())
+        .into_iter()
+        .filter(|path| {
+            Path::new(path)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| acceptable_extensions.contains(&extension.to_string()))
+        })
+        .collect()
 }
 
 pub trait Resource {
@@ -68,19 +86,199 @@ impl Resource for Scene {
     }
 }
 
+impl Resource for MaterialAsset {
+    fn folder_name() -> &'static Path {
+        Path::new("materials")
+    }
+
+    fn acceptable_extensions() -> Vec<String> {
+        vec!["json".to_string()]
+    }
+}
+
+impl ImportOptions {
+    // Steps every model goes through, the options add their own
+    const POSTPROCESS: [PostProcess; 6] = [
+        PostProcess::Triangulate,
+        PostProcess::GenerateSmoothNormals, // only for meshes that come without normals
+        PostProcess::CalculateTangentSpace,
+        PostProcess::OptimizeMeshes,
+        PostProcess::JoinIdenticalVertices,
+        PostProcess::ImproveCacheLocality,
+    ];
+    const MAX_BONE_WEIGHTS: &'static [u8] = b"PP_LBW_MAX_WEIGHTS\0";
+    // Separates the model path from the options hash in the cache key
+    const KEY_SEPARATOR: &'static str = "?";
+
+    pub fn post_process(&self) -> PostProcessSteps {
+        let mut steps = Vec::from(Self::POSTPROCESS);
+        if self.optimize_graph {
+            steps.push(PostProcess::OptimizeGraph);
+        }
+        if self.flip_uvs {
+            steps.push(PostProcess::FlipUVs);
+        }
+        if self.smooth_normals {
+            steps.push(PostProcess::ForceGenerateNormals);
+        }
+        if self.split_large_meshes {
+            steps.push(PostProcess::SplitLargeMeshes);
+        }
+        if self.max_bone_weights.is_some() {
+            steps.push(PostProcess::LimitBoneWeights);
+        }
+        steps
+    }
+
+    fn properties(&self) -> PropertyStore {
+        let mut properties = PropertyStore::default();
+        if let Some(max) = self.max_bone_weights {
+            properties.set_integer(Self::MAX_BONE_WEIGHTS, max as i32);
+        }
+        properties
+    }
+
+    // Cooked models are only taken when they were imported with the same options
+    pub fn salt(&self) -> String {
+        format!("{self:?}")
+    }
+
+    // Name the model is cached under, its path for the default options
+    pub fn model_key(&self, path: &str) -> String {
+        if self.is_default() {
+            return path.to_string();
+        }
+        let mut hasher = FxHasher64::default();
+        self.salt().hash(&mut hasher);
+        format!("{path}{}{:016x}", Self::KEY_SEPARATOR, hasher.finish())
+    }
+
+    pub fn model_path(key: &str) -> &str {
+        key.split(Self::KEY_SEPARATOR).next().unwrap()
+    }
+
+    // Scale and up axis are applied to the vertices and, through `convert`, to node
+    // transforms, assimp's GlobalScale only changes the root node
+    fn position(&self, position: Vector3D) -> Vector3D {
+        let position = self.direction(position);
+        Vector3D {
+            x: position.x * self.scale,
+            y: position.y * self.scale,
+            z: position.z * self.scale,
+        }
+    }
+
+    fn node_transform(&self, matrix: &Matrix4x4) -> Mat4 {
+        #[rustfmt::skip]
+        let matrix = glm::mat4(
+            matrix.a1, matrix.a2, matrix.a3, matrix.a4,
+            matrix.b1, matrix.b2, matrix.b3, matrix.b4,
+            matrix.c1, matrix.c2, matrix.c3, matrix.c4,
+            matrix.d1, matrix.d2, matrix.d3, matrix.d4,
+        );
+        self.convert(&matrix)
+    }
+
+    // Placement of a camera or light relative to its node, turning the entity's z axis to
+    // `z_axis` and its y axis to `up`
+    fn local_frame(&self, position: Vector3D, z_axis: Vector3D, up: Vector3D) -> Mat4 {
+        let vec3 = |vector: Vector3D| glm::vec3(vector.x, vector.y, vector.z);
+        // Point lights come without a direction
+        let z = vec3(z_axis)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or(glm::Vec3::z());
+        let y = [vec3(up), glm::Vec3::y(), glm::Vec3::x()]
+            .into_iter()
+            .find_map(|up| (up - z * up.dot(&z)).try_normalize(1e-4))
+            .unwrap();
+        let rotation = glm::Mat3::from_columns(&[y.cross(&z), y, z]);
+        let matrix = glm::translation(&vec3(position)) * glm::mat3_to_mat4(&rotation);
+        self.convert(&matrix)
+    }
+
+    // Transforms are converted like the vertices they place, the conversion is undone on
+    // the way in and applied again on the way out
+    fn convert(&self, matrix: &Mat4) -> Mat4 {
+        let rotation = match self.up_axis {
+            UpAxis::Y => Mat4::identity(),
+            UpAxis::Z => glm::rotation(-FRAC_PI_2, &glm::Vec3::x_axis()),
+        };
+        let conversion = glm::scaling(&glm::Vec3::from_element(self.scale)) * rotation;
+        conversion * matrix * glm::inverse(&conversion)
+    }
+
+    fn direction(&self, direction: Vector3D) -> Vector3D {
+        match self.up_axis {
+            UpAxis::Y => direction,
+            UpAxis::Z => Vector3D {
+                x: direction.x,
+                y: direction.z,
+                z: -direction.y,
+            },
+        }
+    }
+
+    // Animation keys are converted like node transforms, rotations as x, y, z, w
+    fn rotation(&self, rotation: &Quaternion) -> [f32; 4] {
+        let rotation = glm::quat(rotation.x, rotation.y, rotation.z, rotation.w);
+        let rotation = match self.up_axis {
+            UpAxis::Y => rotation,
+            UpAxis::Z => {
+                let conversion = glm::quat_angle_axis(-FRAC_PI_2, &glm::Vec3::x());
+                conversion * rotation * glm::quat_conjugate(&conversion)
+            }
+        };
+        [rotation.i, rotation.j, rotation.k, rotation.w]
+    }
+
+    // Uniform scale of the model doesn't change the scale of its nodes
+    fn scaling(&self, scaling: Vector3D) -> [f32; 3] {
+        match self.up_axis {
+            UpAxis::Y => [scaling.x, scaling.y, scaling.z],
+            UpAxis::Z => [scaling.x, scaling.z, scaling.y],
+        }
+    }
+}
+
+impl MaterialAsset {
+    const SUFFIX: &'static str = ".material.json";
+
+    // "assets/materials/rock.material.json" is referenced as "rock"
+    pub fn name_from_path(path: &str) -> Option<String> {
+        let file_name = Path::new(path).file_name()?.to_str()?;
+        file_name
+            .strip_suffix(Self::SUFFIX)
+            .map(|name| name.to_string())
+    }
+}
+
 pub type RangeIndex = Range<usize>;
 
-pub struct ResourceContainer<Resource, ResourceIndex: Clone> {
-    // Add resource releasing logic along with free indecies accounting logic
+const ANIMATION_CLIP_SUFFIX: &str = ".anim.json";
+
+// Resources are counted by their users when garbage is collected: `Mesh` components for
+// meshes and materials, materials for textures. Unused and not pinned ones are freed and
+// their slots are reused by later pushes
+pub struct ResourceContainer<Resource, ResourceIndex: Clone + Eq + Hash> {
     table: FxHashMap<String, ResourceIndex>,
-    vec: Vec<Resource>,
+    entries: FxHashMap<ResourceIndex, Entry>,
+    vec: Vec<Option<Resource>>,
+    free: Vec<ResourceIndex>,
 }
 
-impl<Resource, ResourceIndex: Clone> ResourceContainer<Resource, ResourceIndex> {
+pub struct Entry {
+    pub name: String,
+    pub users: usize,
+    pub pinned: bool,
+}
+
+impl<Resource, ResourceIndex: Clone + Eq + Hash> ResourceContainer<Resource, ResourceIndex> {
     pub fn new() -> Self {
         Self {
             table: Default::default(),
+            entries: Default::default(),
             vec: Vec::new(),
+            free: Vec::new(),
         }
     }
 
@@ -92,34 +290,146 @@ impl<Resource, ResourceIndex: Clone> ResourceContainer<Resource, ResourceIndex>
         self.table.contains_key(name)
     }
 
+    pub fn pin(&mut self, idx: &ResourceIndex) {
+        self.entries.get_mut(idx).unwrap().pinned = true;
+    }
+
+    pub fn reset_users(&mut self) {
+        self.entries.values_mut().for_each(|entry| entry.users = 0);
+    }
+
+    pub fn add_user(&mut self, idx: &ResourceIndex) {
+        if let Some(entry) = self.entries.get_mut(idx) {
+            entry.users += 1;
+        }
+    }
+
+    pub fn add_named_user(&mut self, name: &str) {
+        if let Some(idx) = self.table.get(name).cloned() {
+            self.add_user(&idx);
+        }
+    }
+
+    // Used or pinned, so it survives garbage collection
+    pub fn is_kept(&self, name: &str) -> bool {
+        self.table
+            .get(name)
+            .and_then(|idx| self.entries.get(idx))
+            .is_some_and(|entry| entry.users != 0 || entry.pinned)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&ResourceIndex, &Entry)> {
+        self.entries.iter()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Resource> {
+        self.vec.iter().flatten()
+    }
+
+    // Removes unused resources from the table, the caller frees their slots
+    fn take_garbage(&mut self) -> Vec<ResourceIndex> {
+        let garbage = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.users == 0 && !entry.pinned)
+            .map(|(idx, _)| idx.clone())
+            .collect::<Vec<ResourceIndex>>();
+        for idx in &garbage {
+            let entry = self.entries.remove(idx).unwrap();
+            _ = self.table.remove(&entry.name);
+        }
+        garbage
+    }
+
     pub fn unload_all(&mut self) {
         self.table.clear();
+        self.entries.clear();
         self.vec.clear();
+        self.free.clear();
     }
 }
 
 pub type RangeIndexContainer<Resource> = ResourceContainer<Resource, RangeIndex>;
 
 impl<Resource> RangeIndexContainer<Resource> {
-    pub fn push(&mut self, name: &str, mut resource: Vec<Resource>) -> RangeIndex {
+    pub fn push(&mut self, name: &str, resource: Vec<Resource>) -> RangeIndex {
         assert!(
             !self.table.contains_key(name),
             "Container already has this resource"
         );
-        let idx = Range {
-            start: self.vec.len(),
-            end: self.vec.len() + resource.len(),
+        let len = resource.len();
+        let idx = match self.free.iter().position(|range| range.len() >= len && len != 0) {
+            Some(i) => {
+                let range = self.free.swap_remove(i);
+                if range.len() > len {
+                    self.free.push(range.start + len..range.end);
+                }
+                range.start..range.start + len
+            }
+            None => {
+                let start = self.vec.len();
+                self.vec.resize_with(start + len, || None);
+                start..start + len
+            }
         };
+        for (slot, item) in self.vec[idx.clone()].iter_mut().zip(resource) {
+            *slot = Some(item);
+        }
+
         _ = self.table.insert(name.to_string(), idx.clone());
-        self.vec.append(&mut resource);
+        // Empty ranges don't own anything, so they are never collected
+        if len != 0 {
+            let entry = Entry {
+                name: name.to_string(),
+                users: 0,
+                pinned: false,
+            };
+            _ = self.entries.insert(idx.clone(), entry);
+        }
         idx
     }
 
-    pub fn get(&self, idx: &RangeIndex) -> &[Resource] {
-        &self.vec[idx.start..idx.end]
+    pub fn get(&self, idx: &RangeIndex) -> impl Iterator<Item = &Resource> + Clone {
+        self.vec[idx.start..idx.end]
+            .iter()
+            .map(|item| item.as_ref().expect("Resource was unloaded"))
+    }
+
+    pub fn get_mut(&mut self, idx: &RangeIndex) -> impl Iterator<Item = &mut Resource> {
+        self.vec[idx.start..idx.end]
+            .iter_mut()
+            .map(|item| item.as_mut().expect("Resource was unloaded"))
+    }
+
+    // Components of a model spawned as a hierarchy hold parts of its range
+    pub fn add_part_user(&mut self, part: &RangeIndex) {
+        if part.is_empty() {
+            return;
+        }
+        let owner = self
+            .entries
+            .iter_mut()
+            .find(|(idx, _)| contains_part(idx, part));
+        if let Some((_, entry)) = owner {
+            entry.users += 1;
+        }
+    }
+
+    // Returns freed ranges
+    pub fn collect_garbage(&mut self) -> Vec<RangeIndex> {
+        let garbage = self.take_garbage();
+        for idx in &garbage {
+            self.vec[idx.clone()].iter_mut().for_each(|item| *item = None);
+            self.free.push(idx.clone());
+        }
+        garbage
     }
 }
 
+fn contains_part(range: &RangeIndex, part: &RangeIndex) -> bool {
+    !part.is_empty() && range.start <= part.start && part.end <= range.end
+}
+
 pub type SingleIndexContainer<Resource> = ResourceContainer<Resource, usize>;
 
 impl<Resource> SingleIndexContainer<Resource> {
@@ -128,14 +438,38 @@ impl<Resource> SingleIndexContainer<Resource> {
             !self.table.contains_key(name),
             "Container already has this resource"
         );
-        let idx = self.vec.len();
+        let idx = match self.free.pop() {
+            Some(idx) => {
+                self.vec[idx] = Some(resource);
+                idx
+            }
+            None => {
+                self.vec.push(Some(resource));
+                self.vec.len() - 1
+            }
+        };
         _ = self.table.insert(name.to_string(), idx);
-        self.vec.push(resource);
+        let entry = Entry {
+            name: name.to_string(),
+            users: 0,
+            pinned: false,
+        };
+        _ = self.entries.insert(idx, entry);
         idx
     }
 
     pub fn get(&self, idx: usize) -> &Resource {
-        &self.vec[idx]
+        self.vec[idx].as_ref().expect("Resource was unloaded")
+    }
+
+    // Returns freed indices
+    pub fn collect_garbage(&mut self) -> Vec<usize> {
+        let garbage = self.take_garbage();
+        for idx in &garbage {
+            self.vec[*idx] = None;
+            self.free.push(*idx);
+        }
+        garbage
     }
 }
 
@@ -143,22 +477,49 @@ pub struct ResourceManager<'a> {
     pd: PhantomData<&'a ()>,
     mesh_manager: MeshManager,
     scripts: FxHashMap<String, CompiledScript>,
+    vfs: Arc<Vfs>,
+    cooked: Arc<Manifest>,
     scenes: Vec<Scene>,
+    material_asset_paths: FxHashMap<String, String>,
+    material_assets: FxHashMap<String, MaterialAsset>,
+    pending_meshes: Vec<(EntityRef, serializable::Mesh)>,
+    animation_clips: FxHashMap<String, Vec<Rc<TransformClip>>>, // by clip path or model key
 }
 
 impl<'a> ResourceManager<'a> {
     pub fn new(_: &'a Gl) -> Self {
+        let vfs = Arc::new(Vfs::mount());
+        let cooked = Arc::new(Manifest::load(&vfs));
+
+        let mut material_asset_paths = FxHashMap::default();
+        for path in get_paths::<MaterialAsset>(&vfs) {
+            if let Some(name) = MaterialAsset::name_from_path(&path) {
+                let previous = material_asset_paths.insert(name, path);
+                assert!(previous.is_none(), "Material asset names must be unique");
+            }
+        }
+
         Self {
             pd: PhantomData::default(),
-            mesh_manager: MeshManager::new(),
+            mesh_manager: MeshManager::new(vfs.clone(), cooked.clone()),
             scripts: Default::default(),
-            scenes: get_paths::<Scene>()
+            scenes: get_paths::<Scene>(&vfs)
                 .iter()
                 .map(|path| Scene::new(path))
                 .collect(),
+            material_asset_paths,
+            material_assets: Default::default(),
+            pending_meshes: Default::default(),
+            animation_clips: Default::default(),
+            vfs,
+            cooked,
         }
     }
 
+    pub fn vfs(&self) -> &Vfs {
+        &self.vfs
+    }
+
     pub fn mesh_manager(&self) -> &MeshManager {
         &self.mesh_manager
     }
@@ -171,176 +532,791 @@ impl<'a> ResourceManager<'a> {
         &self.scenes
     }
 
-    pub fn get_script(&self, script: &ScriptObject) -> String {
-        // will be replaced later with some binary storing logic
-        fs::read_to_string(&script.script_path).unwrap()
+    // `None` for unknown names and broken files, which are reported
+    pub fn get_material_asset(&mut self, name: &str) -> Option<&MaterialAsset> {
+        if !self.material_assets.contains_key(name) {
+            match self.read_material_asset(name) {
+                Ok(asset) => _ = self.material_assets.insert(name.to_string(), asset),
+                Err(err) => {
+                    println!("Failed to load material asset {name}: {err}");
+                    return None;
+                }
+            }
+        }
+        self.material_assets.get(name)
     }
-}
 
-pub struct MeshManager {
-    meshes: RangeIndexContainer<MeshData>,
-    materials: RangeIndexContainer<Material>,
-    textures: SingleIndexContainer<Texture>,
-}
+    // Materials made from the asset are rewritten in place, meshes keep using them
+    pub fn reload_material_asset(&mut self, path: &str) {
+        let name = match MaterialAsset::name_from_path(path) {
+            Some(name) => name,
+            None => return,
+        };
+        _ = self
+            .material_asset_paths
+            .entry(name.clone())
+            .or_insert_with(|| path.to_string());
+        _ = self.material_assets.remove(&name);
+        if self.get_material_asset(&name).is_some() {
+            let asset = &self.material_assets[&name];
+            let path = &self.material_asset_paths[&name];
+            self.mesh_manager.reload_material(path, asset);
+        }
+    }
 
-impl MeshManager {
-    const DEFAULT_POSTPROCESS: [PostProcess; 5] = [
-        PostProcess::Triangulate,
-        PostProcess::OptimizeMeshes,
-        PostProcess::OptimizeGraph,
-        PostProcess::JoinIdenticalVertices,
-        PostProcess::ImproveCacheLocality,
-    ];
+    fn read_material_asset(&self, name: &str) -> Result<MaterialAsset, String> {
+        let path = self
+            .material_asset_paths
+            .get(name)
+            .ok_or_else(|| "unknown name".to_string())?;
+        let json_str = self
+            .vfs
+            .read_to_string(path)
+            .map_err(|err| err.to_string())?;
+        serde_json::from_str::<MaterialAsset>(&json_str).map_err(|err| err.to_string())
+    }
 
-    pub fn new() -> Self {
-        let mut textures = SingleIndexContainer::<Texture>::new();
-        let default_textures = Self::default_textures();
-        textures.push("default_base_color", default_textures.0);
-        textures.push("default_metalness", default_textures.1);
-        textures.push("default_roughness", default_textures.2);
-        textures.push("default_ao", default_textures.3);
-        textures.push("default_normals", default_textures.4);
-        textures.push("default_displacement", default_textures.5);
+    pub fn get_mesh_lazily(&mut self, mesh: &serializable::Mesh) -> Mesh {
+        self.get_mesh_n_asset(mesh).0
+    }
 
-        Self {
-            meshes: RangeIndexContainer::new(),
-            materials: RangeIndexContainer::new(),
-            textures,
+    // Also tells whether a material asset replaced the materials of the model, those are
+    // not loaded then. Models fall back to their own ones when the asset can't be loaded
+    fn get_mesh_n_asset(&mut self, mesh: &serializable::Mesh) -> (Mesh, bool) {
+        let asset = match &mesh.material {
+            Some(name) => self.get_asset_material(name),
+            None => None,
+        };
+        let mut result = self.mesh_manager.get_mesh_lazily(mesh, asset.is_none());
+        let replaced = asset.is_some();
+        if let Some(material_index) = asset {
+            result.material_index = material_index;
         }
+        (result, replaced)
     }
 
-    pub fn mesh_n_material(&self, mesh: &Mesh) -> Zip<Iter<MeshData>, Iter<Material>> {
-        let mesh_data = self.meshes.get(&mesh.mesh_index);
-        let materials = self.materials.get(&mesh.material_index);
-        mesh_data.iter().zip(materials)
+    fn get_asset_material(&mut self, name: &str) -> Option<RangeIndex> {
+        self.get_material_asset(name)?;
+        let asset = &self.material_assets[name];
+        let path = &self.material_asset_paths[name];
+        Some(self.mesh_manager.get_material_lazily(path, asset))
     }
 
-    pub fn meshes(&self) -> &RangeIndexContainer<MeshData> {
-        &self.meshes
+    // Nodes of the model with a `Mesh` for each of their submeshes
+    pub fn get_model_nodes(&mut self, mesh: &serializable::Mesh) -> Vec<(ModelNode, Vec<Mesh>)> {
+        let (whole, replaced) = self.get_mesh_n_asset(mesh);
+        let key = MeshManager::model_key(mesh);
+        let part = |range: &RangeIndex, i: usize| range.start + i..range.start + i + 1;
+        self.mesh_manager
+            .model_nodes(&key)
+            .iter()
+            .map(|node| {
+                let meshes = node
+                    .meshes
+                    .iter()
+                    .map(|i| Mesh {
+                        mesh_index: part(&whole.mesh_index, *i),
+                        // A material asset is a single material shared by every submesh
+                        material_index: match replaced {
+                            true => whole.material_index.clone(),
+                            false => part(&whole.material_index, *i),
+                        },
+                        morph_weights: self
+                            .mesh_manager
+                            .morph_weights(&part(&whole.mesh_index, *i)),
+                    })
+                    .collect();
+                let mut node = node.clone();
+                if !mesh.cameras_and_lights {
+                    node.cameras.clear();
+                    node.light_sources.clear();
+                }
+                (node, meshes)
+            })
+            .collect()
     }
 
-    pub fn materials(&self) -> &RangeIndexContainer<Material> {
-        &self.materials
+    // The `Mesh` component for the entity is handed out by `process_loaded` once the model
+    // has been imported
+    pub fn request_mesh(&mut self, owner: EntityRef, mesh: &serializable::Mesh) {
+        self.mesh_manager.request_mesh(mesh);
+        self.pending_meshes.push((owner, mesh.clone()));
     }
 
-    pub fn textures(&self) -> &SingleIndexContainer<Texture> {
-        &self.textures
+    pub fn cancel_pending_meshes(&mut self) {
+        self.pending_meshes.clear();
+    }
+
+    pub fn process_loaded(&mut self, budget: Duration) -> Vec<(EntityRef, LoadedMesh)> {
+        self.mesh_manager.process_loaded(budget);
+
+        let mut ready = Vec::new();
+        for (owner, mesh) in mem::take(&mut self.pending_meshes) {
+            let key = MeshManager::model_key(&mesh);
+            if self.mesh_manager.meshes().contains(&key) {
+                let loaded = if mesh.hierarchy {
+                    LoadedMesh::Nodes(self.get_model_nodes(&mesh))
+                } else {
+                    LoadedMesh::Mesh(self.get_mesh_lazily(&mesh))
+                };
+                ready.push((owner, loaded));
+                if let Some(skeleton) = self.mesh_manager.model_skeleton(&key) {
+                    ready.push((owner, LoadedMesh::Skeleton(Skeleton::new(skeleton))));
+                }
+            } else if self.mesh_manager.is_loading(&key) {
+                self.pending_meshes.push((owner, mesh));
+            }
+            // otherwise the import failed and the request is dropped
+        }
+        ready
     }
 
-    fn default_textures() -> (Texture, Texture, Texture, Texture, Texture, Texture) {
-        let size = (1, 1);
+    // (finished, total) loading jobs
+    pub fn loading_progress(&self) -> (usize, usize) {
+        self.mesh_manager.loading_progress()
+    }
 
-        let data = (128u8, 128u8, 128u8);
-        let base_color = Self::create_dafault_texture(ptr::from_ref(&data.0), size, gl::RGB);
+    pub fn collect_garbage(&mut self, meshes: &[Component<Mesh>]) {
+        self.mesh_manager.collect_garbage(meshes);
+    }
 
-        let data = 0u8;
-        let metalness = Self::create_dafault_texture(ptr::from_ref(&data), size, gl::RED);
+    pub fn memory_report(&mut self, meshes: &[Component<Mesh>]) -> MemoryReport {
+        self.mesh_manager.memory_report(meshes)
+    }
 
-        let data = 128u8;
-        let roughness = Self::create_dafault_texture(ptr::from_ref(&data), size, gl::RED);
+    // Where the clips of each path come from: the ".anim.json" file itself, or the key of
+    // the model as the mesh in `meshes` with that path imports it
+    pub fn clip_sources(&self, paths: &[AssetPath], meshes: &[serializable::Mesh]) -> Vec<String> {
+        let mut sources = Vec::with_capacity(paths.len());
+        for path in paths {
+            let resolved = path.resolve();
+            if Self::clip_file_name(&resolved).is_some() {
+                sources.push(resolved);
+                continue;
+            }
+            match meshes.iter().find(|mesh| mesh.path == *path) {
+                Some(mesh) => sources.push(MeshManager::model_key(mesh)),
+                None => println!("Clips of {path} need a mesh of the model on the same entity"),
+            }
+        }
+        sources
+    }
 
-        let data = 255u8;
-        let ao = Self::create_dafault_texture(ptr::from_ref(&data), size, gl::RED);
+    // Clips are shared between the players that use the same sources. None while a model
+    // they come from is still loading
+    pub fn get_animation_clips(&mut self, sources: &[String]) -> Option<Vec<Rc<TransformClip>>> {
+        let mut result = Vec::new();
+        for source in sources {
+            if !self.animation_clips.contains_key(source) {
+                let clips = match Self::clip_file_name(source) {
+                    Some(name) => self.load_clip_file(source, name),
+                    None if self.mesh_manager.is_loading(source) => return None,
+                    None => match self.mesh_manager.model_clips(source) {
+                        Some(clips) => clips,
+                        None => {
+                            println!("Failed to load animation clips of {source}");
+                            continue;
+                        }
+                    },
+                };
+                _ = self.animation_clips.insert(source.clone(), clips);
+            }
+            result.extend(self.animation_clips[source].iter().cloned());
+        }
+        Some(result)
+    }
 
-        let data = (0u8, 0u8, 255u8); // Set proper value
-        let normals = Self::create_dafault_texture(ptr::from_ref(&data.0), size, gl::RGB);
+    fn clip_file_name(path: &str) -> Option<&str> {
+        Path::new(path)
+            .file_name()
+            .and_then(|item| item.to_str())
+            .and_then(|item| item.strip_suffix(ANIMATION_CLIP_SUFFIX))
+    }
 
-        let data = 255u8; // Set proper value
-        let displacement = Self::create_dafault_texture(ptr::from_ref(&data), size, gl::RED);
+    fn load_clip_file(&self, path: &str, name: &str) -> Vec<Rc<TransformClip>> {
+        let clip = self
+            .vfs
+            .read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|json_str| {
+                serde_json::from_str::<serializable::AnimationClip>(&json_str)
+                    .map_err(|err| err.to_string())
+            });
+        match clip {
+            Ok(mut clip) => {
+                if clip.name.is_empty() {
+                    clip.name = name.to_string();
+                }
+                vec![Rc::new(clip.into())]
+            }
+            Err(err) => {
+                println!("Failed to load animation clip {path}: {err}");
+                vec![]
+            }
+        }
+    }
 
-        (base_color, metalness, roughness, ao, normals, displacement)
+    // Cooked bytecode, unless the script has been edited since it was cooked
+    pub fn get_script(&self, script: &ScriptObject) -> Vec<u8> {
+        let path = script.script_path.resolve();
+        self.cooked
+            .cooked(&self.vfs, &path, "")
+            .unwrap_or_else(|| self.vfs.read(&path).unwrap())
     }
+}
 
-    fn create_dafault_texture(data: *const u8, size: (i32, i32), format: GLenum) -> Texture {
-        let tex = Texture::new(gl::TEXTURE_2D).unwrap();
-        tex.bind();
-        tex.texture_data(size, data.cast(), gl::UNSIGNED_BYTE, format, format);
-        tex.parameter(gl::TEXTURE_WRAP_S, gl::REPEAT);
-        tex.parameter(gl::TEXTURE_WRAP_T, gl::REPEAT);
-        tex.parameter(gl::TEXTURE_MIN_FILTER, gl::NEAREST);
-        tex.parameter(gl::TEXTURE_MAG_FILTER, gl::NEAREST);
-        tex
+pub enum LoadedMesh {
+    Mesh(Mesh),
+    Nodes(Vec<(ModelNode, Vec<Mesh>)>), // spawned as children of the entity
+    Skeleton(Skeleton),                 // comes along with a skinned model
+}
+
+pub struct MemoryReport {
+    pub items: Vec<MemoryReportItem>,
+}
+
+pub struct MemoryReportItem {
+    pub kind: &'static str,
+    pub name: String,
+    pub size: usize, // bytes, GPU memory for meshes and textures
+    pub users: Vec<String>,
+    pub pinned: bool,
+}
+
+impl MemoryReport {
+    pub fn total_size(&self) -> usize {
+        self.items.iter().map(|item| item.size).sum()
     }
+}
 
-    pub fn get_mesh_lazily(&mut self, mesh: &serializable::Mesh) -> Mesh {
-        if !self.meshes.contains(&mesh.path) {
-            self.load_mesh(&mesh, Self::DEFAULT_POSTPROCESS.into());
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in &self.items {
+            let users = if item.pinned {
+                "pinned".to_string()
+            } else if item.users.is_empty() {
+                "unused".to_string()
+            } else {
+                item.users.join(", ")
+            };
+            writeln!(
+                f,
+                "{:<8} {:>10.1} KiB  {}  [{}]",
+                item.kind,
+                item.size as f64 / 1024.0,
+                item.name,
+                users
+            )?;
+        }
+        write!(f, "total    {:>10.1} KiB", self.total_size() as f64 / 1024.0)
+    }
+}
+
+// Separates the model path from the name of a texture stored inside the model
+const EMBEDDED_SEPARATOR: &str = "::";
+// Keys of meshes created at runtime, kept apart from model paths
+const DYNAMIC_SCHEME: &str = "dynamic://";
+
+// From EXT_texture_filter_anisotropic, core since 4.6 which the bindings are older than
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+// What assimp assumes for formats that don't store it
+const DEFAULT_TICKS_PER_SECOND: f64 = 25.0;
+// Vertex color of meshes that don't have any
+const WHITE: Color4D = Color4D {
+    r: 1.0,
+    g: 1.0,
+    b: 1.0,
+    a: 1.0,
+};
+
+// Geometry and material texture paths of a model file, prepared off the main thread
+pub struct ImportedModel {
+    submeshes: Vec<(Vec<Vertex>, Vec<u32>)>,
+    materials: Vec<MaterialTextures>, // one per submesh
+    embedded: FxHashMap<String, Vec<u8>>, // compressed images stored inside the file, by texture path
+    nodes: Vec<ModelNode>,
+    bones: Vec<Bone>,
+    clips: Vec<AnimationClip>,
+    morph_targets: Vec<Vec<MorphTarget>>, // one list per submesh
+}
+
+// Node of the model's graph, used when the model is spawned as a hierarchy of entities
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ModelNode {
+    pub name: String,
+    pub parent: Option<usize>, // nodes come after their parent
+    pub transform: [f32; 16],  // column-major, relative to the parent
+    pub meshes: Vec<usize>,    // submeshes of the model
+    pub cameras: Vec<serializable::Camera>,
+    pub light_sources: Vec<serializable::LightSource>,
+}
+
+impl ModelNode {
+    fn new(name: &str, parent: Option<usize>, transform: &Mat4) -> Self {
+        Self {
+            name: name.to_string(),
+            parent,
+            transform: transform.as_slice().try_into().unwrap(),
+            meshes: Vec::new(),
+            cameras: Vec::new(),
+            light_sources: Vec::new(),
         }
-        let mesh_index = self.meshes.get_index(&mesh.path);
-        let material_index = self.materials.get_index(&mesh.path);
+    }
+}
 
-        Mesh {
-            mesh_index,
-            material_index,
+impl ImportedModel {
+    const COOKED_MAGIC: &'static [u8; 4] = b"LGMS";
+
+    // Takes the cooked model when it is up to date, imports the file otherwise
+    pub fn load(
+        path: &str,
+        options: &ImportOptions,
+        vfs: &Vfs,
+        cooked: &Manifest,
+    ) -> Result<Self, String> {
+        // Generated meshes are cheaper to make than to cook, import options don't apply
+        if procedural::is_builtin(path) {
+            return procedural::generate(path).map(|geometry| Self::generated(path, geometry));
+        }
+        if let Some(model) = cooked
+            .cooked(vfs, path, &options.salt())
+            .and_then(|blob| Self::from_cooked(&blob))
+        {
+            return Ok(model);
+        }
+        Self::read(path, options, vfs).map_err(|error| error.to_string())
+    }
+
+    // A single submesh with an untextured material
+    fn generated(name: &str, geometry: (Vec<Vertex>, Vec<u32>)) -> Self {
+        let mut root = ModelNode::new(name, None, &Mat4::identity());
+        root.meshes.push(0);
+        Self {
+            submeshes: vec![geometry],
+            materials: vec![MaterialTextures::default()],
+            embedded: Default::default(),
+            nodes: vec![root],
+            bones: vec![],
+            clips: vec![],
+            morph_targets: vec![vec![]],
         }
     }
 
-    fn load_mesh(&mut self, mesh: &serializable::Mesh, post_process: PostProcessSteps) {
-        let scene = russimp::scene::Scene::from_file(&mesh.path, post_process).unwrap();
+    pub fn read(path: &str, options: &ImportOptions, vfs: &Vfs) -> Result<Self, RussimpError> {
+        let scene = russimp::scene::Scene::from_file_system_with_props(
+            path,
+            options.post_process(),
+            &mut ModelFiles(vfs),
+            &options.properties(),
+        )?;
 
-        let mut submeshes_data = Vec::with_capacity(scene.meshes.len());
+        let mut submeshes = Vec::with_capacity(scene.meshes.len());
         let mut material_indecies = Vec::with_capacity(scene.meshes.len());
-        let mut vertex_data = Vec::<Vertex>::new();
-        let mut index_data = Vec::<u32>::new();
+        let mut bones = Vec::<Bone>::new(); // shared by the submeshes
+        let mut missing_bones = Vec::<String>::new();
+
+        // Bones refer to the nodes
+        let mut nodes = Vec::new();
+        if let Some(root) = &scene.root {
+            Self::read_nodes(root, None, &scene, options, &mut nodes);
+        }
 
         for submesh in &scene.meshes {
             // processing submeshes
-            for i in 0..submesh.vertices.len() {
-                let position = submesh.vertices[i];
-                let normal = submesh.normals[i];
-                let tex_coord =
-                    submesh.texture_coords[0]
-                        .as_ref()
-                        .map_or(Vector2D::default(), |coords| Vector2D {
-                            x: coords[i].x,
-                            y: coords[i].y,
-                        });
+            let mut vertex_data = Vec::<Vertex>::with_capacity(submesh.vertices.len());
+            let mut index_data = Vec::<u32>::new();
+
+            let tex_coords = |set: usize, i: usize| {
+                submesh
+                    .texture_coords
+                    .get(set)
+                    .and_then(|coords| coords.as_ref())
+                    .map_or(Vector2D::default(), |coords| Vector2D {
+                        x: coords[i].x,
+                        y: coords[i].y,
+                    })
+            };
+            // Points and lines are left without normals even by GenSmoothNormals, tangents
+            // are missing without texture coordinates
+            let or_default = |attribute: &Vec<Vector3D>, i: usize| {
+                attribute.get(i).copied().unwrap_or_default()
+            };
+            let colors = submesh.colors.first().and_then(|colors| colors.as_ref());
 
+            for i in 0..submesh.vertices.len() {
                 let vertex = Vertex {
-                    position,
-                    normal,
-                    tex_coord,
+                    position: options.position(submesh.vertices[i]),
+                    normal: options.direction(or_default(&submesh.normals, i)),
+                    tex_coord: tex_coords(0, i),
+                    tangent: options.direction(or_default(&submesh.tangents, i)),
+                    bitangent: options.direction(or_default(&submesh.bitangents, i)),
+                    color: colors.map_or(WHITE, |colors| colors[i]),
+                    tex_coord2: tex_coords(1, i),
+                    bone_indices: [0.0; 4],
+                    bone_weights: [0.0; 4],
                 };
                 vertex_data.push(vertex);
             }
 
+            for bone in &submesh.bones {
+                // Its vertices are left to their other bones, or in place
+                let Some(node) = Self::find_node(&nodes, &bone.name) else {
+                    if !missing_bones.contains(&bone.name) {
+                        println!("{path} has no node for bone {}, it is skipped", bone.name);
+                        missing_bones.push(bone.name.clone());
+                    }
+                    continue;
+                };
+                let index = match bones.iter().position(|other| other.node == node) {
+                    Some(index) => index,
+                    None => {
+                        let offset = options.node_transform(&bone.offset_matrix);
+                        bones.push(Bone {
+                            node,
+                            offset: offset.as_slice().try_into().unwrap(),
+                        });
+                        bones.len() - 1
+                    }
+                };
+                if index >= animation::MAX_BONES {
+                    continue;
+                }
+                for weight in &bone.weights {
+                    vertex_data[weight.vertex_id as usize].add_bone_weight(index, weight.weight);
+                }
+            }
+            if !submesh.bones.is_empty() {
+                vertex_data
+                    .iter_mut()
+                    .for_each(|vertex| vertex.normalize_bone_weights());
+            }
+
             for face in &submesh.faces {
                 for index in &face.0 {
                     index_data.push(*index);
                 }
             }
 
-            let submesh_data =
-                MeshData::from_vertex_index_data(&vertex_data, &index_data, gl::STATIC_DRAW);
-            submeshes_data.push(submesh_data);
+            submeshes.push((vertex_data, index_data));
             material_indecies.push(submesh.material_index);
-
-            vertex_data.clear();
-            index_data.clear();
         }
 
-        _ = self.meshes.push(&mesh.path, submeshes_data);
+        let mut embedded = FxHashMap::default();
+        let materials = Self::read_materials(&scene, &material_indecies, path, &mut embedded);
 
-        self.load_material(&scene, &material_indecies, &mesh.path, &mesh.material_info);
+        if bones.len() > animation::MAX_BONES {
+            println!(
+                "{path} has {} bones, vertices weighted to the ones past {} are left in place",
+                bones.len(),
+                animation::MAX_BONES
+            );
+        }
+        let mut morph_targets = Vec::new();
+        if scene.meshes.iter().any(|mesh| !mesh.anim_meshes.is_empty()) {
+            morph_targets = Self::read_morph_targets(path, options, vfs).unwrap_or_else(|error| {
+                println!("Failed to read blend shapes of {path}: {error}");
+                Vec::new()
+            });
+        }
+        morph_targets.resize_with(submeshes.len(), Vec::new);
+        let clips = Self::read_clips(&scene, &nodes, &morph_targets, options);
+
+        Ok(Self {
+            submeshes,
+            materials,
+            embedded,
+            nodes,
+            bones,
+            clips,
+            morph_targets,
+        })
+    }
 
-        // self.get_material_lazily(material, scene_path, &scene, &material_indecies);
+    // russimp keeps nothing of the blend shapes but their bitangents, so they are read
+    // through the assimp C API in a second import that only models having them go through
+    fn read_morph_targets(
+        path: &str,
+        options: &ImportOptions,
+        vfs: &Vfs,
+    ) -> Result<Vec<Vec<MorphTarget>>, String> {
+        let files = ModelFiles(vfs);
+        let mut file_io = files.ai_file_io();
+        let c_path = CString::new(path).map_err(|error| error.to_string())?;
+        let flags = options
+            .post_process()
+            .into_iter()
+            .fold(0, |flags, step| flags | step as u32);
+        unsafe {
+            let properties = sys::aiCreatePropertyStore();
+            if let Some(max) = options.max_bone_weights {
+                let name = ImportOptions::MAX_BONE_WEIGHTS.as_ptr().cast();
+                sys::aiSetImportPropertyInteger(properties, name, max as i32);
+            }
+            let scene =
+                sys::aiImportFileExWithProperties(c_path.as_ptr(), flags, &mut file_io, properties);
+            sys::aiReleasePropertyStore(properties);
+            let Some(scene) = scene.as_ref() else {
+                let error = CStr::from_ptr(sys::aiGetErrorString());
+                return Err(error.to_string_lossy().into_owned());
+            };
+            let targets = raw_slice(scene.mMeshes, scene.mNumMeshes)
+                .iter()
+                .map(|mesh| Self::read_mesh_morph_targets(&**mesh, path, options))
+                .collect();
+            sys::aiReleaseImport(scene);
+            Ok(targets)
+        }
+    }
 
-        // _ = self.get_material_lazily(&mesh.material, &mesh.path, &scene, &material_indecies);
+    // Assimp gives every shape whole, only the offsets from the mesh are kept. Shapes keep
+    // their indices even when left empty, animation keys refer to them by it
+    unsafe fn read_mesh_morph_targets(
+        mesh: &aiMesh,
+        path: &str,
+        options: &ImportOptions,
+    ) -> Vec<MorphTarget> {
+        let shapes = raw_slice(mesh.mAnimMeshes, mesh.mNumAnimMeshes);
+        if shapes.len() > data3d::MAX_MORPH_TARGETS {
+            println!(
+                "{path} has a mesh with {} blend shapes, the ones past {} are left out",
+                shapes.len(),
+                data3d::MAX_MORPH_TARGETS
+            );
+        }
+        let vertices = raw_slice(mesh.mVertices, mesh.mNumVertices);
+        let normals = raw_slice(mesh.mNormals, mesh.mNumVertices);
+        let offset = |base: Vector3D, shape: Vector3D| {
+            [shape.x - base.x, shape.y - base.y, shape.z - base.z, 0.0]
+        };
+        let position = |vector: &aiVector3D| options.position(vector.into());
+        let direction = |vector: &aiVector3D| options.direction(vector.into());
+
+        let mut targets = Vec::with_capacity(shapes.len());
+        for (i, shape) in shapes.iter().take(data3d::MAX_MORPH_TARGETS).enumerate() {
+            let shape = &**shape;
+            let name = match String::from(&shape.mName) {
+                name if name.is_empty() => i.to_string(),
+                name => name,
+            };
+            let mut deltas = vec![MorphDelta::default(); vertices.len()];
+            if shape.mNumVertices == mesh.mNumVertices {
+                let pairs = vertices
+                    .iter()
+                    .zip(raw_slice(shape.mVertices, shape.mNumVertices));
+                for (delta, (base, morphed)) in deltas.iter_mut().zip(pairs) {
+                    delta.position = offset(position(base), position(morphed));
+                }
+                let pairs = normals
+                    .iter()
+                    .zip(raw_slice(shape.mNormals, shape.mNumVertices));
+                for (delta, (base, morphed)) in deltas.iter_mut().zip(pairs) {
+                    delta.normal = offset(direction(base), direction(morphed));
+                }
+            } else {
+                println!("{path}: blend shape {name} doesn't match its mesh and is left out");
+            }
+            targets.push(MorphTarget { name, deltas });
+        }
+        targets
     }
 
-    fn load_material(
-        &mut self,
-        scene: &russimp::scene::Scene,
-        material_indecies: &Vec<u32>,
-        mesh_path: &str,
-        material_info: &serializable::MaterialInfo,
-    ) {
-        let mut material_items = Vec::with_capacity(material_indecies.len());
-        let mut tex_files = Vec::new();
+    // Bones and animation channels refer to nodes by their names. Nodes added for cameras
+    // and lights share the name of the node they are under, which comes first
+    fn find_node(nodes: &[ModelNode], name: &str) -> Option<usize> {
+        nodes.iter().position(|node| node.name == name)
+    }
 
-        for index in material_indecies {
-            // TODO: embeded textures loading
+    fn read_clips(
+        scene: &russimp::scene::Scene,
+        nodes: &[ModelNode],
+        morph_targets: &[Vec<MorphTarget>],
+        options: &ImportOptions,
+    ) -> Vec<AnimationClip> {
+        let mut clips = Vec::with_capacity(scene.animations.len());
+        let find_node = |name: &str, clip: &str| {
+            let node = Self::find_node(nodes, name);
+            if node.is_none() {
+                println!("Clip {clip} animates a missing node {name}, the channel is skipped");
+            }
+            node
+        };
+        for animation in &scene.animations {
+            let ticks_per_second = match animation.ticks_per_second {
+                ticks if ticks > 0.0 => ticks,
+                _ => DEFAULT_TICKS_PER_SECOND,
+            };
+            let channels = animation
+                .channels
+                .iter()
+                .filter_map(|channel| {
+                    Some(Channel {
+                        node: find_node(&channel.name, &animation.name)?,
+                        keys: Self::read_keys(channel, ticks_per_second, options),
+                    })
+                })
+                .collect();
+            // Weights are for the shapes of the node's mesh
+            let morph_channels = animation
+                .morph_mesh_channels
+                .iter()
+                .filter_map(|channel| {
+                    let node = find_node(&channel.name, &animation.name)?;
+                    let targets = &morph_targets[*nodes[node].meshes.first()?];
+                    Some(MorphChannel {
+                        node,
+                        keys: Self::read_morph_keys(channel, targets, ticks_per_second),
+                    })
+                })
+                .collect();
+            clips.push(AnimationClip {
+                name: animation.name.clone(),
+                duration: (animation.duration / ticks_per_second) as f32,
+                channels,
+                morph_channels,
+            });
+        }
+        clips
+    }
+
+    fn read_keys(
+        channel: &NodeAnim,
+        ticks_per_second: f64,
+        options: &ImportOptions,
+    ) -> TransformKeys {
+        let seconds = |ticks: f64| (ticks / ticks_per_second) as f32;
+        TransformKeys {
+            positions: channel
+                .position_keys
+                .iter()
+                .map(|key| {
+                    let value = options.position(key.value);
+                    Key {
+                        time: seconds(key.time),
+                        value: [value.x, value.y, value.z],
+                    }
+                })
+                .collect(),
+            rotations: channel
+                .rotation_keys
+                .iter()
+                .map(|key| Key {
+                    time: seconds(key.time),
+                    value: options.rotation(&key.value),
+                })
+                .collect(),
+            scales: channel
+                .scaling_keys
+                .iter()
+                .map(|key| Key {
+                    time: seconds(key.time),
+                    value: options.scaling(key.value),
+                })
+                .collect(),
+        }
+    }
 
+    // A key lists the weights of some of the shapes, the others are zero
+    fn read_morph_keys(
+        channel: &MeshMorphAnim,
+        targets: &[MorphTarget],
+        ticks_per_second: f64,
+    ) -> MorphKeys {
+        let weights = channel
+            .keys
+            .iter()
+            .map(|key| {
+                let mut weights = vec![0.0; targets.len()];
+                for (target, weight) in key.values.iter().zip(&key.weights) {
+                    if let Some(value) = weights.get_mut(*target as usize) {
+                        *value = *weight as f32;
+                    }
+                }
+                Key {
+                    time: (key.time / ticks_per_second) as f32,
+                    value: weights,
+                }
+            })
+            .collect();
+        MorphKeys {
+            targets: targets.iter().map(|target| target.name.clone()).collect(),
+            weights,
+        }
+    }
+
+    fn read_nodes(
+        node: &Node,
+        parent: Option<usize>,
+        scene: &russimp::scene::Scene,
+        options: &ImportOptions,
+        nodes: &mut Vec<ModelNode>,
+    ) {
+        let index = nodes.len();
+        let transform = options.node_transform(&node.transformation);
+        let mut model_node = ModelNode::new(&node.name, parent, &transform);
+        model_node.meshes = node.meshes.iter().map(|mesh| *mesh as usize).collect();
+
+        let mut attached = Self::read_attached(node, index, scene, options);
+        let children = node.children.borrow();
+        // Usually the node holds nothing but the camera or light, then it takes its placement
+        if node.meshes.is_empty() && children.is_empty() && attached.len() == 1 {
+            let attached = attached.pop().unwrap();
+            let transform = transform * glm::make_mat4(&attached.transform);
+            model_node.transform = transform.as_slice().try_into().unwrap();
+            model_node.cameras = attached.cameras;
+            model_node.light_sources = attached.light_sources;
+        }
+        nodes.push(model_node);
+        nodes.extend(attached);
+
+        for child in children.iter() {
+            Self::read_nodes(child, Some(index), scene, options, nodes);
+        }
+    }
+
+    // Cameras and lights assimp ties to the node by its name, each placed relative to it as
+    // a node of its own
+    fn read_attached(
+        node: &Node,
+        index: usize,
+        scene: &russimp::scene::Scene,
+        options: &ImportOptions,
+    ) -> Vec<ModelNode> {
+        let mut attached = Vec::new();
+        for camera in scene.cameras.iter().filter(|item| item.name == node.name) {
+            // The camera looks along its -z axis
+            let z_axis = Vector3D {
+                x: -camera.look_at.x,
+                y: -camera.look_at.y,
+                z: -camera.look_at.z,
+            };
+            let transform = options.local_frame(camera.position, z_axis, camera.up);
+            let mut model_node = ModelNode::new(&node.name, Some(index), &transform);
+            model_node.cameras.push(import_camera(camera));
+            attached.push(model_node);
+        }
+        for light in scene.lights.iter().filter(|item| item.name == node.name) {
+            let Some(light_source) = import_light(light) else {
+                continue;
+            };
+            let transform = options.local_frame(light.pos, light.direction, light.up);
+            let mut model_node = ModelNode::new(&node.name, Some(index), &transform);
+            model_node.light_sources.push(light_source);
+            attached.push(model_node);
+        }
+        attached
+    }
+
+    fn read_materials(
+        scene: &russimp::scene::Scene,
+        material_indecies: &Vec<u32>,
+        mesh_path: &str,
+        embedded: &mut FxHashMap<String, Vec<u8>>,
+    ) -> Vec<MaterialTextures> {
+        let mut material_items = Vec::with_capacity(material_indecies.len());
+        let mut tex_files = Vec::new();
+
+        for index in material_indecies {
             let m = &scene.materials[*index as usize];
 
             tex_files.clear();
@@ -351,170 +1327,988 @@ impl MeshManager {
                 .filter(|item| item.key == "$tex.file")
                 .for_each(|item| tex_files.push(item));
 
-            // for property in &m.properties {
-            //     if property.key == "$tex.file" {
-            //         // "$tex.file" is just how it is marked by Assimp
-            //         tex_files.push(property);
-            //     }
-            // }
+            // russimp resolves "*N" references (glTF, GLB) and file names of images packed
+            // into the file (FBX) to the embedded texture itself
+            let mut find = |semantic: TextureType| {
+                let prop = tex_files.iter().find(|p| p.semantic == semantic)?;
+                match m.textures.get(&semantic) {
+                    Some(texture) => {
+                        Self::read_embedded_texture(&texture.borrow(), prop, mesh_path, embedded)
+                    }
+                    None => Some(Self::get_texture_path(prop, mesh_path)),
+                }
+            };
 
             // Filter out only PBR textures
-            let base_color = tex_files
-                .iter()
-                .find(|p| p.semantic == TextureType::BaseColor)
-                .or_else(|| {
-                    tex_files
-                        .iter()
-                        .find(|p| p.semantic == TextureType::Diffuse)
-                })
-                .map(|prop| Self::get_texture_path(prop, mesh_path));
-            let metalness = tex_files
-                .iter()
-                .find(|p| p.semantic == TextureType::Metalness)
-                .map(|prop| Self::get_texture_path(prop, mesh_path));
-            let roughness = tex_files
-                .iter()
-                .find(|p| p.semantic == TextureType::Roughness)
-                .map(|prop| Self::get_texture_path(prop, mesh_path));
-            let ao = tex_files
-                .iter()
-                .find(|p| p.semantic == TextureType::AmbientOcclusion)
-                .map(|prop| Self::get_texture_path(prop, mesh_path));
-            let normals = tex_files
-                .iter()
-                .find(|p| p.semantic == TextureType::Normals)
-                .map(|prop| Self::get_texture_path(prop, mesh_path));
-            let displacement = tex_files
-                .iter()
-                .find(|p| p.semantic == TextureType::Displacement)
-                .map(|prop| Self::get_texture_path(prop, mesh_path));
-
-            material_items.push((base_color, metalness, roughness, ao, normals, displacement));
-        }
-
-        let mut items = Vec::with_capacity(material_items.len());
-        for item in material_items {
-            items.push(self.load_material_textures(
-                material_info,
-                &(item.0, item.1, item.2, item.3, item.4, item.5),
-            ));
-        }
-
-        _ = self.materials.push(mesh_path, items);
-
-        // let items = match &material.textures {
-        //     serializable::Textures::Own => {
-        //         items
-        //     }
-        //     serializable::Textures::Custom {
-        //         base_color,
-        //         metalness,
-        //         roughness,
-        //         ao,
-        //         normals,
-        //         displacement,
-        //     } => {
-        //         let m = self.load_material_textures(
-        //             material,
-        //             (base_color, metalness, roughness, ao, normals, displacement),
-        //         );
-        //         vec![m]
-        //     }
-        // };
+            let textures = MaterialTextures {
+                base_color: find(TextureType::BaseColor).or_else(|| find(TextureType::Diffuse)),
+                metalness: find(TextureType::Metalness),
+                roughness: find(TextureType::Roughness),
+                ao: find(TextureType::AmbientOcclusion),
+                normals: find(TextureType::Normals),
+                displacement: find(TextureType::Displacement),
+                emissive: find(TextureType::EmissionColor).or_else(|| find(TextureType::Emissive)),
+            };
+
+            material_items.push(textures);
+        }
+
+        material_items
     }
 
     fn get_texture_path(prop: &MaterialProperty, mesh_path: &str) -> String {
         if let PropertyTypeInfo::String(s) = &prop.data {
-            let mut path = PathBuf::from(&mesh_path);
-            path.pop();
-            path.push(PathBuf::from(&s));
-            return path.to_str().unwrap().to_string();
+            return asset_path::join(mesh_path, s);
         }
         unreachable!()
     }
 
-    fn load_material_textures(
-        &mut self,
-        material: &serializable::MaterialInfo,
-        texs: &(
-            Option<String>,
-            Option<String>,
-            Option<String>,
-            Option<String>,
-            Option<String>,
-            Option<String>,
-        ),
-    ) -> Material {
-        let mut base_color = self.textures.get_index("default_base_color");
-        if let Some(path) = &texs.0 {
-            let img = StbImage::load(path, true);
-            base_color = self.load_tex(img.data(), (img.x(), img.y()), img.channels(), path, true);
+    // Embedded textures get a path inside the model file, "model.glb::*0", so they are
+    // cached and reloaded together with the model
+    fn read_embedded_texture(
+        texture: &russimp::material::Texture,
+        prop: &MaterialProperty,
+        mesh_path: &str,
+        embedded: &mut FxHashMap<String, Vec<u8>>,
+    ) -> Option<String> {
+        let name = match &prop.data {
+            PropertyTypeInfo::String(s) => s,
+            _ => unreachable!(),
+        };
+        let path = format!("{mesh_path}{EMBEDDED_SEPARATOR}{name}");
+        if embedded.contains_key(&path) {
+            return Some(path);
         }
 
-        let mut metalness = self.textures.get_index("default_metalness");
-        let mut roughness = self.textures.get_index("default_roughness");
-        let mut ao = self.textures.get_index("default_ao");
+        match &texture.data {
+            // Zero height means the data is a whole image file (png, jpg, ...)
+            DataContent::Bytes(bytes) if texture.height == 0 => {
+                _ = embedded.insert(path.clone(), bytes.clone());
+                Some(path)
+            }
+            // russimp hands over only the first `width` bytes of raw texel data
+            _ => {
+                println!("Uncompressed embedded texture {path} is not supported");
+                None
+            }
+        }
+    }
 
-        match &material.pbr_channels {
-            PBRTextures::Separated => {
-                if let Some(path) = &texs.1 {
-                    let img = StbImage::load(path, true);
-                    metalness =
-                        self.load_tex(img.data(), (img.x(), img.y()), img.channels(), path, false);
-                }
-                if let Some(path) = &texs.2 {
-                    let img = StbImage::load(path, true);
-                    roughness =
-                        self.load_tex(img.data(), (img.x(), img.y()), img.channels(), path, false);
+    pub fn to_cooked(&self) -> Vec<u8> {
+        let mut blob = BlobWriter::new(Self::COOKED_MAGIC);
+        blob.u32(self.submeshes.len() as u32);
+        for (vertex_data, index_data) in &self.submeshes {
+            blob.array(vertex_data);
+            blob.array(index_data);
+        }
+        blob.bytes(&serde_json::to_vec(&self.materials).unwrap());
+        blob.bytes(&serde_json::to_vec(&self.nodes).unwrap());
+        blob.bytes(&serde_json::to_vec(&self.bones).unwrap());
+        blob.bytes(&serde_json::to_vec(&self.clips).unwrap());
+        for targets in &self.morph_targets {
+            blob.u32(targets.len() as u32);
+            for target in targets {
+                blob.bytes(target.name.as_bytes());
+                blob.array(&target.deltas);
+            }
+        }
+        blob.u32(self.embedded.len() as u32);
+        for (path, bytes) in &self.embedded {
+            blob.bytes(path.as_bytes());
+            blob.bytes(bytes);
+        }
+        blob.finish()
+    }
+
+    fn from_cooked(data: &[u8]) -> Option<Self> {
+        let mut blob = BlobReader::new(data, Self::COOKED_MAGIC)?;
+        let submesh_count = blob.u32()?;
+        let mut submeshes = Vec::with_capacity(submesh_count as usize);
+        for _ in 0..submesh_count {
+            submeshes.push((blob.array::<Vertex>()?, blob.array::<u32>()?));
+        }
+        let materials = serde_json::from_slice(blob.bytes()?).ok()?;
+        let nodes = serde_json::from_slice(blob.bytes()?).ok()?;
+        let bones = serde_json::from_slice(blob.bytes()?).ok()?;
+        let clips = serde_json::from_slice(blob.bytes()?).ok()?;
+        let mut morph_targets = Vec::with_capacity(submeshes.len());
+        for _ in 0..submeshes.len() {
+            let mut targets = Vec::new();
+            for _ in 0..blob.u32()? {
+                let name = String::from_utf8(blob.bytes()?.to_vec()).ok()?;
+                targets.push(MorphTarget {
+                    name,
+                    deltas: blob.array::<MorphDelta>()?,
+                });
+            }
+            morph_targets.push(targets);
+        }
+        let mut embedded = FxHashMap::default();
+        for _ in 0..blob.u32()? {
+            let path = String::from_utf8(blob.bytes()?.to_vec()).ok()?;
+            _ = embedded.insert(path, blob.bytes()?.to_vec());
+        }
+
+        Some(Self {
+            submeshes,
+            materials,
+            embedded,
+            nodes,
+            bones,
+            clips,
+            morph_targets,
+        })
+    }
+
+    fn skeleton(&self) -> Option<Rc<SkeletonData>> {
+        if self.bones.is_empty() {
+            return None;
+        }
+        let skeleton = SkeletonData::new(&self.nodes, &self.bones, self.clips.clone());
+        Some(Rc::new(skeleton))
+    }
+
+    fn upload(&self, retain_geometry: bool) -> Vec<MeshData> {
+        self.submeshes
+            .iter()
+            .zip(&self.morph_targets)
+            .map(|((vertex_data, index_data), targets)| {
+                let mut mesh_data =
+                    MeshData::from_vertex_index_data(vertex_data, index_data, gl::STATIC_DRAW);
+                mesh_data.set_morph_targets(targets);
+                if retain_geometry {
+                    mesh_data.retain_geometry(vertex_data.clone(), index_data.clone());
                 }
-                if let Some(path) = &texs.3 {
-                    let img = StbImage::load(path, true);
-                    ao = self.load_tex(img.data(), (img.x(), img.y()), img.channels(), path, false);
+                mesh_data
+            })
+            .collect()
+    }
+}
+
+// Assimp describes perspective cameras only, with half of the horizontal field of view. The
+// aspect is replaced by the framebuffer's when the camera is spawned
+fn import_camera(camera: &Camera) -> serializable::Camera {
+    let aspect = match camera.aspect {
+        aspect if aspect > 0.0 => aspect,
+        _ => 1.0,
+    };
+    let fovy = 2.0 * (camera.horizontal_fov.tan() / aspect).atan();
+    serializable::Camera {
+        projection: Projection::new_perspective(
+            aspect,
+            fovy.to_degrees(),
+            camera.clip_plane_near,
+            camera.clip_plane_far,
+        ),
+    }
+}
+
+// The colour comes with the intensity already applied. Attenuation isn't taken, the
+// renderer's falloff is physical, and cone angles are measured from the direction as the
+// renderer expects. Ambient and area lights have no counterpart
+fn import_light(light: &Light) -> Option<serializable::LightSource> {
+    let type_ = match light.light_source_type {
+        LightSourceType::Directional => LightType::Directional,
+        LightSourceType::Point => LightType::Point,
+        LightSourceType::Spot => LightType::Spot,
+        _ => return None,
+    };
+    let color = &light.color_diffuse;
+    Some(serializable::LightSource {
+        color: serializable::Vec3 {
+            x: color.r,
+            y: color.g,
+            z: color.b,
+        },
+        type_,
+        inner: light.angle_inner_cone.to_degrees(),
+        outer: light.angle_outer_cone.to_degrees(),
+        shadow_distance: lighting::LightSource::DEFAULT_SHADOW_DISTANCE,
+        shadows: Default::default(),
+    })
+}
+
+// Arrays of the assimp C API, the pointers of missing ones are null
+unsafe fn raw_slice<'a, T>(data: *const T, len: u32) -> &'a [T] {
+    if data.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len as usize)
+    }
+}
+
+struct ModelInfo {
+    options: ImportOptions,             // for reloading
+    nodes: Vec<ModelNode>,              // for spawning
+    skeleton: Option<Rc<SkeletonData>>, // for models with bones
+    clips: Vec<AnimationClip>,          // for animation players
+    // Own materials are loaded for the first user without a material asset
+    material_info: serializable::MaterialInfo,
+    materials: Vec<MaterialTextures>,
+    embedded: FxHashMap<String, Vec<u8>>, // images of the materials, until they are loaded
+}
+
+pub struct MeshManager {
+    meshes: RangeIndexContainer<MeshData>,
+    materials: RangeIndexContainer<Material>,
+    textures: SingleIndexContainer<Texture>,
+    texture_sources: Vec<TextureSource>,
+    vfs: Arc<Vfs>,
+    cooked: Arc<Manifest>,
+    loader: AssetLoader,
+    // Material info and retain_geometry of the models being imported
+    loading_models: FxHashMap<String, (serializable::MaterialInfo, bool)>,
+    models: FxHashMap<String, ModelInfo>, // by model key
+    image_requests: FxHashMap<String, Vec<ImageTarget>>,
+    embedded_images: FxHashMap<String, Vec<u8>>, // of the model being added, by canonical path
+    max_anisotropy: f32,
+    dynamic_mesh_counter: usize, // for the keys of dynamic meshes
+}
+
+// Where a texture came from, so it can be uploaded again when the image file changes
+struct TextureSource {
+    key: TextureKey,
+    index: usize,
+}
+
+// Identifies a texture in the cache. The same file referenced by different models and
+// materials, however its path is spelled, ends up in a single texture
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TextureKey {
+    pub path: String, // canonical
+    pub channel: Option<usize>,
+    pub srgb: bool,
+    pub sampler: SamplerParams,
+}
+
+impl TextureKey {
+    pub fn new(path: &str, channel: Option<usize>, srgb: bool, sampler: SamplerParams) -> Self {
+        Self {
+            path: Self::canonical_path(path),
+            channel,
+            srgb,
+            sampler,
+        }
+    }
+
+    fn canonical_path(path: &str) -> String {
+        // Only the model part of an embedded texture path exists on disk
+        if let Some((model, name)) = path.rsplit_once(EMBEDDED_SEPARATOR) {
+            return format!("{}{EMBEDDED_SEPARATOR}{name}", Self::canonical_path(model));
+        }
+        match fs::canonicalize(path) {
+            // Verbatim prefix added on Windows is not understood by stb_image
+            Ok(canonical) => canonical
+                .to_str()
+                .unwrap()
+                .trim_start_matches(r"\\?\")
+                .to_string(),
+            // Not on disk when the assets are packed
+            Err(_) => vfs::normalize(path),
+        }
+    }
+
+    // Name in the texture container, parameters are only spelled out when they differ
+    // from the defaults to keep the memory report readable
+    fn name(&self) -> String {
+        let mut name = self.path.clone();
+        if let Some(channel) = self.channel {
+            name.push_str(&format!(" channel={channel}"));
+        }
+        if self.srgb {
+            name.push_str(" srgb");
+        }
+        if self.sampler != SamplerParams::default() {
+            name.push_str(&format!(" {:?}", self.sampler));
+        }
+        name
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SamplerParams {
+    pub wrap_s: GLenum,
+    pub wrap_t: GLenum,
+    pub min_filter: GLenum,
+    pub mag_filter: GLenum,
+    pub anisotropy: u32,
+}
+
+impl Default for SamplerParams {
+    fn default() -> Self {
+        SamplerInfo::default().into()
+    }
+}
+
+impl From<SamplerInfo> for SamplerParams {
+    fn from(info: SamplerInfo) -> Self {
+        let wrap = |mode: WrapMode| match mode {
+            WrapMode::Repeat => gl::REPEAT,
+            WrapMode::MirroredRepeat => gl::MIRRORED_REPEAT,
+            WrapMode::ClampToEdge => gl::CLAMP_TO_EDGE,
+        };
+        let min_filter = match (info.filter, info.mipmaps) {
+            (FilterMode::Nearest, false) => gl::NEAREST,
+            (FilterMode::Nearest, true) => gl::NEAREST_MIPMAP_LINEAR,
+            (FilterMode::Linear, false) => gl::LINEAR,
+            (FilterMode::Linear, true) => gl::LINEAR_MIPMAP_LINEAR,
+        };
+        let mag_filter = match info.filter {
+            FilterMode::Nearest => gl::NEAREST,
+            FilterMode::Linear => gl::LINEAR,
+        };
+
+        Self {
+            wrap_s: wrap(info.wrap_s),
+            wrap_t: wrap(info.wrap_t),
+            min_filter,
+            mag_filter,
+            anisotropy: info.anisotropy.max(1),
+        }
+    }
+}
+
+impl SamplerParams {
+    pub fn uses_mipmaps(&self) -> bool {
+        self.min_filter != gl::NEAREST && self.min_filter != gl::LINEAR
+    }
+
+    fn apply(&self, tex: &Texture, max_anisotropy: f32) {
+        tex.parameter(gl::TEXTURE_WRAP_S, self.wrap_s);
+        tex.parameter(gl::TEXTURE_WRAP_T, self.wrap_t);
+        tex.parameter(gl::TEXTURE_MIN_FILTER, self.min_filter);
+        tex.parameter(gl::TEXTURE_MAG_FILTER, self.mag_filter);
+        // The parameter is unknown to drivers without the extension
+        if max_anisotropy > 1.0 {
+            let anisotropy = (self.anisotropy as f32).min(max_anisotropy);
+            tex.parameter_f(TEXTURE_MAX_ANISOTROPY, anisotropy);
+        }
+    }
+}
+
+impl MeshManager {
+    // 1x1 textures used when a material lacks a map, they also stand in for textures
+    // that are still being loaded
+    #[rustfmt::skip]
+    const DEFAULT_TEXTURES: [(&'static str, [u8; 3], GLenum); 7] = [
+        ("default_base_color",   [128, 128, 128], gl::RGB),
+        ("default_metalness",    [0, 0, 0],       gl::RED),
+        ("default_roughness",    [128, 0, 0],     gl::RED),
+        ("default_ao",           [255, 0, 0],     gl::RED),
+        ("default_normals",      [128, 128, 255], gl::RGB), // flat in tangent space
+        ("default_displacement", [255, 0, 0],     gl::RED), // Set proper value
+        ("default_emissive",     [255, 255, 255], gl::RGB), // scaled by the emissive factor, which is black by default
+    ];
+
+    pub fn new(vfs: Arc<Vfs>, cooked: Arc<Manifest>) -> Self {
+        let mut textures = SingleIndexContainer::<Texture>::new();
+        for (name, _, _) in &Self::DEFAULT_TEXTURES {
+            let idx = textures.push(name, Self::create_dafault_texture(name));
+            textures.pin(&idx);
+        }
+
+        Self {
+            meshes: RangeIndexContainer::new(),
+            materials: RangeIndexContainer::new(),
+            textures,
+            texture_sources: Vec::new(),
+            loader: AssetLoader::new(vfs.clone(), cooked.clone()),
+            vfs,
+            cooked,
+            loading_models: Default::default(),
+            models: Default::default(),
+            image_requests: Default::default(),
+            embedded_images: Default::default(),
+            // Drivers without anisotropic filtering leave it at 1
+            max_anisotropy: gl_wrappers::get_float(MAX_TEXTURE_MAX_ANISOTROPY, 1.0),
+            dynamic_mesh_counter: 0,
+        }
+    }
+
+    // Of all the submeshes, in the space of the model
+    pub fn bounds(&self, mesh_index: &RangeIndex) -> Bounds {
+        self.meshes
+            .get(mesh_index)
+            .map(|mesh_data| mesh_data.bounds)
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or_default()
+    }
+
+    // A single submesh rewritten with `update_dynamic_mesh`, empty until then. It is freed
+    // with the other meshes once no component uses it
+    pub fn add_dynamic_mesh(&mut self, material_info: &serializable::MaterialInfo) -> Mesh {
+        let key = format!("{DYNAMIC_SCHEME}{}", self.dynamic_mesh_counter);
+        self.dynamic_mesh_counter += 1;
+        let mesh_data = MeshData::from_vertex_index_data(&[], &[], gl::DYNAMIC_DRAW);
+        _ = self.meshes.push(&key, vec![mesh_data]);
+        let material = self.load_material_textures(
+            &material_info.pbr_channels,
+            &MaterialTextures::default(),
+            &material_info.sampler,
+            &material_info.texture_settings,
+        );
+        _ = self.materials.push(&key, vec![material]);
+
+        Mesh {
+            mesh_index: self.meshes.get_index(&key),
+            material_index: self.materials.get_index(&key),
+            morph_weights: Vec::new(),
+        }
+    }
+
+    // False for meshes imported from files, those are static
+    pub fn update_dynamic_mesh(
+        &mut self,
+        mesh_index: &RangeIndex,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> bool {
+        match self.meshes.get_mut(mesh_index).next() {
+            Some(mesh_data) if mesh_data.is_dynamic() => {
+                mesh_data.update(vertices, indices);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // One item per submesh, `None` for the ones loaded without their geometry retained
+    pub fn geometry(
+        &self,
+        mesh_index: &RangeIndex,
+    ) -> impl Iterator<Item = Option<(&[Vertex], &[u32])>> {
+        self.meshes
+            .get(mesh_index)
+            .map(|mesh_data| mesh_data.geometry())
+    }
+
+    pub fn mesh_n_material(&self, mesh: &Mesh) -> impl Iterator<Item = (&MeshData, &Material)> {
+        let mesh_data = self.meshes.get(&mesh.mesh_index);
+        let materials = self.materials.get(&mesh.material_index);
+        // A shared material asset is a single item applied to every submesh
+        mesh_data.zip(materials.cycle())
+    }
+
+    pub fn meshes(&self) -> &RangeIndexContainer<MeshData> {
+        &self.meshes
+    }
+
+    pub fn materials(&self) -> &RangeIndexContainer<Material> {
+        &self.materials
+    }
+
+    pub fn textures(&self) -> &SingleIndexContainer<Texture> {
+        &self.textures
+    }
+
+    fn count_users(&mut self, meshes: &[Component<Mesh>]) {
+        self.meshes.reset_users();
+        self.materials.reset_users();
+        for mesh in meshes {
+            self.meshes.add_part_user(&mesh.data.mesh_index);
+            self.materials.add_part_user(&mesh.data.material_index);
+        }
+        // Meshes of a model are kept with its own materials and the other way around, a
+        // model whose materials were replaced by an asset still has them looked up by key
+        let kept = self
+            .meshes
+            .table
+            .keys()
+            .filter(|key| self.meshes.is_kept(key) || self.materials.is_kept(key))
+            .cloned()
+            .collect::<Vec<String>>();
+        for key in &kept {
+            self.meshes.add_named_user(key);
+            self.materials.add_named_user(key);
+        }
+        self.count_texture_users();
+    }
+
+    fn count_texture_users(&mut self) {
+        self.textures.reset_users();
+        for material in self.materials.iter() {
+            for idx in material.textures() {
+                self.textures.add_user(&idx);
+            }
+        }
+    }
+
+    // Frees everything not referenced by the given components, normally called after
+    // a scene is unloaded
+    pub fn collect_garbage(&mut self, meshes: &[Component<Mesh>]) {
+        self.count_users(meshes);
+        _ = self.meshes.collect_garbage();
+        _ = self.materials.collect_garbage();
+        // Textures of the materials freed above are unused now
+        self.count_texture_users();
+        let freed = self.textures.collect_garbage();
+        self.texture_sources
+            .retain(|source| !freed.contains(&source.index));
+        let meshes = &self.meshes;
+        self.models.retain(|key, _| meshes.contains(key));
+    }
+
+    pub fn memory_report(&mut self, meshes: &[Component<Mesh>]) -> MemoryReport {
+        self.count_users(meshes);
+        let mut items = Vec::new();
+
+        let users_of = |predicate: &dyn Fn(&Mesh) -> bool| {
+            meshes
+                .iter()
+                .filter(|item| predicate(&item.data))
+                .map(|item| format!("entity {}", item.owner_id()))
+                .collect::<Vec<String>>()
+        };
+
+        for (idx, entry) in self.meshes.entries() {
+            items.push(MemoryReportItem {
+                kind: "mesh",
+                name: entry.name.clone(),
+                size: self.meshes.get(idx).map(|item| item.memory_size()).sum(),
+                users: users_of(&|mesh| contains_part(idx, &mesh.mesh_index)),
+                pinned: entry.pinned,
+            });
+        }
+        for (idx, entry) in self.materials.entries() {
+            items.push(MemoryReportItem {
+                kind: "material",
+                name: entry.name.clone(),
+                size: idx.len() * mem::size_of::<Material>(),
+                users: users_of(&|mesh| contains_part(idx, &mesh.material_index)),
+                pinned: entry.pinned,
+            });
+        }
+        for (idx, entry) in self.textures.entries() {
+            let users = self
+                .materials
+                .entries()
+                .filter(|(range, _)| {
+                    self.materials
+                        .get(range)
+                        .any(|material| material.textures().contains(idx))
+                })
+                .map(|(_, material)| format!("material {}", material.name))
+                .collect();
+            items.push(MemoryReportItem {
+                kind: "texture",
+                name: entry.name.clone(),
+                size: self.textures.get(*idx).memory_size(),
+                users,
+                pinned: entry.pinned,
+            });
+        }
+
+        items.sort_by(|a, b| b.size.cmp(&a.size));
+        MemoryReport { items }
+    }
+
+    fn create_dafault_texture(name: &str) -> Texture {
+        let (_, data, format) = Self::DEFAULT_TEXTURES
+            .iter()
+            .find(|item| item.0 == name)
+            .unwrap();
+
+        let tex = Texture::new(gl::TEXTURE_2D).unwrap();
+        tex.bind();
+        tex.texture_data((1, 1), data.as_ptr().cast(), gl::UNSIGNED_BYTE, *format, *format);
+        tex.parameter(gl::TEXTURE_WRAP_S, gl::REPEAT);
+        tex.parameter(gl::TEXTURE_WRAP_T, gl::REPEAT);
+        tex.parameter(gl::TEXTURE_MIN_FILTER, gl::NEAREST);
+        tex.parameter(gl::TEXTURE_MAG_FILTER, gl::NEAREST);
+        tex
+    }
+
+    // Blocks until the model is imported, textures still arrive asynchronously. Without
+    // `own_materials` the material range is left empty for the caller to fill
+    pub fn get_mesh_lazily(&mut self, mesh: &serializable::Mesh, own_materials: bool) -> Mesh {
+        let key = Self::model_key(mesh);
+        if !self.meshes.contains(&key) {
+            let path = mesh.path.resolve();
+            let options = Self::import_options(mesh);
+            let model = ImportedModel::load(&path, &options, &self.vfs, &self.cooked).unwrap();
+            let retain = mesh.retain_geometry;
+            self.add_model(&key, &mesh.material_info, &options, retain, model);
+        }
+        let mesh_index = self.meshes.get_index(&key);
+        let material_index = match own_materials {
+            true => self.model_materials(&key),
+            false => Default::default(),
+        };
+
+        Mesh {
+            morph_weights: self.morph_weights(&mesh_index),
+            mesh_index,
+            material_index,
+        }
+    }
+
+    // Every blend shape of the submeshes, at zero weight
+    pub fn morph_weights(&self, mesh_index: &RangeIndex) -> Vec<(String, f32)> {
+        let mut weights = Vec::<(String, f32)>::new();
+        for mesh_data in self.meshes.get(mesh_index) {
+            for name in &mesh_data.morph_targets {
+                if !weights.iter().any(|(target, _)| target == name) {
+                    weights.push((name.clone(), 0.0));
                 }
             }
-            PBRTextures::Merged(pbr_channels) => {
-                if let Some(path) = texs.1.as_ref().or(texs.2.as_ref()).or(texs.3.as_ref()) {
-                    let img = StbImage::load(path, true);
+        }
+        weights
+    }
 
-                    if let Some(path) = &texs.1 {
-                        let ch = img.extract_channel(pbr_channels.metalness_offset());
-                        let mut p = PathBuf::from(pbr_channels.metalness_offset().to_string());
-                        p.push(path);
-                        metalness =
-                            self.load_tex(&ch, (img.x(), img.y()), 1, p.to_str().unwrap(), false);
-                    }
+    // Starts importing the model on a worker, see `process_loaded`
+    pub fn request_mesh(&mut self, mesh: &serializable::Mesh) {
+        let key = Self::model_key(mesh);
+        if self.meshes.contains(&key) || self.loading_models.contains_key(&key) {
+            return;
+        }
+        let info = (mesh.material_info.clone(), mesh.retain_geometry);
+        _ = self.loading_models.insert(key.clone(), info);
+        self.loader.submit(Job::Model {
+            key,
+            path: mesh.path.resolve(),
+            options: Self::import_options(mesh),
+        });
+    }
 
-                    if let Some(path) = &texs.2 {
-                        let ch = img.extract_channel(pbr_channels.roughness_offset());
-                        let mut p = PathBuf::from(pbr_channels.roughness_offset().to_string());
-                        p.push(path);
-                        roughness =
-                            self.load_tex(&ch, (img.x(), img.y()), 1, p.to_str().unwrap(), false);
-                    }
+    pub fn model_nodes(&self, key: &str) -> &[ModelNode] {
+        &self.models[key].nodes
+    }
 
-                    if let Some(path) = &texs.3 {
-                        let ch = img.extract_channel(pbr_channels.ao_offset());
-                        let mut p = PathBuf::from(pbr_channels.ao_offset().to_string());
-                        p.push(path);
-                        ao = self.load_tex(&ch, (img.x(), img.y()), 1, p.to_str().unwrap(), false);
+    pub fn model_skeleton(&self, key: &str) -> Option<Rc<SkeletonData>> {
+        self.models.get(key)?.skeleton.clone()
+    }
+
+    // Channels of the clips refer to the nodes imported along with them
+    pub fn model_clips(&self, key: &str) -> Option<Vec<Rc<TransformClip>>> {
+        let info = self.models.get(key)?;
+        let clips = info
+            .clips
+            .iter()
+            .map(|clip| Rc::new(clip.to_transform_clip(&info.nodes)))
+            .collect();
+        Some(clips)
+    }
+
+    // Name the meshes and materials of the model are stored under
+    pub fn model_key(mesh: &serializable::Mesh) -> String {
+        let key = Self::import_options(mesh).model_key(&mesh.path.resolve());
+        // Copies of the geometry aren't part of the import, the cooked model still fits
+        match mesh.retain_geometry {
+            true => format!("{key}{}retain", ImportOptions::KEY_SEPARATOR),
+            false => key,
+        }
+    }
+
+    // OptimizeGraph merges the nodes a hierarchy is spawned from, so it is left out and the
+    // model is cached apart from its flat import
+    fn import_options(mesh: &serializable::Mesh) -> ImportOptions {
+        let mut options = mesh.import.clone();
+        options.optimize_graph &= !mesh.hierarchy;
+        options
+    }
+
+    pub fn is_loading(&self, key: &str) -> bool {
+        self.loading_models.contains_key(key)
+    }
+
+    pub fn loading_progress(&self) -> (usize, usize) {
+        self.loader.progress()
+    }
+
+    // Creates GL objects for whatever the workers have finished, until the budget runs out.
+    // At least one result is handled per call so loading always moves forward
+    pub fn process_loaded(&mut self, budget: Duration) {
+        let start = Instant::now();
+        while let Some(loaded) = self.loader.try_recv() {
+            match loaded {
+                Loaded::Model {
+                    key,
+                    path,
+                    options,
+                    model,
+                } => {
+                    let (material_info, retain) = self.loading_models.remove(&key).unwrap();
+                    match model {
+                        Ok(model) => self.add_model(&key, &material_info, &options, retain, model),
+                        Err(error) => println!("Failed to load {path}: {error}"),
                     }
                 }
+                Loaded::Image { path, images } => match images {
+                    Some(images) => {
+                        for (index, image) in images {
+                            // The texture could have been collected while the image was
+                            // decoded, and its slot given to another one
+                            let source = self.texture_sources.iter().find(|item| {
+                                item.index == index && item.key.path == path
+                            });
+                            let source = match source {
+                                Some(source) => source,
+                                None => continue,
+                            };
+                            let tex = self.textures.get(index);
+                            Self::upload_tex(tex, &image, &source.key);
+                            source.key.sampler.apply(tex, self.max_anisotropy);
+                        }
+                    }
+                    None => println!("Failed to load {path}"),
+                },
+            }
+
+            if start.elapsed() >= budget {
+                break;
             }
         }
+    }
+
+    fn add_model(
+        &mut self,
+        key: &str,
+        material_info: &serializable::MaterialInfo,
+        options: &ImportOptions,
+        retain_geometry: bool,
+        model: ImportedModel,
+    ) {
+        _ = self.meshes.push(key, model.upload(retain_geometry));
+        let info = ModelInfo {
+            options: options.clone(),
+            skeleton: model.skeleton(),
+            nodes: model.nodes,
+            clips: model.clips,
+            material_info: material_info.clone(),
+            materials: model.materials,
+            embedded: model.embedded,
+        };
+        _ = self.models.insert(key.to_string(), info);
+    }
+
+    // Materials of the model, their textures start loading the first time they are asked for
+    fn model_materials(&mut self, key: &str) -> RangeIndex {
+        if !self.materials.contains(key) {
+            let info = self.models.get_mut(key).unwrap();
+            let embedded = mem::take(&mut info.embedded);
+            let material_info = info.material_info.clone();
+            let materials = info.materials.clone();
+
+            self.set_embedded_images(embedded);
+            let mut items = Vec::with_capacity(materials.len());
+            for item in &materials {
+                items.push(self.load_material_textures(
+                    &material_info.pbr_channels,
+                    item,
+                    &material_info.sampler,
+                    &material_info.texture_settings,
+                ));
+            }
+            _ = self.materials.push(key, items);
+            self.embedded_images.clear();
+        }
+        self.materials.get_index(key)
+    }
+
+    fn set_embedded_images(&mut self, embedded: FxHashMap<String, Vec<u8>>) {
+        self.embedded_images = embedded
+            .into_iter()
+            .map(|(path, bytes)| (TextureKey::canonical_path(&path), bytes))
+            .collect();
+    }
+
+    // Reloads the models that are made of the file along with their own one, the buffers of
+    // glTF models
+    pub fn reload_model_dependency(&mut self, path: &str) {
+        let mut models = self
+            .models
+            .keys()
+            .map(|key| ImportOptions::model_path(key).to_string())
+            .filter(|model_path| {
+                cook::dependencies(&self.vfs, model_path)
+                    .iter()
+                    .any(|dependency| hot_reload::same_file(dependency, path))
+            })
+            .collect::<Vec<String>>();
+        models.sort();
+        models.dedup();
+        for model_path in models {
+            self.reload_mesh(&model_path);
+        }
+    }
+
+    // Geometry is replaced in place so `Mesh` components keep their indices. Materials
+    // of the model are left as they are, their textures are reloaded on their own
+    pub fn reload_mesh(&mut self, path: &str) {
+        let keys = self
+            .meshes
+            .table
+            .iter()
+            .filter(|(key, _)| hot_reload::same_file(ImportOptions::model_path(key), path))
+            .map(|(key, idx)| (key.clone(), idx.clone()))
+            .collect::<Vec<(String, RangeIndex)>>();
+
+        for (key, idx) in keys {
+            let options = &self.models[&key].options;
+            let model_path = ImportOptions::model_path(&key);
+            let model = match ImportedModel::read(model_path, options, &self.vfs) {
+                Ok(model) => model,
+                Err(error) => {
+                    println!("Failed to reload {key}: {error}");
+                    continue;
+                }
+            };
+            if model.submeshes.len() != idx.len() {
+                println!("Submesh count of {key} has changed, restart to reload it");
+                continue;
+            }
+            // Copies are kept if the old geometry had them
+            let retain = self
+                .meshes
+                .get(&idx)
+                .any(|mesh_data| mesh_data.geometry().is_some());
+            for (old, new) in self.meshes.get_mut(&idx).zip(model.upload(retain)) {
+                *old = new;
+            }
+            // Entities and skeletons spawned from the old model are left as they are
+            let materials_loaded = self.materials.contains(&key);
+            let info = self.models.get_mut(&key).unwrap();
+            info.skeleton = model.skeleton();
+            info.nodes = model.nodes;
+            info.clips = model.clips;
+            info.materials = model.materials;
+            if materials_loaded {
+                self.reload_embedded_textures(model.embedded);
+            } else {
+                info.embedded = model.embedded;
+            }
+        }
+    }
+
+    fn reload_embedded_textures(&mut self, embedded: FxHashMap<String, Vec<u8>>) {
+        self.set_embedded_images(embedded);
+        for source in &self.texture_sources {
+            if self.embedded_images.contains_key(&source.key.path) {
+                self.image_requests
+                    .entry(source.key.path.clone())
+                    .or_default()
+                    .push(ImageTarget {
+                        index: source.index,
+                        channel: source.key.channel,
+                    });
+            }
+        }
+        self.submit_image_requests();
+        self.embedded_images.clear();
+    }
+
+    pub fn get_material_lazily(&mut self, asset_path: &str, asset: &MaterialAsset) -> RangeIndex {
+        if !self.materials.contains(asset_path) {
+            let material = self.asset_material(asset_path, asset);
+            _ = self.materials.push(asset_path, vec![material]);
+        }
+        self.materials.get_index(asset_path)
+    }
+
+    // Textures the edited asset no longer uses are freed with the next garbage collection
+    pub fn reload_material(&mut self, asset_path: &str, asset: &MaterialAsset) {
+        if self.materials.contains(asset_path) {
+            let material = self.asset_material(asset_path, asset);
+            let idx = self.materials.get_index(asset_path);
+            *self.materials.get_mut(&idx).next().unwrap() = material;
+        }
+    }
+
+    fn asset_material(&mut self, asset_path: &str, asset: &MaterialAsset) -> Material {
+        let resolve = |texture: &Option<String>| {
+            texture
+                .as_ref()
+                .map(|relative| asset_path::join(asset_path, relative))
+        };
+        let textures = MaterialTextures {
+            base_color: resolve(&asset.textures.base_color),
+            metalness: resolve(&asset.textures.metalness),
+            roughness: resolve(&asset.textures.roughness),
+            ao: resolve(&asset.textures.ao),
+            normals: resolve(&asset.textures.normals),
+            displacement: resolve(&asset.textures.displacement),
+            emissive: resolve(&asset.textures.emissive),
+        };
+
+        let mut material = self.load_material_textures(
+            &asset.pbr_channels,
+            &textures,
+            &asset.sampler,
+            &asset.texture_settings,
+        );
+        material.factors = asset.factors;
+        material.alpha_mode = asset.alpha_mode;
+        material.two_sided = asset.two_sided;
+        material
+    }
+
+    fn load_material_textures(
+        &mut self,
+        pbr_textures: &PBRTextures,
+        texs: &MaterialTextures,
+        sampler: &SamplerInfo,
+        settings: &MaterialTextureSettings,
+    ) -> Material {
+        // Color maps are sRGB unless the settings say otherwise
+        let options = |settings: &TextureSettings, srgb: bool| {
+            let sampler = settings.sampler.unwrap_or(*sampler);
+            (settings.srgb(srgb), SamplerParams::from(sampler))
+        };
+
+        let mut base_color = self.textures.get_index("default_base_color");
+        if let Some(path) = &texs.base_color {
+            let options = options(&settings.base_color, true);
+            base_color = self.request_tex(path, None, options, "default_base_color");
+        }
+
+        let mut metalness = self.textures.get_index("default_metalness");
+        let mut roughness = self.textures.get_index("default_roughness");
+        let mut ao = self.textures.get_index("default_ao");
+
+        let (metalness_channel, roughness_channel, ao_channel) = match pbr_textures {
+            PBRTextures::Separated => (None, None, None),
+            PBRTextures::Merged(pbr_channels) => (
+                Some(pbr_channels.metalness_offset()),
+                Some(pbr_channels.roughness_offset()),
+                Some(pbr_channels.ao_offset()),
+            ),
+        };
+        if let Some(path) = &texs.metalness {
+            let options = options(&settings.metalness, false);
+            metalness = self.request_tex(path, metalness_channel, options, "default_metalness");
+        }
+        if let Some(path) = &texs.roughness {
+            let options = options(&settings.roughness, false);
+            roughness = self.request_tex(path, roughness_channel, options, "default_roughness");
+        }
+        if let Some(path) = &texs.ao {
+            let options = options(&settings.ao, false);
+            ao = self.request_tex(path, ao_channel, options, "default_ao");
+        }
 
         let mut normals = self.textures.get_index("default_normals");
-        if let Some(path) = &texs.4 {
-            let img = StbImage::load(path, true);
-            normals = self.load_tex(img.data(), (img.x(), img.y()), img.channels(), path, false);
+        if let Some(path) = &texs.normals {
+            let options = options(&settings.normals, false);
+            normals = self.request_tex(path, None, options, "default_normals");
         }
 
         let mut displacement = self.textures.get_index("default_displacement");
-        if let Some(path) = &texs.5 {
-            let img = StbImage::load(path, true);
-            displacement =
-                self.load_tex(img.data(), (img.x(), img.y()), img.channels(), path, false);
+        if let Some(path) = &texs.displacement {
+            let options = options(&settings.displacement, false);
+            displacement = self.request_tex(path, None, options, "default_displacement");
+        }
+
+        let mut factors = MaterialFactors::default();
+        let mut emissive = self.textures.get_index("default_emissive");
+        if let Some(path) = &texs.emissive {
+            let options = options(&settings.emissive, true);
+            emissive = self.request_tex(path, None, options, "default_emissive");
+            // The map is scaled by the factor, which is black by default
+            factors.emissive = [1.0; 3];
         }
 
+        self.submit_image_requests();
+
         Material {
             base_color,
             metalness,
@@ -522,23 +2316,112 @@ impl MeshManager {
             ao,
             normals,
             displacement,
+            emissive,
+            factors,
+            alpha_mode: Default::default(),
+            two_sided: false,
         }
     }
 
-    fn load_tex(
+    // Returns a texture that shows the placeholder until the image is decoded. `channel`
+    // picks a single channel out of a merged image
+    fn request_tex(
         &mut self,
-        img: &[u8],
-        size: (usize, usize),
-        channels: usize,
         path: &str,
-        srgb: bool,
+        channel: Option<usize>,
+        (srgb, sampler): (bool, SamplerParams),
+        placeholder: &str,
     ) -> usize {
-        if self.textures.contains(path) {
-            return self.textures.get_index(path);
+        let key = TextureKey::new(path, channel, srgb, sampler);
+        let name = key.name();
+        if self.textures.contains(&name) {
+            return self.textures.get_index(&name);
+        }
+
+        let index = self
+            .textures
+            .push(&name, Self::create_dafault_texture(placeholder));
+        // Channels of a merged image share one decode
+        self.image_requests
+            .entry(key.path.clone())
+            .or_default()
+            .push(ImageTarget { index, channel });
+        self.texture_sources.push(TextureSource { key, index });
+        index
+    }
+
+    fn submit_image_requests(&mut self) {
+        for (path, targets) in self.image_requests.drain() {
+            let data = self.embedded_images.get(&path).cloned();
+            self.loader.submit(Job::Image {
+                path,
+                data,
+                targets,
+            });
         }
+    }
 
-        let (format, internal_format) = if channels == 1 {
+    // Every texture gets its mipmaps here. Cooked images and DDS/KTX2 files come with their
+    // levels, decoded ones only with the first and the rest are generated. Samplers that don't
+    // mipmap only get the first level
+    fn upload_tex(tex: &Texture, image: &DecodedImage, key: &TextureKey) {
+        let srgb = key.srgb;
+        let mipmaps = key.sampler.uses_mipmaps();
+        let level_count = if mipmaps { image.levels.len() } else { 1 };
+        tex.bind();
+        // Rows of small levels aren't 4 byte aligned
+        gl_wrappers::pixel_store(gl::UNPACK_ALIGNMENT, 1);
+
+        for (level, data) in image.levels.iter().take(level_count).enumerate() {
+            let size = cook::level_size(image.size, level);
+            let size = (size.0 as i32, size.1 as i32);
+            match image.compression {
+                Some(compression) => tex.compressed_texture_level_data(
+                    level as i32,
+                    size,
+                    data,
+                    compression.internal_format(srgb),
+                ),
+                None => {
+                    let (format, internal_format) =
+                        Self::pixel_format(image.channels, image.pixel_type, srgb);
+                    tex.texture_level_data(
+                        level as i32,
+                        size,
+                        data.as_ptr().cast(),
+                        image.pixel_type.gl_type(),
+                        format,
+                        internal_format,
+                    );
+                }
+            }
+        }
+        if mipmaps && level_count == 1 {
+            tex.parameter(gl::TEXTURE_MAX_LEVEL, 1000); // the default
+            tex.generate_mipmaps();
+        } else {
+            // Chains stored in files may stop before 1x1
+            tex.parameter(gl::TEXTURE_MAX_LEVEL, level_count as GLenum - 1);
+        }
+    }
+
+    fn pixel_format(channels: usize, pixel_type: PixelType, srgb: bool) -> (GLenum, GLenum) {
+        // There are no sRGB formats wider than 8 bits, such data is expected to be linear
+        if pixel_type != PixelType::U8 {
+            let formats = [gl::RED, gl::RG, gl::RGB, gl::RGBA];
+            let internal_formats = match pixel_type {
+                PixelType::U16 => [gl::R16, gl::RG16, gl::RGB16, gl::RGBA16],
+                PixelType::F16 => [gl::R16F, gl::RG16F, gl::RGB16F, gl::RGBA16F],
+                PixelType::F32 => [gl::R32F, gl::RG32F, gl::RGB32F, gl::RGBA32F],
+                PixelType::U8 => unreachable!(),
+            };
+            return (formats[channels - 1], internal_formats[channels - 1]);
+        }
+
+        if channels == 1 {
             (gl::RED, gl::RED)
+        } else if channels == 2 {
+            (gl::RG, gl::RG)
         } else if channels == 3 {
             (gl::RGB, gl::RGB * (!srgb as u32) + gl::SRGB * (srgb as u32)) // or SRGB
         } else if channels == 4 {
@@ -548,24 +2431,24 @@ impl MeshManager {
             )
         } else {
             unreachable!()
-        };
-
-        let tex = Texture::new(gl::TEXTURE_2D).unwrap();
-        tex.bind();
-        tex.texture_data(
-            (size.0 as i32, size.1 as i32),
-            img.as_ptr().cast(),
-            gl::UNSIGNED_BYTE,
-            format,
-            internal_format,
-        );
-        tex.generate_mipmaps();
-        tex.parameter(gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE); // Not sure about CLAMP_TO_EDGE, could be REPEAT
-        tex.parameter(gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE);
-        tex.parameter(gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR);
-        tex.parameter(gl::TEXTURE_MAG_FILTER, gl::LINEAR);
+        }
+    }
 
-        self.textures.push(path, tex)
+    // Decodes the file again and re-uploads every texture made from it into its existing
+    // texture object, materials keep pointing at the same indices
+    pub fn reload_texture(&mut self, path: &str) {
+        for source in &self.texture_sources {
+            if hot_reload::same_file(&source.key.path, path) {
+                self.image_requests
+                    .entry(source.key.path.clone())
+                    .or_default()
+                    .push(ImageTarget {
+                        index: source.index,
+                        channel: source.key.channel,
+                    });
+            }
+        }
+        self.submit_image_requests();
     }
 
     // fn get_material_lazily(
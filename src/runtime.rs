@@ -1,17 +1,23 @@
 use crate::{
+    asset_path,
+    cook,
+    data3d::Mesh,
     entity_system::SceneManager,
     gl_wrappers::Gl,
+    hot_reload::AssetWatcher,
     rendering::{Renderer, Screen},
     resources::ResourceManager,
     scripting::Scripting,
+    serializable::MaterialAsset,
 };
 use core::result;
 use glfw::{
     fail_on_errors, Action, ClientApiHint, Context, CursorMode, GlfwReceiver, Key, Modifiers,
-    MouseButton, OpenGlProfileHint, PWindow, SwapInterval, WindowEvent, WindowHint, WindowMode,
+    MouseButton, OpenGlProfileHint, PWindow, SwapInterval, Version, WindowEvent, WindowHint,
+    WindowMode,
 };
 use spin_sleep::{SpinSleeper, SpinStrategy};
-use std::time::Duration;
+use std::{fs, path::Path, time::Duration};
 
 const CONTEXT_VERSION: WindowHint = WindowHint::ContextVersion(4, 6);
 const OPENGL_PROFILE: WindowHint = WindowHint::OpenGlProfile(OpenGlProfileHint::Core);
@@ -20,6 +26,9 @@ const MODE: WindowMode<'static> = WindowMode::Windowed;
 const SWAP_INTERVAL: SwapInterval = SwapInterval::Sync(1);
 const WIDTH: u32 = 800;
 const HEIGHT: u32 = 600;
+const HOT_RELOAD: bool = cfg!(debug_assertions);
+const HOT_RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+const UPLOAD_BUDGET: Duration = Duration::from_millis(4); // per frame
 
 pub fn run() {
     let mut glfw = glfw::init(fail_on_errors!()).unwrap();
@@ -49,24 +58,36 @@ pub fn run() {
 
     let gl = Gl::load();
 
+    let mut resource_manager = ResourceManager::new(&gl);
     let mut renderer = Renderer::new(
         window.get_framebuffer_size(),
         window.get_context_version(),
+        resource_manager.vfs(),
         &gl,
     );
     let mut screen = Screen::new(
         window.get_framebuffer_size(),
         window.get_context_version(),
+        resource_manager.vfs(),
         &gl,
     );
     let scripting = Scripting::new();
-    let mut resource_manager = ResourceManager::new(&gl);
     let mut scene_manager = SceneManager::default();
     let mut events = WindowEvents::new();
     let mut frametime = 0.0;
+    let mut loading_progress = (0, 0);
     let mut sleep_time = Duration::ZERO;
+    // Packed assets can't change while running
+    let mut watcher = (HOT_RELOAD && resource_manager.vfs().is_loose())
+        .then(|| AssetWatcher::new(asset_path::root(), HOT_RELOAD_INTERVAL));
 
-    scripting.load_api(&mut scene_manager, &events, &window, &frametime);
+    scripting.load_api(
+        &mut scene_manager,
+        &events,
+        &window,
+        &frametime,
+        &loading_progress,
+    );
     scene_manager.load_scene(0, &mut resource_manager, &scripting);
     scene_manager.framebuffer_size(window.get_framebuffer_size());
 
@@ -80,11 +101,36 @@ pub fn run() {
             &mut sleep_time,
             &mut [&mut renderer, &mut screen, &mut scene_manager],
         );
+        if let Some(watcher) = &mut watcher {
+            reload_iteration(
+                watcher,
+                window.get_context_version(),
+                &scripting,
+                &mut renderer,
+                &mut screen,
+                &mut resource_manager,
+            );
+        }
+        loading_iteration(
+            &mut scene_manager,
+            &mut resource_manager,
+            &scripting,
+            &mut loading_progress,
+        );
+        if events.get_key((Key::F9, Action::Press, Modifiers::empty())) {
+            let meshes = scene_manager.component_slice::<Mesh>();
+            println!("{}", resource_manager.memory_report(meshes));
+        }
+        if events.get_key((Key::F10, Action::Press, Modifiers::empty())) {
+            renderer.set_debug_cascades(!renderer.debug_cascades());
+        }
         script_iteration(&scripting);
+        animation_iteration(&mut scene_manager, &scripting, frametime);
+        scene_manager.apply_geometry_updates(&mut resource_manager);
         render_iteration(
             &mut window,
             &screen,
-            &renderer,
+            &mut renderer,
             &scene_manager,
             &resource_manager,
             &sleep_time,
@@ -142,15 +188,83 @@ fn process_events(
     events.update_cursor_pos(window.get_cursor_pos());
 }
 
+fn reload_iteration(
+    watcher: &mut AssetWatcher,
+    context_version: Version,
+    scripting: &Scripting,
+    renderer: &mut Renderer,
+    screen: &mut Screen,
+    resource_manager: &mut ResourceManager,
+) {
+    let mut shaders_changed = false;
+
+    for path in watcher.poll() {
+        let extension = Path::new(&path)
+            .extension()
+            .and_then(|item| item.to_str())
+            .map(|item| item.to_lowercase());
+
+        match extension.as_deref() {
+            Some("lua") => {
+                if let Ok(src) = fs::read_to_string(&path) {
+                    scripting.reload_script(&path, &src);
+                }
+            }
+            Some("glsl") => shaders_changed = true,
+            Some(extension) if cook::IMAGE_EXTENSIONS.contains(&extension) => {
+                resource_manager.mesh_manager_mut().reload_texture(&path);
+            }
+            Some(extension) if cook::MODEL_EXTENSIONS.contains(&extension) => {
+                resource_manager.mesh_manager_mut().reload_mesh(&path);
+            }
+            Some("json") if MaterialAsset::name_from_path(&path).is_some() => {
+                resource_manager.reload_material_asset(&path);
+            }
+            // Scenes and clips are read once, other files can be buffers of glTF models
+            Some(_) => resource_manager
+                .mesh_manager_mut()
+                .reload_model_dependency(&path),
+            None => {}
+        }
+    }
+
+    if shaders_changed {
+        renderer.reload_shaders(context_version, resource_manager.vfs());
+        screen.reload_shaders(context_version, resource_manager.vfs());
+    }
+}
+
+fn loading_iteration(
+    scene_manager: &mut SceneManager,
+    resource_manager: &mut ResourceManager,
+    scripting: &Scripting,
+    loading_progress: &mut (usize, usize),
+) {
+    for (owner, mesh) in scene_manager.take_mesh_requests() {
+        resource_manager.request_mesh(owner, &mesh);
+    }
+    let meshes = resource_manager.process_loaded(UPLOAD_BUDGET);
+    scene_manager.attach_loaded_meshes(meshes, scripting);
+    scene_manager.attach_animation_players(resource_manager);
+    *loading_progress = resource_manager.loading_progress();
+}
+
 fn script_iteration(scripting: &Scripting) {
     scripting.run_updates();
     scripting.gc_collect();
 }
 
+// After scripts, so clips they start this frame are posed right away
+fn animation_iteration(scene_manager: &mut SceneManager, scripting: &Scripting, frametime: f64) {
+    scene_manager.update_skeletons(frametime as f32);
+    let events = scene_manager.update_animation_players(frametime as f32);
+    scripting.fire_animation_events(scene_manager, events);
+}
+
 fn render_iteration(
     window: &mut PWindow,
     screen: &Screen,
-    renderer: &Renderer,
+    renderer: &mut Renderer,
     scene_manager: &SceneManager,
     resource_manager: &ResourceManager,
     sleep_period: &Duration,
@@ -1,12 +1,15 @@
 use crate::{
+    asset_path::AssetPath,
     lighting::LightType,
     serializable::{
-        Camera, Entity, LightSource, MaterialInfo, Mesh, PBRChannels, PBRTextures, ScriptObject,
-        Transform, Vec3,
+        Camera, Entity, ImportOptions, LightSource, MaterialInfo, Mesh, PBRChannels, PBRTextures,
+        ScriptObject, Transform, Vec3,
     },
+    vfs::Vfs,
 };
 use serde::Serialize;
 use std::fs;
+use uuid::Uuid;
 
 pub struct Scene {
     pub path: String,
@@ -17,28 +20,45 @@ impl Scene {
         Self { path: path.clone() }
     }
 
-    pub fn read_entities(&self) -> Vec<Entity> {
-        let json_str = fs::read_to_string(&self.path).unwrap();
+    pub fn read_entities(&self, vfs: &Vfs) -> Vec<Entity> {
+        let json_str = vfs.read_to_string(&self.path).unwrap();
         let entities = serde_json::from_str::<Vec<Entity>>(&json_str).unwrap();
         entities
     }
 
+    // Goes through `serde_json::Value` so object keys come out sorted, which keeps
+    // scene files stable under version control
+    pub fn write_entities(path: &str, entities: &Vec<Entity>) {
+        let value = serde_json::to_value(entities).unwrap();
+        let json_str = serde_json::to_string_pretty(&value).unwrap();
+        fs::write(path, json_str).unwrap();
+    }
+
     pub fn sample() {
         let entity1 = Entity {
+            guid: Uuid::new_v4(),
             name: "Object".to_string(),
             transform: Transform::default(),
             children: vec![],
             meshes: vec![Mesh {
-                path: "assets\\meshes\\boulder_01.gltf".to_string(),
+                path: AssetPath::new("asset://meshes/boulder_01.gltf"),
                 material_info: MaterialInfo {
                     pbr_channels: PBRTextures::Merged(PBRChannels::ARM),
+                    ..Default::default()
                 },
+                material: None,
+                import: ImportOptions::default(),
+                hierarchy: false,
+                cameras_and_lights: false,
+                retain_geometry: false,
             }],
             cameras: vec![],
             light_sources: vec![],
             scripts: vec![],
+            animation_players: vec![],
         };
         let entity2 = Entity {
+            guid: Uuid::new_v4(),
             name: "Camera".to_string(),
             transform: Transform::default(),
             children: vec![],
@@ -46,10 +66,12 @@ impl Scene {
             cameras: vec![Camera::default()],
             light_sources: vec![],
             scripts: vec![ScriptObject {
-                script_path: "assets\\scripts\\camera-controller.lua".to_string(),
+                script_path: AssetPath::new("asset://scripts/camera-controller.lua"),
             }],
+            animation_players: vec![],
         };
         let entity3 = Entity {
+            guid: Uuid::new_v4(),
             name: "Light".to_string(),
             transform: Transform::default(),
             children: vec![],
@@ -65,11 +87,13 @@ impl Scene {
                 shadow_distance: 100.0,
                 inner: 0.0,
                 outer: 0.0,
+                shadows: Default::default(),
             }],
             scripts: vec![],
+            animation_players: vec![],
         };
 
-        let str_ = serde_json::to_string(&vec![entity1, entity2, entity3]).unwrap();
-        fs::write("assets\\scenes\\sample.json", str_).unwrap();
+        let path = AssetPath::new("asset://scenes/sample.json").resolve();
+        Self::write_entities(&path, &vec![entity1, entity2, entity3]);
     }
 }
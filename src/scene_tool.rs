@@ -0,0 +1,636 @@
+use crate::{scene::Scene, serializable::Entity};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs, process,
+};
+
+const USAGE: &str = "usage:
+    scene normalize <scene.json>...
+    scene diff <old.json> <new.json>
+    scene merge <base.json> <ours.json> <theirs.json> [-o <output.json>]
+
+merge writes into <ours.json> unless -o is given and exits with 1 on conflicts, so it can
+be used as a git merge driver: `learning_gl scene merge %O %A %B`";
+
+const GUID: &str = "guid";
+const NAME: &str = "name";
+const CHILDREN: &str = "children";
+
+pub fn run(args: &[String]) {
+    match args.first().map(String::as_str) {
+        Some("normalize") if args.len() > 1 => {
+            for path in &args[1..] {
+                normalize(path);
+            }
+        }
+        Some("diff") if args.len() == 3 => {
+            let changes = diff(&read(&args[1]), &read(&args[2]));
+            for change in &changes {
+                println!("{change}");
+            }
+        }
+        Some("merge") if args.len() == 4 || (args.len() == 6 && args[4] == "-o") => {
+            let output = args.get(5).unwrap_or(&args[2]);
+            let (merged, conflicts) = merge(&read(&args[1]), &read(&args[2]), &read(&args[3]));
+            let entities = serde_json::from_value::<Vec<Entity>>(merged).unwrap();
+            Scene::write_entities(output, &entities);
+
+            if !conflicts.is_empty() {
+                for conflict in &conflicts {
+                    eprintln!("{conflict}");
+                }
+                process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            process::exit(2);
+        }
+    }
+}
+
+// Fills in missing guids and rewrites the file with sorted keys
+fn normalize(path: &str) {
+    let json_str = fs::read_to_string(path).unwrap();
+    let entities = serde_json::from_str::<Vec<Entity>>(&json_str).unwrap();
+    Scene::write_entities(path, &entities);
+}
+
+fn read(path: &str) -> Value {
+    let json_str = fs::read_to_string(path).unwrap();
+    let value = serde_json::from_str::<Value>(&json_str).unwrap();
+    if let Err(error) = serde_json::from_value::<Vec<Entity>>(value.clone()) {
+        panic!("{path} is not a valid scene: {error}");
+    }
+    value
+}
+
+#[derive(Clone)]
+struct Node {
+    parent: Option<String>,
+    index: usize,
+    fields: Value, // the entity without its children
+}
+
+impl Node {
+    fn name(&self) -> &str {
+        self.fields[NAME].as_str().unwrap_or_default()
+    }
+
+    fn same_content(&self, other: &Node) -> bool {
+        self.parent == other.parent && self.fields == other.fields
+    }
+}
+
+// Returns entities by guid along with their pre-order
+fn flatten(entities: &Value) -> (BTreeMap<String, Node>, Vec<String>) {
+    fn visit(
+        entities: &Value,
+        parent: Option<&String>,
+        nodes: &mut BTreeMap<String, Node>,
+        order: &mut Vec<String>,
+    ) {
+        for (index, entity) in entities.as_array().unwrap().iter().enumerate() {
+            let guid = match entity[GUID].as_str() {
+                Some(guid) => guid.to_string(),
+                None => panic!("Entity without a guid, run `scene normalize` on the file first"),
+            };
+            let mut fields = entity.clone();
+            let children = fields.as_object_mut().unwrap().remove(CHILDREN);
+
+            let node = Node {
+                parent: parent.cloned(),
+                index,
+                fields,
+            };
+            assert!(
+                nodes.insert(guid.clone(), node).is_none(),
+                "Duplicate entity guid {guid}"
+            );
+            order.push(guid.clone());
+
+            if let Some(children) = children {
+                visit(&children, Some(&guid), nodes, order);
+            }
+        }
+    }
+
+    let mut nodes = BTreeMap::new();
+    let mut order = Vec::new();
+    visit(entities, None, &mut nodes, &mut order);
+    (nodes, order)
+}
+
+pub enum Change {
+    Added {
+        guid: String,
+        name: String,
+        parent: Option<String>,
+    },
+    Removed {
+        guid: String,
+        name: String,
+    },
+    Moved {
+        guid: String,
+        name: String,
+        from: Option<String>,
+        to: Option<String>,
+    },
+    Changed {
+        guid: String,
+        name: String,
+        field: String,
+        old: Option<Value>,
+        new: Option<Value>,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn parent(parent: &Option<String>) -> &str {
+            parent.as_deref().unwrap_or("<root>")
+        }
+        fn value(value: &Option<Value>) -> String {
+            value
+                .as_ref()
+                .map_or("<none>".to_string(), |value| value.to_string())
+        }
+
+        match self {
+            Change::Added { guid, name, parent: p } => {
+                write!(f, "+ {name} ({guid}) under {}", parent(p))
+            }
+            Change::Removed { guid, name } => write!(f, "- {name} ({guid})"),
+            Change::Moved {
+                guid,
+                name,
+                from,
+                to,
+            } => write!(f, "> {name} ({guid}) {} -> {}", parent(from), parent(to)),
+            Change::Changed {
+                guid,
+                name,
+                field,
+                old,
+                new,
+            } => write!(f, "~ {name} ({guid}) {field}: {} -> {}", value(old), value(new)),
+        }
+    }
+}
+
+pub fn diff(old: &Value, new: &Value) -> Vec<Change> {
+    let (old_nodes, old_order) = flatten(old);
+    let (new_nodes, new_order) = flatten(new);
+    let mut changes = Vec::new();
+
+    for guid in &old_order {
+        if !new_nodes.contains_key(guid) {
+            changes.push(Change::Removed {
+                guid: guid.clone(),
+                name: old_nodes[guid].name().to_string(),
+            });
+        }
+    }
+
+    for guid in &new_order {
+        let new_node = &new_nodes[guid];
+        let name = new_node.name().to_string();
+        let old_node = match old_nodes.get(guid) {
+            Some(node) => node,
+            None => {
+                changes.push(Change::Added {
+                    guid: guid.clone(),
+                    name,
+                    parent: new_node.parent.clone(),
+                });
+                continue;
+            }
+        };
+
+        if old_node.parent != new_node.parent {
+            changes.push(Change::Moved {
+                guid: guid.clone(),
+                name: name.clone(),
+                from: old_node.parent.clone(),
+                to: new_node.parent.clone(),
+            });
+        }
+
+        let mut fields = Vec::new();
+        diff_values("", Some(&old_node.fields), Some(&new_node.fields), &mut fields);
+        for (field, old, new) in fields {
+            changes.push(Change::Changed {
+                guid: guid.clone(),
+                name: name.clone(),
+                field,
+                old,
+                new,
+            });
+        }
+    }
+
+    changes
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+fn diff_values(
+    path: &str,
+    old: Option<&Value>,
+    new: Option<&Value>,
+    out: &mut Vec<(String, Option<Value>, Option<Value>)>,
+) {
+    if old == new {
+        return;
+    }
+    match (old, new) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let keys = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+            for key in keys {
+                diff_values(&join(path, key), old.get(key), new.get(key), out);
+            }
+        }
+        (Some(Value::Array(old)), Some(Value::Array(new))) if old.len() == new.len() => {
+            for (i, (old, new)) in old.iter().zip(new).enumerate() {
+                diff_values(&format!("{path}[{i}]"), Some(old), Some(new), out);
+            }
+        }
+        _ => out.push((path.to_string(), old.cloned(), new.cloned())),
+    }
+}
+
+pub struct Conflict {
+    guid: String,
+    name: String,
+    description: String,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "conflict in {} ({}): {}, kept ours",
+            self.name, self.guid, self.description
+        )
+    }
+}
+
+pub fn merge(base: &Value, ours: &Value, theirs: &Value) -> (Value, Vec<Conflict>) {
+    let (base_nodes, _) = flatten(base);
+    let (our_nodes, our_order) = flatten(ours);
+    let (their_nodes, their_order) = flatten(theirs);
+
+    let mut order = our_order;
+    order.extend(
+        their_order
+            .into_iter()
+            .filter(|guid| !our_nodes.contains_key(guid)),
+    );
+
+    let mut conflicts = Vec::new();
+    let mut conflict = |guid: &String, node: &Node, description: String| {
+        conflicts.push(Conflict {
+            guid: guid.clone(),
+            name: node.name().to_string(),
+            description,
+        });
+    };
+
+    let mut merged = BTreeMap::new();
+    for guid in &order {
+        let base = base_nodes.get(guid);
+        let ours = our_nodes.get(guid);
+        let theirs = their_nodes.get(guid);
+
+        let node = match (base, ours, theirs) {
+            (None, Some(ours), None) => Some(ours.clone()),
+            (None, None, Some(theirs)) => Some(theirs.clone()),
+            (None, Some(ours), Some(theirs)) => {
+                if !ours.same_content(theirs) {
+                    conflict(guid, ours, "added on both sides".to_string());
+                }
+                Some(ours.clone())
+            }
+            (Some(base), Some(ours), None) => {
+                if ours.same_content(base) {
+                    None
+                } else {
+                    conflict(guid, ours, "changed in ours, removed in theirs".to_string());
+                    Some(ours.clone())
+                }
+            }
+            (Some(base), None, Some(theirs)) => {
+                if theirs.same_content(base) {
+                    None
+                } else {
+                    conflict(guid, theirs, "removed in ours, changed in theirs".to_string());
+                    Some(theirs.clone())
+                }
+            }
+            (Some(base), Some(ours), Some(theirs)) => {
+                let mut fields = Vec::new();
+                let merged_fields = merge_values(
+                    "",
+                    Some(&base.fields),
+                    Some(&ours.fields),
+                    Some(&theirs.fields),
+                    &mut fields,
+                )
+                .unwrap();
+                for field in fields {
+                    conflict(guid, ours, format!("{field} changed on both sides"));
+                }
+
+                let parent = match merge3(&base.parent, &ours.parent, &theirs.parent) {
+                    Some(parent) => parent,
+                    None => {
+                        conflict(guid, ours, "moved on both sides".to_string());
+                        ours.parent.clone()
+                    }
+                };
+                let index = merge3(&base.index, &ours.index, &theirs.index).unwrap_or(ours.index);
+
+                Some(Node {
+                    parent,
+                    index,
+                    fields: merged_fields,
+                })
+            }
+            (_, None, None) => None,
+        };
+
+        if let Some(node) = node {
+            merged.insert(guid.clone(), node);
+        }
+    }
+
+    // Entities whose parent is gone, or that ended up parented to their own descendant
+    // because of moves on both sides, are placed at the root. Detaching one entity of a
+    // cycle breaks it, entities hanging below a cycle stay where they are
+    let guids = merged.keys().cloned().collect::<Vec<String>>();
+    for guid in &guids {
+        let mut detached = None;
+        let mut visited = BTreeSet::new();
+        let mut current = merged[guid].parent.clone();
+        while let Some(parent) = current {
+            if !merged.contains_key(&parent) {
+                if merged[guid].parent.as_ref() == Some(&parent) {
+                    detached = Some("parent was removed");
+                }
+                break;
+            }
+            if &parent == guid {
+                detached = Some("moves on both sides form a cycle");
+                break;
+            }
+            // A cycle above the entity that it isn't part of
+            if !visited.insert(parent.clone()) {
+                break;
+            }
+            current = merged[&parent].parent.clone();
+        }
+
+        if let Some(reason) = detached {
+            let node = merged.get_mut(guid).unwrap();
+            node.parent = None;
+            conflict(guid, node, format!("{reason}, moved to the root"));
+        }
+    }
+
+    let value = build(None, &merged, &order);
+    (value, conflicts)
+}
+
+fn merge3<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if ours == theirs || theirs == base {
+        Some(ours.clone())
+    } else if ours == base {
+        Some(theirs.clone())
+    } else {
+        None
+    }
+}
+
+// `None` means the value is absent in the result
+fn merge_values(
+    path: &str,
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    conflicts: &mut Vec<String>,
+) -> Option<Value> {
+    if ours == theirs || theirs == base {
+        return ours.cloned();
+    }
+    if ours == base {
+        return theirs.cloned();
+    }
+
+    match (base, ours, theirs) {
+        (Some(Value::Object(base)), Some(Value::Object(ours)), Some(Value::Object(theirs))) => {
+            let keys = base
+                .keys()
+                .chain(ours.keys())
+                .chain(theirs.keys())
+                .collect::<BTreeSet<_>>();
+            let mut result = Map::new();
+            for key in keys {
+                let value = merge_values(
+                    &join(path, key),
+                    base.get(key),
+                    ours.get(key),
+                    theirs.get(key),
+                    conflicts,
+                );
+                if let Some(value) = value {
+                    result.insert(key.clone(), value);
+                }
+            }
+            Some(Value::Object(result))
+        }
+        (Some(Value::Array(base)), Some(Value::Array(ours)), Some(Value::Array(theirs)))
+            if base.len() == ours.len() && base.len() == theirs.len() =>
+        {
+            let mut result = Vec::with_capacity(base.len());
+            for i in 0..base.len() {
+                let value = merge_values(
+                    &format!("{path}[{i}]"),
+                    Some(&base[i]),
+                    Some(&ours[i]),
+                    Some(&theirs[i]),
+                    conflicts,
+                );
+                result.push(value.unwrap_or(Value::Null));
+            }
+            Some(Value::Array(result))
+        }
+        _ => {
+            conflicts.push(path.to_string());
+            ours.cloned()
+        }
+    }
+}
+
+fn build(parent: Option<&String>, nodes: &BTreeMap<String, Node>, order: &[String]) -> Value {
+    let mut children = order
+        .iter()
+        .enumerate()
+        .filter(|(_, guid)| nodes.get(*guid).is_some_and(|node| node.parent.as_ref() == parent))
+        .map(|(position, guid)| (nodes[guid].index, position, guid))
+        .collect::<Vec<_>>();
+    children.sort();
+
+    let entities = children
+        .into_iter()
+        .map(|(_, _, guid)| {
+            let mut fields = nodes[guid].fields.clone();
+            let object = fields.as_object_mut().unwrap();
+            object.insert(CHILDREN.to_string(), build(Some(guid), nodes, order));
+            fields
+        })
+        .collect();
+
+    Value::Array(entities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entity(guid: &str, children: Vec<Value>) -> Value {
+        json!({ GUID: guid, NAME: guid.to_uppercase(), "meshes": [], CHILDREN: children })
+    }
+
+    fn with_field(mut entity: Value, key: &str, value: Value) -> Value {
+        entity[key] = value;
+        entity
+    }
+
+    fn parents(scene: &Value) -> BTreeMap<String, Option<String>> {
+        let (nodes, _) = flatten(scene);
+        nodes
+            .into_iter()
+            .map(|(guid, node)| (guid, node.parent))
+            .collect()
+    }
+
+    fn parent(scene: &Value, guid: &str) -> Option<String> {
+        parents(scene)[guid].clone()
+    }
+
+    #[test]
+    fn diff_reports_every_kind_of_change() {
+        let old = json!([
+            entity("a", vec![entity("b", vec![])]),
+            entity("c", vec![]),
+            entity("d", vec![]),
+        ]);
+        let new = json!([
+            entity("a", vec![]),
+            entity("c", vec![entity("b", vec![])]),
+            with_field(entity("e", vec![]), "meshes", json!([1])),
+            with_field(entity("d", vec![]), NAME, json!("Renamed")),
+        ]);
+
+        let changes = diff(&old, &new);
+        assert_eq!(changes.len(), 3);
+        assert!(changes.iter().any(|change| matches!(
+            change,
+            Change::Moved { guid, from, to, .. }
+                if guid == "b" && from.as_deref() == Some("a") && to.as_deref() == Some("c")
+        )));
+        assert!(changes.iter().any(|change| matches!(
+            change,
+            Change::Added { guid, parent: None, .. } if guid == "e"
+        )));
+        assert!(changes.iter().any(|change| matches!(
+            change,
+            Change::Changed { guid, field, new: Some(new), .. }
+                if guid == "d" && field == NAME && new == "Renamed"
+        )));
+        let old = json!([entity("a", vec![])]);
+        let new = json!([]);
+        assert!(matches!(
+            diff(&old, &new).as_slice(),
+            [Change::Removed { guid, .. }] if guid == "a"
+        ));
+    }
+
+    #[test]
+    fn diff_of_same_scene_is_empty() {
+        let scene = json!([entity("a", vec![entity("b", vec![])])]);
+        assert!(diff(&scene, &scene).is_empty());
+    }
+
+    #[test]
+    fn merge_takes_changes_of_both_sides() {
+        let base = json!([entity("a", vec![]), entity("b", vec![])]);
+        let ours = json!([
+            with_field(entity("a", vec![]), "meshes", json!([1])),
+            entity("b", vec![]),
+            entity("c", vec![]),
+        ]);
+        let theirs = json!([entity("a", vec![entity("b", vec![])])]);
+
+        let (merged, conflicts) = merge(&base, &ours, &theirs);
+        assert!(conflicts.is_empty());
+        let (nodes, _) = flatten(&merged);
+        assert_eq!(nodes["a"].fields["meshes"], json!([1]));
+        assert_eq!(nodes["b"].parent.as_deref(), Some("a"));
+        assert!(nodes.contains_key("c"));
+    }
+
+    #[test]
+    fn merge_keeps_ours_on_conflicting_fields() {
+        let base = json!([entity("a", vec![])]);
+        let ours = json!([with_field(entity("a", vec![]), NAME, json!("Ours"))]);
+        let theirs = json!([with_field(entity("a", vec![]), NAME, json!("Theirs"))]);
+
+        let (merged, conflicts) = merge(&base, &ours, &theirs);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].guid, "a");
+        assert_eq!(merged[0][NAME], json!("Ours"));
+    }
+
+    #[test]
+    fn merge_moves_orphans_to_the_root() {
+        let base = json!([entity("a", vec![]), entity("b", vec![])]);
+        let ours = json!([entity("a", vec![entity("b", vec![])])]);
+        let theirs = json!([entity("b", vec![])]);
+
+        let (merged, conflicts) = merge(&base, &ours, &theirs);
+        // a was removed in theirs and is unchanged in ours, b was moved under it in ours
+        assert_eq!(parents(&merged).len(), 1);
+        assert_eq!(parent(&merged, "b"), None);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].guid, "b");
+    }
+
+    #[test]
+    fn merge_breaks_cycles_without_moving_their_descendants() {
+        // b and c are moved under each other, a hangs below the cycle and sorts first
+        let base = json!([entity("b", vec![entity("a", vec![])]), entity("c", vec![])]);
+        let ours = json!([entity("c", vec![entity("b", vec![entity("a", vec![])])])]);
+        let theirs = json!([entity("b", vec![entity("a", vec![]), entity("c", vec![])])]);
+
+        let (merged, conflicts) = merge(&base, &ours, &theirs);
+        assert_eq!(conflicts.len(), 1);
+        assert_ne!(conflicts[0].guid, "a");
+        assert_eq!(parents(&merged).len(), 3);
+        assert_eq!(parent(&merged, "a").as_deref(), Some("b"));
+        let roots = parents(&merged)
+            .into_values()
+            .filter(|parent| parent.is_none())
+            .count();
+        assert_eq!(roots, 1);
+    }
+}
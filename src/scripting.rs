@@ -1,5 +1,12 @@
 use crate::{
-    entity_system::SceneManager, resources::ResourceManager, runtime::WindowEvents, serializable,
+    animation::{AnimationPlayer, PlayMode, Skeleton},
+    asset_path::AssetPath,
+    data3d::{Mesh, Vertex},
+    entity_system::SceneManager,
+    hot_reload, procedural,
+    resources::ResourceManager,
+    runtime::WindowEvents,
+    serializable,
 };
 use glfw::{Action, Key, Modifiers, MouseButton, PWindow};
 use glm::Vec3;
@@ -26,6 +33,12 @@ impl<'lua> IntoLua<'lua> for Indecies {
 #[derive(Debug)]
 pub struct CompiledScript(Vec<u8>);
 
+impl CompiledScript {
+    pub fn data(&self) -> &[u8] {
+        &self.0
+    }
+}
+
 #[derive(Debug)]
 pub struct ScriptObject(RegistryKey);
 
@@ -36,6 +49,7 @@ pub struct Scripting {
     entity_handlers: RegistryKey,
     starts: RegistryKey,
     updates: RegistryKey,
+    script_paths: RegistryKey,
 }
 
 impl Scripting {
@@ -46,6 +60,7 @@ impl Scripting {
         let entity_handlers = Self::create_table(&lua, None);
         let starts = Self::create_table(&lua, Some("kv"));
         let updates = Self::create_table(&lua, Some("kv"));
+        let script_paths = Self::create_table(&lua, Some("k"));
 
         Self {
             lua,
@@ -53,6 +68,7 @@ impl Scripting {
             entity_handlers,
             starts,
             updates,
+            script_paths,
         }
     }
 
@@ -76,11 +92,12 @@ impl Scripting {
             .lua
             .registry_value::<Table>(&self.creation_functions)
             .unwrap();
-        let function = match creation_functions.get::<&str, Function>(&script.script_path) {
+        let path = script.script_path.resolve();
+        let function = match creation_functions.get::<&str, Function>(path.as_str()) {
             Ok(function) => function,
             Err(_) => {
                 let src = resource_manager.get_script(script);
-                self.load_script_object(&src, &script.script_path)
+                self.load_script_object(&src, &path)
             }
         };
 
@@ -103,6 +120,9 @@ impl Scripting {
             starts.set(object.clone(), start).unwrap();
         }
 
+        let script_paths = self.lua.registry_value::<Table>(&self.script_paths).unwrap();
+        script_paths.set(object.clone(), path).unwrap();
+
         let key = self.lua.create_registry_value(object).unwrap();
 
         ScriptObject(key)
@@ -150,10 +170,15 @@ impl Scripting {
         events: &WindowEvents,
         window: &PWindow,
         frametime: &f64,
+        loading_progress: &(usize, usize),
     ) {
         TransformApi::create_wrappers(&self.lua, scene_manager);
+        EntityApi::create_wrappers(&self.lua, scene_manager, &self.entity_handlers);
+        AnimationApi::create_wrappers(&self.lua, scene_manager);
+        AnimationPlayerApi::create_wrappers(&self.lua, scene_manager);
+        MeshApi::create_wrappers(&self.lua, scene_manager);
         InputApi::create_wrappers(&self.lua, events, window);
-        OtherApi::create_wrappers(&self.lua, frametime);
+        OtherApi::create_wrappers(&self.lua, frametime, loading_progress);
     }
 
     pub fn run_updates(&self) {
@@ -163,13 +188,39 @@ impl Scripting {
             .unwrap();
     }
 
+    // Calls `onAnimationEvent(self, clip, event)` of every script object of the entity that
+    // has it
+    pub fn fire_animation_events(
+        &self,
+        scene_manager: &SceneManager,
+        events: Vec<(usize, String, String)>,
+    ) {
+        for (owner_id, clip, event) in events {
+            // Collected first, callbacks may create or delete entities
+            let objects = scene_manager
+                .component_slice::<ScriptObject>()
+                .iter()
+                .filter(|item| item.owner_id() == owner_id)
+                .map(|item| self.lua.registry_value::<Table>(&item.data.0).unwrap())
+                .collect::<Vec<Table>>();
+            for object in objects {
+                if let Ok(callback) = object.get::<_, Function>("onAnimationEvent") {
+                    callback
+                        .call::<_, ()>((object, clip.as_str(), event.as_str()))
+                        .unwrap();
+                }
+            }
+        }
+    }
+
     pub fn compile_script(&self, src: &str, name: &str) -> Result<CompiledScript> {
         let chunk = self.lua.load(src).set_name(name);
         let dumped = chunk.into_function()?.dump(false);
         Ok(CompiledScript(dumped))
     }
 
-    fn load_script_object(&self, src: &str, name: &str) -> Function {
+    // `src` is either source text or bytecode from the cook
+    fn load_script_object(&self, src: &[u8], name: &str) -> Function {
         let function = self.lua.load(src).eval::<Function>().unwrap();
         let creation_functions = self
             .lua
@@ -179,22 +230,80 @@ impl Scripting {
         function
     }
 
+    // Live objects are patched in place rather than recreated, so registry keys held by
+    // components and references between scripts stay valid. Fields the new version
+    // doesn't know about are kept, methods and metatables come from the new version
+    pub fn reload_script(&self, path: &str, src: &str) {
+        let creation_functions = self
+            .lua
+            .registry_value::<Table>(&self.creation_functions)
+            .unwrap();
+        let names = creation_functions
+            .clone()
+            .pairs::<String, Function>()
+            .filter_map(|pair| pair.ok())
+            .map(|(name, _)| name)
+            .filter(|name| hot_reload::same_file(name, path))
+            .collect::<Vec<String>>();
+
+        for name in names {
+            let function = match self.lua.load(src).set_name(&name).eval::<Function>() {
+                Ok(function) => function,
+                Err(error) => {
+                    println!("Failed to reload {name}: {error}");
+                    continue;
+                }
+            };
+            creation_functions.set(name.as_str(), function.clone()).unwrap();
+
+            let script_paths = self.lua.registry_value::<Table>(&self.script_paths).unwrap();
+            let objects = script_paths
+                .clone()
+                .pairs::<Table, String>()
+                .filter_map(|pair| pair.ok())
+                .filter(|(_, object_path)| *object_path == name)
+                .map(|(object, _)| object)
+                .collect::<Vec<Table>>();
+
+            for object in objects {
+                if let Err(error) = self.patch_script_object(&object, &function) {
+                    println!("Failed to reload an object of {name}: {error}");
+                }
+            }
+        }
+    }
+
+    fn patch_script_object(&self, object: &Table, function: &Function) -> Result<()> {
+        let fresh = function.call::<_, Table>(())?;
+        for pair in fresh.clone().pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            let old = object.raw_get::<_, Value>(key.clone())?;
+            if old.is_nil() || matches!(value, Value::Function(_)) {
+                object.raw_set(key, value)?;
+            }
+        }
+        object.set_metatable(fresh.get_metatable());
+
+        let updates = self.lua.registry_value::<Table>(&self.updates)?;
+        match object.get::<_, Function>("update") {
+            Ok(update) => updates.set(object.clone(), update)?,
+            Err(_) => updates.set(object.clone(), Nil)?,
+        }
+        Ok(())
+    }
+
     pub fn gc_collect(&self) {
         self.lua.gc_collect();
     }
 
-    // pub fn delete_script_object(&self, script_object: ScriptObject) {
-    //     let object = self.lua.registry_value::<Table>(&script_object.0).unwrap();
-    //     self.object_owners
-    //         .as_table(&self.lua)
-    //         .unwrap()
-    //         .set(object.clone(), Value::Nil);
-    //     self.updates
-    //         .as_table(&self.lua)
-    //         .unwrap()
-    //         .set(object.clone(), Value::Nil);
-    //     self.lua.remove_registry_value(script_object.0);
-    // }
+    pub fn delete_script_object(&self, script_object: ScriptObject) {
+        let object = self.lua.registry_value::<Table>(&script_object.0).unwrap();
+        for registry in [&self.updates, &self.starts, &self.script_paths] {
+            let table = self.lua.registry_value::<Table>(registry).unwrap();
+            table.set(object.clone(), Nil).unwrap();
+        }
+        self.lua.remove_registry_value(script_object.0).unwrap();
+    }
 }
 
 struct TransformApi;
@@ -374,6 +483,417 @@ impl TransformApi {
     }
 }
 
+struct EntityApi;
+
+impl EntityApi {
+    fn create_wrappers(lua: &Lua, scene_manager: &mut SceneManager, entity_handlers: &RegistryKey) {
+        // The function keeps its own key to the table of handlers
+        let handlers = lua.registry_value::<Table>(entity_handlers).unwrap();
+        let handlers = lua.create_registry_value(handlers).unwrap();
+        let find_child = lua
+            .create_function(Self::find_child(scene_manager, handlers))
+            .unwrap();
+
+        let entity = lua.create_table().unwrap();
+        entity.set("findChild", find_child).unwrap();
+        lua.globals().set("Entity", entity).unwrap();
+    }
+
+    // Searches the whole subtree, so nodes of a model spawned as a hierarchy are found
+    // by their names
+    fn find_child(
+        scene_manager: *const SceneManager,
+        handlers: RegistryKey,
+    ) -> impl for<'lua> Fn(&'lua Lua, (Table<'lua>, String)) -> Result<Option<Table<'lua>>> {
+        move |lua: &Lua, args: (Table, String)| {
+            let scene_manager = unsafe { &*scene_manager };
+            let metatable = TransformApi::get_metatable(args.0)?;
+            let entity = metatable.get::<_, Table>(Indecies::EntityHandler)?;
+            let id = entity.get::<_, IdWrapper>(Indecies::Id)?.0;
+
+            match scene_manager.find_descendant(id, &args.1) {
+                Some(child) => {
+                    let handlers = lua.registry_value::<Table>(&handlers)?;
+                    let handler = handlers.get::<_, Table>(child)?;
+                    Ok(Some(handler.get::<_, Table>(Indecies::EntityWeakRef)?))
+                }
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+struct AnimationApi;
+
+impl AnimationApi {
+    fn create_wrappers(lua: &Lua, scene_manager: &mut SceneManager) {
+        let play = lua.create_function(Self::play(scene_manager)).unwrap();
+        let cross_fade = lua
+            .create_function(Self::cross_fade(scene_manager))
+            .unwrap();
+        let blend = lua.create_function(Self::blend(scene_manager)).unwrap();
+        let set_speed = lua.create_function(Self::set_speed(scene_manager)).unwrap();
+        let stop = lua.create_function(Self::stop(scene_manager)).unwrap();
+        let is_playing = lua
+            .create_function(Self::is_playing(scene_manager))
+            .unwrap();
+        let clips = lua.create_function(Self::clips(scene_manager)).unwrap();
+
+        let animation = lua.create_table().unwrap();
+        animation.set("play", play).unwrap();
+        animation.set("crossFade", cross_fade).unwrap();
+        animation.set("blend", blend).unwrap();
+        animation.set("setSpeed", set_speed).unwrap();
+        animation.set("stop", stop).unwrap();
+        animation.set("isPlaying", is_playing).unwrap();
+        animation.set("getClips", clips).unwrap();
+        lua.globals().set("Animation", animation).unwrap();
+    }
+
+    // Skeleton of the entity or of its closest ancestor that has one. There is none until
+    // the model has loaded, functions return false then
+    fn get_skeleton<'a>(
+        scene_manager: *mut SceneManager,
+        weak_ref: Table,
+    ) -> Result<Option<&'a mut Skeleton>> {
+        let scene_manager = unsafe { &mut *scene_manager };
+        let metatable = TransformApi::get_metatable(weak_ref)?;
+        let entity = metatable.get::<_, Table>(Indecies::EntityHandler)?;
+        let id = entity.get::<_, IdWrapper>(Indecies::Id)?.0;
+
+        Ok(scene_manager
+            .skeleton_owner(id)
+            .and_then(|owner| scene_manager.get_skeleton_mut(owner)))
+    }
+
+    const fn play(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, (Table<'_>, String, Option<bool>)) -> Result<bool> {
+        move |_: &Lua, args: (Table, String, Option<bool>)| {
+            let looping = args.2.unwrap_or(true);
+            Ok(Self::get_skeleton(scene_manager, args.0)?
+                .is_some_and(|skeleton| skeleton.play(&args.1, looping)))
+        }
+    }
+
+    const fn cross_fade(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, (Table<'_>, String, f32, Option<bool>)) -> Result<bool> {
+        move |_: &Lua, args: (Table, String, f32, Option<bool>)| {
+            let looping = args.3.unwrap_or(true);
+            Ok(Self::get_skeleton(scene_manager, args.0)?
+                .is_some_and(|skeleton| skeleton.cross_fade(&args.1, args.2, looping)))
+        }
+    }
+
+    const fn blend(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, (Table<'_>, String, f32, Option<bool>)) -> Result<bool> {
+        move |_: &Lua, args: (Table, String, f32, Option<bool>)| {
+            let looping = args.3.unwrap_or(true);
+            Ok(Self::get_skeleton(scene_manager, args.0)?
+                .is_some_and(|skeleton| skeleton.blend(&args.1, args.2, looping)))
+        }
+    }
+
+    const fn set_speed(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, (Table<'_>, String, f32)) -> Result<bool> {
+        move |_: &Lua, args: (Table, String, f32)| {
+            Ok(Self::get_skeleton(scene_manager, args.0)?
+                .is_some_and(|skeleton| skeleton.set_speed(&args.1, args.2)))
+        }
+    }
+
+    const fn stop(scene_manager: *mut SceneManager) -> impl Fn(&Lua, Table<'_>) -> Result<()> {
+        move |_: &Lua, weak_ref: Table| {
+            if let Some(skeleton) = Self::get_skeleton(scene_manager, weak_ref)? {
+                skeleton.stop();
+            }
+            Ok(())
+        }
+    }
+
+    const fn is_playing(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, (Table<'_>, String)) -> Result<bool> {
+        move |_: &Lua, args: (Table, String)| {
+            Ok(Self::get_skeleton(scene_manager, args.0)?
+                .is_some_and(|skeleton| skeleton.is_playing(&args.1)))
+        }
+    }
+
+    const fn clips(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, Table<'_>) -> Result<Vec<String>> {
+        move |_: &Lua, weak_ref: Table| {
+            let skeleton = Self::get_skeleton(scene_manager, weak_ref)?;
+            Ok(skeleton.map_or(Vec::new(), |skeleton| {
+                skeleton.clip_names().map(|name| name.to_string()).collect()
+            }))
+        }
+    }
+}
+
+struct AnimationPlayerApi;
+
+impl AnimationPlayerApi {
+    fn create_wrappers(lua: &Lua, scene_manager: &mut SceneManager) {
+        let play = lua.create_function(Self::play(scene_manager)).unwrap();
+        let pause = lua.create_function(Self::pause(scene_manager)).unwrap();
+        let resume = lua.create_function(Self::resume(scene_manager)).unwrap();
+        let stop = lua.create_function(Self::stop(scene_manager)).unwrap();
+        let is_playing = lua
+            .create_function(Self::is_playing(scene_manager))
+            .unwrap();
+        let set_speed = lua.create_function(Self::set_speed(scene_manager)).unwrap();
+        let set_mode = lua.create_function(Self::set_mode(scene_manager)).unwrap();
+
+        let animation_player = lua.create_table().unwrap();
+        animation_player.set("play", play).unwrap();
+        animation_player.set("pause", pause).unwrap();
+        animation_player.set("resume", resume).unwrap();
+        animation_player.set("stop", stop).unwrap();
+        animation_player.set("isPlaying", is_playing).unwrap();
+        animation_player.set("setSpeed", set_speed).unwrap();
+        animation_player.set("setMode", set_mode).unwrap();
+        lua.globals()
+            .set("AnimationPlayer", animation_player)
+            .unwrap();
+    }
+
+    // Functions return false when the entity has no player
+    fn get_player<'a>(
+        scene_manager: *mut SceneManager,
+        weak_ref: Table,
+    ) -> Result<Option<&'a mut AnimationPlayer>> {
+        let scene_manager = unsafe { &mut *scene_manager };
+        let metatable = TransformApi::get_metatable(weak_ref)?;
+        let entity = metatable.get::<_, Table>(Indecies::EntityHandler)?;
+        let id = entity.get::<_, IdWrapper>(Indecies::Id)?.0;
+
+        Ok(scene_manager.get_animation_player_mut(id))
+    }
+
+    const fn play(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, (Table<'_>, String)) -> Result<bool> {
+        move |_: &Lua, args: (Table, String)| {
+            Ok(Self::get_player(scene_manager, args.0)?.is_some_and(|player| player.play(&args.1)))
+        }
+    }
+
+    const fn pause(scene_manager: *mut SceneManager) -> impl Fn(&Lua, Table<'_>) -> Result<bool> {
+        move |_: &Lua, weak_ref: Table| {
+            let player = Self::get_player(scene_manager, weak_ref)?;
+            Ok(player.map(|player| player.pause()).is_some())
+        }
+    }
+
+    const fn resume(scene_manager: *mut SceneManager) -> impl Fn(&Lua, Table<'_>) -> Result<bool> {
+        move |_: &Lua, weak_ref: Table| {
+            let player = Self::get_player(scene_manager, weak_ref)?;
+            Ok(player.map(|player| player.resume()).is_some())
+        }
+    }
+
+    const fn stop(scene_manager: *mut SceneManager) -> impl Fn(&Lua, Table<'_>) -> Result<bool> {
+        move |_: &Lua, weak_ref: Table| {
+            let player = Self::get_player(scene_manager, weak_ref)?;
+            Ok(player.map(|player| player.stop()).is_some())
+        }
+    }
+
+    const fn is_playing(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, Table<'_>) -> Result<bool> {
+        move |_: &Lua, weak_ref: Table| {
+            let player = Self::get_player(scene_manager, weak_ref)?;
+            Ok(player.is_some_and(|player| player.is_playing()))
+        }
+    }
+
+    const fn set_speed(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, (Table<'_>, f32)) -> Result<bool> {
+        move |_: &Lua, args: (Table, f32)| {
+            let player = Self::get_player(scene_manager, args.0)?;
+            Ok(player.map(|player| player.speed = args.1).is_some())
+        }
+    }
+
+    const fn set_mode(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, (Table<'_>, String)) -> Result<bool> {
+        move |_: &Lua, args: (Table, String)| {
+            let mode = match args.1.as_str() {
+                "once" => PlayMode::Once,
+                "loop" => PlayMode::Loop,
+                "ping_pong" => PlayMode::PingPong,
+                _ => return Err(Error::external(CustomError("Invalid argument".to_string()))),
+            };
+            let player = Self::get_player(scene_manager, args.0)?;
+            Ok(player.map(|player| player.mode = mode).is_some())
+        }
+    }
+}
+
+struct MeshApi;
+
+impl MeshApi {
+    fn create_wrappers(lua: &Lua, scene_manager: &mut SceneManager) {
+        let set_morph_weight = lua
+            .create_function(Self::set_morph_weight(scene_manager))
+            .unwrap();
+        let get_morph_weight = lua
+            .create_function(Self::get_morph_weight(scene_manager))
+            .unwrap();
+        let get_morph_targets = lua
+            .create_function(Self::get_morph_targets(scene_manager))
+            .unwrap();
+        let add = lua.create_function(Self::add(scene_manager)).unwrap();
+        let set_geometry = lua
+            .create_function(Self::set_geometry(scene_manager))
+            .unwrap();
+
+        let mesh = lua.create_table().unwrap();
+        mesh.set("add", add).unwrap();
+        mesh.set("setGeometry", set_geometry).unwrap();
+        mesh.set("setMorphWeight", set_morph_weight).unwrap();
+        mesh.set("getMorphWeight", get_morph_weight).unwrap();
+        mesh.set("getMorphTargets", get_morph_targets).unwrap();
+        lua.globals().set("Mesh", mesh).unwrap();
+    }
+
+    // There is no mesh until the model has loaded
+    fn get_mesh<'a>(
+        scene_manager: *mut SceneManager,
+        weak_ref: Table,
+    ) -> Result<Option<&'a mut Mesh>> {
+        let scene_manager = unsafe { &mut *scene_manager };
+        let metatable = TransformApi::get_metatable(weak_ref)?;
+        let entity = metatable.get::<_, Table>(Indecies::EntityHandler)?;
+        let id = entity.get::<_, IdWrapper>(Indecies::Id)?.0;
+
+        Ok(scene_manager.get_mesh_mut(id))
+    }
+
+    const fn add(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, (Table<'_>, String, Option<String>)) -> Result<()> {
+        move |_: &Lua, args: (Table, String, Option<String>)| {
+            let scene_manager = unsafe { &mut *scene_manager };
+            let metatable = TransformApi::get_metatable(args.0)?;
+            let entity = metatable.get::<_, Table>(Indecies::EntityHandler)?;
+            let id = entity.get::<_, IdWrapper>(Indecies::Id)?.0;
+
+            scene_manager.request_mesh(id, AssetPath::new(&args.1), args.2);
+            Ok(())
+        }
+    }
+
+    const fn set_geometry(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, (Table<'_>, Table<'_>)) -> Result<()> {
+        move |_: &Lua, args: (Table, Table)| {
+            let scene_manager = unsafe { &mut *scene_manager };
+            let metatable = TransformApi::get_metatable(args.0)?;
+            let entity = metatable.get::<_, Table>(Indecies::EntityHandler)?;
+            let id = entity.get::<_, IdWrapper>(Indecies::Id)?.0;
+
+            let (vertices, indices) = Self::read_geometry(args.1)?;
+            scene_manager.update_geometry(id, vertices, indices);
+            Ok(())
+        }
+    }
+
+    // Flat arrays of numbers, three per position and normal, two per uv and four per color.
+    // Indices start at 1 like Lua arrays, without them every three vertices are a triangle
+    fn read_geometry(geometry: Table) -> Result<(Vec<Vertex>, Vec<u32>)> {
+        let invalid = || Error::external(CustomError("Invalid geometry".to_string()));
+        let positions = geometry.get::<_, Vec<f32>>("positions")?;
+        let normals = geometry.get::<_, Option<Vec<f32>>>("normals")?;
+        let uvs = geometry.get::<_, Option<Vec<f32>>>("uvs")?;
+        let colors = geometry.get::<_, Option<Vec<f32>>>("colors")?;
+        let indices = geometry.get::<_, Option<Vec<u32>>>("indices")?;
+
+        let count = positions.len() / 3;
+        let fits = |array: &Option<Vec<f32>>, components: usize| {
+            array
+                .as_ref()
+                .map_or(true, |array| array.len() == count * components)
+        };
+        if positions.len() % 3 != 0 || !fits(&normals, 3) || !fits(&uvs, 2) || !fits(&colors, 4) {
+            return Err(invalid());
+        }
+        let indices = match indices {
+            Some(indices) => indices
+                .into_iter()
+                .map(|index| index.wrapping_sub(1))
+                .collect::<Vec<u32>>(),
+            None => (0..count as u32).collect(),
+        };
+        if indices.len() % 3 != 0 || indices.iter().any(|index| *index as usize >= count) {
+            return Err(invalid());
+        }
+
+        let mut vertices = (0..count)
+            .map(|i| {
+                let vec3 =
+                    |array: &[f32]| glm::vec3(array[i * 3], array[i * 3 + 1], array[i * 3 + 2]);
+                let normal = normals.as_deref().map_or(Vec3::zeros(), vec3);
+                let uv = uvs
+                    .as_ref()
+                    .map_or((0.0, 0.0), |uvs| (uvs[i * 2], uvs[i * 2 + 1]));
+                let mut vertex = procedural::vertex(vec3(&positions), normal, Vec3::zeros(), uv);
+                if let Some(colors) = &colors {
+                    vertex.color.r = colors[i * 4];
+                    vertex.color.g = colors[i * 4 + 1];
+                    vertex.color.b = colors[i * 4 + 2];
+                    vertex.color.a = colors[i * 4 + 3];
+                }
+                vertex
+            })
+            .collect::<Vec<Vertex>>();
+        if normals.is_none() {
+            procedural::smooth_normals(&mut vertices, &indices);
+        }
+        Ok((vertices, indices))
+    }
+
+    const fn set_morph_weight(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, (Table<'_>, String, f32)) -> Result<bool> {
+        move |_: &Lua, args: (Table, String, f32)| {
+            Ok(Self::get_mesh(scene_manager, args.0)?
+                .is_some_and(|mesh| mesh.set_morph_weight(&args.1, args.2)))
+        }
+    }
+
+    const fn get_morph_weight(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, (Table<'_>, String)) -> Result<Option<f32>> {
+        move |_: &Lua, args: (Table, String)| {
+            let mesh = Self::get_mesh(scene_manager, args.0)?;
+            Ok(mesh.and_then(|mesh| mesh.morph_weight(&args.1)))
+        }
+    }
+
+    const fn get_morph_targets(
+        scene_manager: *mut SceneManager,
+    ) -> impl Fn(&Lua, Table<'_>) -> Result<Vec<String>> {
+        move |_: &Lua, weak_ref: Table| {
+            let mesh = Self::get_mesh(scene_manager, weak_ref)?;
+            Ok(mesh.map_or(Vec::new(), |mesh| {
+                mesh.morph_weights
+                    .iter()
+                    .map(|(name, _)| name.clone())
+                    .collect()
+            }))
+        }
+    }
+}
+
 struct InputApi;
 
 impl InputApi {
@@ -759,14 +1279,24 @@ impl InputApi {
 struct OtherApi;
 
 impl OtherApi {
-    fn create_wrappers(lua: &Lua, frametime: &f64) {
+    fn create_wrappers(lua: &Lua, frametime: &f64, loading_progress: &(usize, usize)) {
         let frametime = lua.create_function(Self::frametime(frametime)).unwrap();
         lua.globals().set("FrameTime", frametime).unwrap();
+        let loading_progress = lua
+            .create_function(Self::loading_progress(loading_progress))
+            .unwrap();
+        lua.globals().set("LoadingProgress", loading_progress).unwrap();
     }
 
     fn frametime(frametime: *const f64) -> impl Fn(&Lua, ()) -> Result<f64> {
         move |_: &Lua, ()| Ok(unsafe { *frametime })
     }
+
+    fn loading_progress(
+        loading_progress: *const (usize, usize),
+    ) -> impl Fn(&Lua, ()) -> Result<(usize, usize)> {
+        move |_: &Lua, ()| Ok(unsafe { *loading_progress })
+    }
 }
 
 // impl ScriptingApi {
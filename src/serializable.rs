@@ -1,12 +1,19 @@
 use crate::{
+    animation::{self, ClipEvent, Key, MorphKeys, PlayMode, TransformKeys},
+    asset_path::AssetPath,
     camera,
     lighting::{self, LightData, LightType},
-    linear::{self, Projection},
+    linear::{self, Projection, RotationOrder},
+    material::{AlphaMode, MaterialFactors},
 };
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 #[derive(Serialize, Deserialize)]
 pub struct Entity {
+    // Identifies the entity across edits, used when diffing and merging scene files
+    #[serde(default = "Uuid::new_v4")]
+    pub guid: Uuid,
     pub name: String,
     pub transform: Transform,
     pub children: Vec<Entity>,
@@ -14,13 +21,18 @@ pub struct Entity {
     pub cameras: Vec<Camera>,
     pub light_sources: Vec<LightSource>,
     pub scripts: Vec<ScriptObject>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub animation_players: Vec<AnimationPlayer>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
 pub struct Transform {
     pub position: Vec3,
-    pub orientation: Vec3, // This could be needed to be replaced with Quaternions when loading complex rotation
+    pub orientation: Orientation,
     pub scale: Vec3,
+    // Column-major 4x4, as exported by DCC tools. When present it overrides the fields above
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matrix: Option<[f32; 16]>,
 }
 
 impl Default for Transform {
@@ -33,6 +45,7 @@ impl Default for Transform {
                 y: 1.0,
                 z: 1.0,
             },
+            matrix: None,
         }
     }
 }
@@ -41,14 +54,66 @@ impl Into<linear::Transform> for Transform {
     fn into(self) -> linear::Transform {
         let mut result = linear::Transform::new();
 
-        result.position = self.position.into();
-        result.scale = self.scale.into();
-        result.set_orientation(&self.orientation.into());
+        if let Some(matrix) = self.matrix {
+            let (position, orientation, scale) = linear::decompose(&glm::make_mat4(&matrix));
+            result.position = position;
+            result.orientation = orientation;
+            result.scale = scale;
+        } else {
+            result.position = self.position.into();
+            result.scale = self.scale.into();
+            result.orientation = self.orientation.into();
+        }
 
         result
     }
 }
 
+// Variants are tried in order, so the plain Euler form has to stay last
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(untagged)]
+pub enum Orientation {
+    Quaternion {
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+    },
+    Euler {
+        angles: Vec3, // degrees
+        #[serde(default)]
+        order: RotationOrder,
+    },
+    AxisAngle {
+        axis: Vec3,
+        angle: f32, // degrees
+    },
+    // Degrees around world axes in XYZ order, the format older scenes are written in
+    EulerXYZ(Vec3),
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Orientation::EulerXYZ(Default::default())
+    }
+}
+
+impl Into<glm::Quat> for Orientation {
+    fn into(self) -> glm::Quat {
+        match self {
+            Orientation::Quaternion { x, y, z, w } => glm::quat_normalize(&glm::quat(x, y, z, w)),
+            Orientation::Euler { angles, order } => linear::euler_to_quat(&angles.into(), order),
+            Orientation::AxisAngle { axis, angle } => {
+                let axis: glm::Vec3 = axis.into();
+                glm::quat_angle_axis(angle.to_radians(), &glm::normalize(&axis))
+            }
+            Orientation::EulerXYZ(angles) => {
+                linear::euler_to_quat(&angles.into(), RotationOrder::XYZ)
+            }
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Default, Clone, Copy)]
 pub struct Vec3 {
     pub x: f32,
@@ -62,16 +127,78 @@ impl Into<glm::Vec3> for Vec3 {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Mesh {
-    pub path: String,
+    pub path: AssetPath,
     pub material_info: MaterialInfo,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>, // name of a material asset, overrides the model's own materials
+    #[serde(default, skip_serializing_if = "ImportOptions::is_default")]
+    pub import: ImportOptions,
+    // Spawns the nodes of the model as child entities, which scripts can move on their own,
+    // instead of giving the entity a single `Mesh`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hierarchy: bool,
+    // Also spawns the cameras and lights defined in the model, on the nodes they belong to.
+    // Needs `hierarchy`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cameras_and_lights: bool,
+    // Keeps CPU copies of the vertices and indices, for raycasts and colliders. Off by
+    // default as it doubles the memory the model's meshes take
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub retain_geometry: bool,
 }
 
-#[derive(Serialize, Deserialize, Default)]
+// Fixes for models that were exported badly, applied when the file is imported. The same
+// model imported with different options is cached separately
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct ImportOptions {
+    pub scale: f32,
+    pub up_axis: UpAxis,
+    pub flip_uvs: bool,
+    pub smooth_normals: bool, // replaces the normals of the file, missing ones are always generated
+    pub split_large_meshes: bool,
+    pub optimize_graph: bool, // ignored for hierarchies, their nodes are kept
+    pub max_bone_weights: Option<u32>,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            up_axis: UpAxis::Y,
+            flip_uvs: false,
+            smooth_normals: false,
+            split_large_meshes: false,
+            optimize_graph: true,
+            max_bone_weights: None,
+        }
+    }
+}
+
+impl ImportOptions {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+// Axis the model was authored with pointing up, it is rotated to Y up on import
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum UpAxis {
+    #[default]
+    Y,
+    Z,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
 pub struct MaterialInfo {
     // pub textures: Textures,
     pub pbr_channels: PBRTextures,
+    #[serde(default)]
+    pub sampler: SamplerInfo,
+    #[serde(default)]
+    pub texture_settings: MaterialTextureSettings,
 }
 
 #[rustfmt::skip]
@@ -89,7 +216,103 @@ pub enum Textures {
     },
 }
 
+// Contents of a `.material.json` asset, texture paths are relative to the asset file
 #[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct MaterialAsset {
+    pub textures: MaterialTextures,
+    pub pbr_channels: PBRTextures,
+    pub factors: MaterialFactors,
+    pub alpha_mode: AlphaMode,
+    pub two_sided: bool,
+    pub sampler: SamplerInfo,
+    pub texture_settings: MaterialTextureSettings,
+}
+
+#[rustfmt::skip]
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct MaterialTextures {
+    pub base_color:     Option<String>,
+    pub metalness:      Option<String>,
+    pub roughness:      Option<String>,
+    pub ao:             Option<String>,
+    pub normals:        Option<String>,
+    pub displacement:   Option<String>,
+    pub emissive:       Option<String>,
+}
+
+// Overrides for single maps of a material, everything left out comes from the material
+#[rustfmt::skip]
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct MaterialTextureSettings {
+    pub base_color:     TextureSettings,
+    pub metalness:      TextureSettings,
+    pub roughness:      TextureSettings,
+    pub ao:             TextureSettings,
+    pub normals:        TextureSettings,
+    pub displacement:   TextureSettings,
+    pub emissive:       TextureSettings,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+#[serde(default)]
+pub struct TextureSettings {
+    // sRGB for base color and emissive maps, linear for data maps when not given
+    pub color_space: Option<ColorSpace>,
+    pub sampler: Option<SamplerInfo>,
+}
+
+impl TextureSettings {
+    pub fn srgb(&self, by_default: bool) -> bool {
+        self.color_space
+            .map_or(by_default, |color_space| color_space == ColorSpace::Srgb)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(default)]
+pub struct SamplerInfo {
+    pub wrap_s: WrapMode,
+    pub wrap_t: WrapMode,
+    pub filter: FilterMode,
+    pub mipmaps: bool,   // generated on load unless the file has its own
+    pub anisotropy: u32, // 1 turns it off, capped by what the driver supports
+}
+
+impl Default for SamplerInfo {
+    fn default() -> Self {
+        Self {
+            wrap_s: WrapMode::Repeat,
+            wrap_t: WrapMode::Repeat,
+            filter: FilterMode::Linear,
+            mipmaps: true,
+            anisotropy: 8,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WrapMode {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FilterMode {
+    Nearest,
+    Linear,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
 // Metalness, Roughness, Ambient-occlusion channels in a pbr texture
 pub enum PBRTextures {
     #[default]
@@ -97,7 +320,7 @@ pub enum PBRTextures {
     Merged(PBRChannels),
 }
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
 pub enum PBRChannels {
     #[default]
     ARM,
@@ -161,6 +384,8 @@ pub struct LightSource {
     pub inner: f32,
     pub outer: f32,
     pub shadow_distance: f32,
+    #[serde(default)]
+    pub shadows: ShadowSettings,
 }
 
 impl Into<lighting::LightSource> for LightSource {
@@ -170,11 +395,136 @@ impl Into<lighting::LightSource> for LightSource {
             LightType::Point => LightData::new_point(self.color.into()),
             LightType::Spot => LightData::new_spot(self.color.into(), self.inner, self.outer),
         };
-        lighting::LightSource::new(light_data, self.shadow_distance)
+        lighting::LightSource::new(light_data, self.shadow_distance, self.shadows)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    pub resolution: u32,    // of the map, or of each face for point lights
+    pub bias: f32,          // subtracted from the depth of the fragment
+    pub normal_bias: f32,   // world units the fragment is pushed out along its normal
+    pub filter_radius: u32, // in texels, 0 takes a single sample
+    pub cascades: u32,      // directional lights only, up to lighting::MAX_CASCADES
+    pub cascade_split: f32, // 0 spaces cascades evenly, 1 logarithmically
+    pub cascade_blend: f32, // fraction of a cascade faded into the next one
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            resolution: 1024,
+            bias: 0.002,
+            normal_bias: 0.02,
+            filter_radius: 1,
+            cascades: 4,
+            cascade_split: 0.75,
+            cascade_blend: 0.1,
+        }
     }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ScriptObject {
-    pub script_path: String,
+    pub script_path: AssetPath,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AnimationPlayer {
+    // ".anim.json" files, or model files whose every clip is taken. A model needs to be one
+    // of the entity's meshes, the player is attached once it has loaded
+    pub clips: Vec<AssetPath>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub autoplay: Option<String>, // name of a clip
+    #[serde(default)]
+    pub mode: PlayMode,
+    #[serde(default = "AnimationPlayer::default_speed")]
+    pub speed: f32,
+}
+
+impl AnimationPlayer {
+    const fn default_speed() -> f32 {
+        1.0
+    }
+}
+
+// Contents of an ".anim.json" file
+#[derive(Serialize, Deserialize)]
+pub struct AnimationClip {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String, // the file name without the extension when empty
+    pub duration: f32,
+    pub tracks: Vec<Track>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<ClipEvent>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Track {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub target: String, // name of a descendant, the entity itself when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub positions: Vec<Key<Vec3>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rotations: Vec<Key<Orientation>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scales: Vec<Key<Vec3>>,
+    // Blend shapes of the target's mesh, each key has a weight per name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub morph_targets: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub weights: Vec<Key<Vec<f32>>>,
+}
+
+// Keys are sampled by a binary search over their times, files may list them in any order
+fn sorted<T>(mut keys: Vec<Key<T>>) -> Vec<Key<T>> {
+    keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+    keys
+}
+
+impl Into<animation::TransformClip> for AnimationClip {
+    fn into(self) -> animation::TransformClip {
+        let vec3 = |key: &Key<Vec3>| Key {
+            time: key.time,
+            value: [key.value.x, key.value.y, key.value.z],
+        };
+        let tracks = self
+            .tracks
+            .iter()
+            .map(|track| animation::Track {
+                target: track.target.clone(),
+                keys: TransformKeys {
+                    positions: sorted(track.positions.iter().map(vec3).collect()),
+                    rotations: sorted(
+                        track
+                            .rotations
+                            .iter()
+                            .map(|key| {
+                                let value: glm::Quat = key.value.into();
+                                Key {
+                                    time: key.time,
+                                    value: [value.i, value.j, value.k, value.w],
+                                }
+                            })
+                            .collect(),
+                    ),
+                    scales: sorted(track.scales.iter().map(vec3).collect()),
+                },
+                weights: MorphKeys {
+                    targets: track.morph_targets.clone(),
+                    weights: sorted(track.weights.clone()),
+                },
+                rest: None,
+            })
+            .collect();
+        animation::TransformClip {
+            name: self.name,
+            duration: self.duration,
+            tracks,
+            events: self.events,
+        }
+    }
 }
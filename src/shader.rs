@@ -1,6 +1,11 @@
 use crate::{
+    animation,
+    asset_path::AssetPath,
+    data3d,
     gl_wrappers::Shader,
+    lighting,
     rendering::{self, BindingPoints},
+    vfs::Vfs,
 };
 use gl::types::GLenum;
 use glfw::Version;
@@ -11,13 +16,24 @@ pub enum ShaderDataSource {
     FragmentDataOut,
     MatrixData,
     LightingData,
+    BoneData,
+    MorphData,
     FragColorOut,
     VertexAttributes,
     MaterialUniforms,
+    ShadowMaps,
     Custom(String),
 }
 
 impl ShaderDataSource {
+    pub const BASE_COLOR_FACTOR_LOCATION: i32 = 10;
+    pub const METALNESS_FACTOR_LOCATION: i32 = 11;
+    pub const ROUGHNESS_FACTOR_LOCATION: i32 = 12;
+    pub const EMISSIVE_FACTOR_LOCATION: i32 = 13;
+    pub const ALPHA_MODE_LOCATION: i32 = 14;
+    pub const ALPHA_CUTOFF_LOCATION: i32 = 15;
+    pub const DEBUG_CASCADES_LOCATION: i32 = 16;
+
     fn frag_color_out() -> String {
         "
 layout (location = 0) out vec4 frag_color;
@@ -32,6 +48,10 @@ in FragmentData {
     vec3 normal;
     vec2 tex_coord;
     vec3 lightspace_pos;
+    vec3 tangent;
+    vec3 bitangent;
+    vec4 color;
+    vec2 tex_coord2;
 } fragment;
 "
         .to_string()
@@ -44,6 +64,10 @@ out FragmentData {
     vec3 normal;
     vec2 tex_coord;
     vec3 lightspace_pos;
+    vec3 tangent;
+    vec3 bitangent;
+    vec4 color;
+    vec2 tex_coord2;
 } fragment;
 "
         .to_string()
@@ -73,37 +97,119 @@ struct LightSource {{
     float inner_cutoff;
     vec3 dir;
     float outer_cutoff;
+    mat4 light_space[{}]; // a layer of the shadow map each
+    vec4 cascade_splits; // view depth each cascade reaches
+    int shadow_map; // -1 without shadows, indexes shadow_cube_maps for point lights
+    float shadow_bias;
+    float normal_bias;
+    float shadow_far;
+    float filter_radius;
+    uint cascade_count;
+    float cascade_blend;
 }};
 
 layout(std140, binding = {}) uniform LightingData {{
     LightSource sources[{}];
     vec3 viewer_pos;
     uint source_count;
+    vec3 viewer_dir;
 }};
 ",
+            lighting::MAX_CASCADES,
             BindingPoints::LightingData as u32,
             rendering::MAX_LIGHT_SOURCES_PER_FRAME
         )
     }
 
+    fn bone_data() -> String {
+        format!(
+            "
+layout (std140, binding = {}) uniform BoneData {{
+    uint skinned;
+    mat4 bones[{}];
+}};
+",
+            BindingPoints::BoneData as u32,
+            animation::MAX_BONES
+        )
+    }
+
+    // Weights are packed four to a vec4, std140 pads every float of an array to one
+    fn morph_data() -> String {
+        format!(
+            "
+layout (std140, binding = {}) uniform MorphData {{
+    uint morph_target_count;
+    uint morph_vertex_count;
+    vec4 morph_weights[{}];
+}};
+
+layout (std430, binding = {}) readonly buffer MorphDeltas {{
+    vec4 morph_deltas[]; // position and normal of every vertex, target after target
+}};
+",
+            BindingPoints::MorphData as u32,
+            data3d::MAX_MORPH_TARGETS / 4,
+            BindingPoints::MorphDeltas as u32
+        )
+    }
+
     fn vertex_attributes() -> String {
         "
 layout (location = 0) in vec3 position;
 layout (location = 1) in vec3 normal;
 layout (location = 2) in vec2 tex_coord;
+layout (location = 3) in vec3 tangent;
+layout (location = 4) in vec3 bitangent;
+layout (location = 5) in vec4 color;
+layout (location = 6) in vec2 tex_coord2;
+layout (location = 7) in vec4 bone_indices;
+layout (location = 8) in vec4 bone_weights;
 "
         .to_string()
     }
 
     fn material_uniforms() -> String {
-        "
-uniform sampler2D albedo_map;
-uniform sampler2D metallic_map;
-uniform sampler2D roughness_map;
-uniform sampler2D ao_map;
-uniform sampler2D normal_map;
-uniform sampler2D displacement_map;"
-            .to_string()
+        format!(
+            "
+layout (binding = 0) uniform sampler2D albedo_map;
+layout (binding = 1) uniform sampler2D metallic_map;
+layout (binding = 2) uniform sampler2D roughness_map;
+layout (binding = 3) uniform sampler2D ao_map;
+layout (binding = 4) uniform sampler2D normal_map;
+layout (binding = 5) uniform sampler2D displacement_map;
+layout (binding = 6) uniform sampler2D emissive_map;
+
+layout (location = {}) uniform vec4 base_color_factor;
+layout (location = {}) uniform float metalness_factor;
+layout (location = {}) uniform float roughness_factor;
+layout (location = {}) uniform vec3 emissive_factor;
+layout (location = {}) uniform uint alpha_mode; // 0 - opaque, 1 - mask, 2 - blend
+layout (location = {}) uniform float alpha_cutoff;
+",
+            Self::BASE_COLOR_FACTOR_LOCATION,
+            Self::METALNESS_FACTOR_LOCATION,
+            Self::ROUGHNESS_FACTOR_LOCATION,
+            Self::EMISSIVE_FACTOR_LOCATION,
+            Self::ALPHA_MODE_LOCATION,
+            Self::ALPHA_CUTOFF_LOCATION,
+        )
+    }
+
+    fn shadow_maps() -> String {
+        format!(
+            "
+layout (binding = {}) uniform sampler2DArrayShadow shadow_maps[{}];
+layout (binding = {}) uniform samplerCubeShadow shadow_cube_maps[{}];
+
+layout (location = {}) uniform bool debug_cascades;
+",
+            rendering::SHADOW_MAP_UNIT,
+            rendering::MAX_SHADOW_MAPS,
+            rendering::SHADOW_CUBE_MAP_UNIT,
+            rendering::MAX_SHADOW_MAPS,
+            Self::DEBUG_CASCADES_LOCATION,
+        )
     }
 
     pub fn source(&self) -> String {
@@ -113,8 +219,11 @@ uniform sampler2D displacement_map;"
             ShaderDataSource::FragmentDataOut => Self::fragment_data_out(),
             ShaderDataSource::MatrixData => Self::matrix_data(),
             ShaderDataSource::LightingData => Self::lighting_data(),
+            ShaderDataSource::BoneData => Self::bone_data(),
+            ShaderDataSource::MorphData => Self::morph_data(),
             ShaderDataSource::VertexAttributes => Self::vertex_attributes(),
             ShaderDataSource::MaterialUniforms => Self::material_uniforms(),
+            ShaderDataSource::ShadowMaps => Self::shadow_maps(),
             ShaderDataSource::Custom(src) => src.clone(),
         }
     }
@@ -128,6 +237,8 @@ pub enum ShaderType {
 }
 
 pub trait ShaderSource {
+    // Used to look up an override in `assets/shaders/<name>.glsl`
+    fn name(&self) -> &'static str;
     fn type_(&self) -> ShaderType;
     fn source(&self) -> String;
     fn data(&self) -> Vec<ShaderDataSource>;
@@ -152,12 +263,37 @@ impl<T> MainShader<T> {
     fn vert_src() -> &'static str {
         "
 void main() {
-    fragment.pos = (model * vec4(position, 1.0)).xyz;
-    fragment.normal = (orientation * vec4(normal, 1.0)).xyz;
+    vec3 morphed_pos = position;
+    vec3 morphed_normal = normal;
+    for (uint i = 0u; i < morph_target_count; i++) {
+        float weight = morph_weights[i / 4u][i % 4u];
+        if (weight != 0.0) {
+            uint delta = (i * morph_vertex_count + uint(gl_VertexID)) * 2u;
+            morphed_pos += weight * morph_deltas[delta].xyz;
+            morphed_normal += weight * morph_deltas[delta + 1u].xyz;
+        }
+    }
+
+    mat4 skin = mat4(1.0);
+    if (skinned != 0u && bone_weights != vec4(0.0)) {
+        skin = bone_weights.x * bones[int(bone_indices.x)]
+            + bone_weights.y * bones[int(bone_indices.y)]
+            + bone_weights.z * bones[int(bone_indices.z)]
+            + bone_weights.w * bones[int(bone_indices.w)];
+    }
+    vec4 skinned_pos = skin * vec4(morphed_pos, 1.0);
+    mat3 skin_rotation = mat3(skin);
+
+    fragment.pos = (model * skinned_pos).xyz;
+    fragment.normal = mat3(orientation) * skin_rotation * morphed_normal;
     fragment.tex_coord = tex_coord;
-    vec4 lightspace_pos = (light_space * model * vec4(position, 1.0));
+    fragment.tangent = mat3(orientation) * skin_rotation * tangent;
+    fragment.bitangent = mat3(orientation) * skin_rotation * bitangent;
+    fragment.color = color;
+    fragment.tex_coord2 = tex_coord2;
+    vec4 lightspace_pos = (light_space * model * skinned_pos);
     fragment.lightspace_pos = lightspace_pos.xyz / lightspace_pos.w;
-    gl_Position = mvp * vec4(position, 1.0);
+    gl_Position = mvp * skinned_pos;
 "
     }
 
@@ -191,6 +327,10 @@ void main() {"
 }
 
 impl ShaderSource for MainShader<VertShader> {
+    fn name(&self) -> &'static str {
+        "main.vert"
+    }
+
     fn type_(&self) -> ShaderType {
         ShaderType::Vertex
     }
@@ -204,11 +344,17 @@ impl ShaderSource for MainShader<VertShader> {
             ShaderDataSource::VertexAttributes,
             ShaderDataSource::FragmentDataOut,
             ShaderDataSource::MatrixData,
+            ShaderDataSource::BoneData,
+            ShaderDataSource::MorphData,
         ]
     }
 }
 
 impl ShaderSource for MainShader<FragShader> {
+    fn name(&self) -> &'static str {
+        "main.frag"
+    }
+
     fn type_(&self) -> ShaderType {
         ShaderType::Fragment
     }
@@ -302,6 +448,10 @@ void compute_lighting() {
 }
 
 impl ShaderSource for BlinnPhongLighting {
+    fn name(&self) -> &'static str {
+        "blinn-phong.frag"
+    }
+
     fn type_(&self) -> ShaderType {
         ShaderType::Fragment
     }
@@ -412,6 +562,119 @@ vec3 fresnel_schlick(float cos_theta, vec3 F0) {
     return F0 + (1.0 - F0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
 }
 
+// Percentage-closer filtering over a square of texels, on top of the 2x2 the hardware does
+float layer_shadow(LightSource light_source, vec3 pos, int layer) {
+    vec4 clip = light_source.light_space[layer] * vec4(pos, 1.0);
+    vec3 coords = clip.xyz / clip.w * 0.5 + 0.5;
+    if (any(lessThan(coords, vec3(0.0))) || any(greaterThan(coords, vec3(1.0)))) {
+        return 1.0;
+    }
+    vec2 texel = 1.0 / vec2(textureSize(shadow_maps[light_source.shadow_map], 0).xy);
+    float depth = coords.z - light_source.shadow_bias;
+    int radius = int(light_source.filter_radius);
+    float lit = 0.0;
+    for (int x = -radius; x <= radius; x++) {
+        for (int y = -radius; y <= radius; y++) {
+            vec2 offset = vec2(x, y) * texel;
+            vec4 coord = vec4(coords.xy + offset, float(layer), depth);
+            lit += texture(shadow_maps[light_source.shadow_map], coord);
+        }
+    }
+    return lit / float((2 * radius + 1) * (2 * radius + 1));
+}
+
+float view_depth() {
+    return dot(fragment.pos - viewer_pos, viewer_dir);
+}
+
+// -1 past the last cascade
+int cascade_index(LightSource light_source, float depth) {
+    for (int c = 0; c < int(light_source.cascade_count); c++) {
+        if (depth < light_source.cascade_splits[c]) {
+            return c;
+        }
+    }
+    return -1;
+}
+
+// Spot lights have a single layer, directional lights fade the end of each cascade into the
+// next one, and the last one out
+float map_shadow(LightSource light_source, vec3 pos) {
+    if (light_source.type != 0) {
+        return layer_shadow(light_source, pos, 0);
+    }
+    float depth = view_depth();
+    int c = cascade_index(light_source, depth);
+    if (c < 0) {
+        return 1.0;
+    }
+    float lit = layer_shadow(light_source, pos, c);
+    float begin = c == 0 ? 0.0 : light_source.cascade_splits[c - 1];
+    float end = light_source.cascade_splits[c];
+    float blend_begin = end - (end - begin) * light_source.cascade_blend;
+    if (light_source.cascade_blend > 0.0 && depth > blend_begin) {
+        bool last = c + 1 == int(light_source.cascade_count);
+        float next = last ? 1.0 : layer_shadow(light_source, pos, c + 1);
+        lit = mix(lit, next, (depth - blend_begin) / (end - blend_begin));
+    }
+    return lit;
+}
+
+const vec3 CASCADE_COLORS[4] = vec3[](
+    vec3(1.0, 0.2, 0.2), vec3(0.2, 1.0, 0.2), vec3(0.2, 0.2, 1.0), vec3(1.0, 1.0, 0.2)
+);
+
+// Of the first directional light with shadows
+int debug_cascade() {
+    for (int i = 0; i < source_count; i++) {
+        if (sources[i].type == 0 && sources[i].shadow_map >= 0) {
+            return cascade_index(sources[i], view_depth());
+        }
+    }
+    return -1;
+}
+
+const vec3 CUBE_OFFSETS[20] = vec3[](
+    vec3(1, 1, 1), vec3(1, -1, 1), vec3(-1, -1, 1), vec3(-1, 1, 1),
+    vec3(1, 1, -1), vec3(1, -1, -1), vec3(-1, -1, -1), vec3(-1, 1, -1),
+    vec3(1, 1, 0), vec3(1, -1, 0), vec3(-1, -1, 0), vec3(-1, 1, 0),
+    vec3(1, 0, 1), vec3(-1, 0, 1), vec3(1, 0, -1), vec3(-1, 0, -1),
+    vec3(0, 1, 1), vec3(0, -1, 1), vec3(0, -1, -1), vec3(0, 1, -1)
+);
+
+// Cube maps hold the distance to the light divided by shadow_far
+float cube_shadow(LightSource light_source, vec3 pos) {
+    vec3 to_fragment = pos - light_source.pos;
+    float distance = length(to_fragment);
+    float depth = distance / light_source.shadow_far - light_source.shadow_bias;
+    if (depth > 1.0) {
+        return 1.0;
+    }
+    if (light_source.filter_radius == 0.0) {
+        return texture(shadow_cube_maps[light_source.shadow_map], vec4(to_fragment, depth));
+    }
+    // a face spans twice the distance to it
+    float texel = 2.0 * distance / float(textureSize(shadow_cube_maps[light_source.shadow_map], 0).x);
+    float spread = light_source.filter_radius * texel;
+    float lit = 0.0;
+    for (int i = 0; i < 20; i++) {
+        vec3 direction = to_fragment + CUBE_OFFSETS[i] * spread;
+        lit += texture(shadow_cube_maps[light_source.shadow_map], vec4(direction, depth));
+    }
+    return lit / 20.0;
+}
+
+float shadow(LightSource light_source, vec3 surface_normal) {
+    if (light_source.shadow_map < 0) {
+        return 1.0;
+    }
+    vec3 pos = fragment.pos + surface_normal * light_source.normal_bias;
+    if (light_source.type == 1) {
+        return cube_shadow(light_source, pos);
+    }
+    return map_shadow(light_source, pos);
+}
+
 // vec3 pbr(vec3 light, vec3 light_color, float attenuation) {
 //     vec3 viewer = normalize(viewer_pos - fragment.pos);
 //     vec3 halfway = normalize(viewer + light);
@@ -433,10 +696,25 @@ vec3 fresnel_schlick(float cos_theta, vec3 F0) {
 // }
 
 void do_light() {
-    vec3 albedo = texture(albedo_map, fragment.tex_coord).rgb;
-    float metallic = texture(metallic_map, fragment.tex_coord).r;
-    float roughness = texture(roughness_map, fragment.tex_coord).r;
+    vec4 base_color = texture(albedo_map, fragment.tex_coord) * base_color_factor * fragment.color;
+    if (alpha_mode == 1 && base_color.a < alpha_cutoff) {
+        discard;
+    }
+    vec3 albedo = base_color.rgb;
+    float metallic = texture(metallic_map, fragment.tex_coord).r * metalness_factor;
+    float roughness = texture(roughness_map, fragment.tex_coord).r * roughness_factor;
     float ao = texture(ao_map, fragment.tex_coord).r;
+    vec3 emissive = texture(emissive_map, fragment.tex_coord).rgb * emissive_factor;
+    vec3 normal = normalize(fragment.normal);
+    // meshes without texture coordinates have no tangents to map normals with
+    if (dot(fragment.tangent, fragment.tangent) > 0.0) {
+        mat3 tbn = mat3(normalize(fragment.tangent), normalize(fragment.bitangent), normal);
+        normal = normalize(tbn * (texture(normal_map, fragment.tex_coord).rgb * 2.0 - 1.0));
+    }
+    // back faces are only visible on two-sided materials
+    normal = gl_FrontFacing ? normal : -normal;
+    // shadow lookups are offset along the geometry's normal, normal maps would make them noisy
+    vec3 surface_normal = normalize(fragment.normal) * (gl_FrontFacing ? 1.0 : -1.0);
 
     vec3 Lo = vec3(0.0);
     vec3 F0 = vec3(0.04);
@@ -457,8 +735,8 @@ void do_light() {
         vec3 radiance = info.color * info.attenuation;
 
         // Cook-Torrance BRDF
-        float NDF = distribution_GGX(fragment.normal, halfway, roughness);
-        float G = geometry_smith(fragment.normal, viewer, info.dir, roughness);
+        float NDF = distribution_GGX(normal, halfway, roughness);
+        float G = geometry_smith(normal, viewer, info.dir, roughness);
         vec3 F = fresnel_schlick(max(dot(halfway, viewer), 0.0), F0);
 
         vec3 kS = F;
@@ -466,17 +744,23 @@ void do_light() {
         kD *= 1.0 - metallic;
 
         vec3 numerator = NDF * G * F;
-        float denominator = 4.0 * max(dot(fragment.normal, viewer), 0.0) * max(dot(fragment.normal, info.dir), 0.0) + 0.0001;
+        float denominator = 4.0 * max(dot(normal, viewer), 0.0) * max(dot(normal, info.dir), 0.0) + 0.0001;
         vec3 specular = numerator / denominator;
 
-        float NdotL = max(dot(fragment.normal, info.dir), 0.0);
+        float NdotL = max(dot(normal, info.dir), 0.0);
 
-        Lo += (kD * albedo / PI + specular) * radiance * NdotL;
+        Lo += (kD * albedo / PI + specular) * radiance * NdotL * shadow(sources[i], surface_normal);
     }
 
     vec3 ambient = vec3(AMBIENT) * albedo * ao;
-    vec3 color = Lo + ambient;
-    frag_color = vec4(color, 1.0);
+    vec3 color = Lo + ambient + emissive;
+    if (debug_cascades) {
+        int c = debug_cascade();
+        if (c >= 0) {
+            color = mix(color, CASCADE_COLORS[c], 0.5);
+        }
+    }
+    frag_color = vec4(color, alpha_mode == 2 ? base_color.a : 1.0);
 }
 ".to_string()
     }
@@ -487,6 +771,10 @@ void do_light() {
 }
 
 impl ShaderSource for DirectPBR {
+    fn name(&self) -> &'static str {
+        "direct-pbr.frag"
+    }
+
     fn type_(&self) -> ShaderType {
         ShaderType::Fragment
     }
@@ -501,6 +789,7 @@ impl ShaderSource for DirectPBR {
             ShaderDataSource::LightingData,
             ShaderDataSource::FragColorOut,
             ShaderDataSource::MaterialUniforms,
+            ShaderDataSource::ShadowMaps,
         ]
     }
 }
@@ -520,6 +809,59 @@ void do_light();
         .to_string()
     }
 }
+// Depth pass of shadow maps, drawn with the main vertex shader
+pub struct ShadowShaderFrag;
+
+impl ShadowShaderFrag {
+    pub const LIGHT_POSITION_LOCATION: i32 = 20;
+    pub const FAR_LOCATION: i32 = 21;
+
+    fn src() -> String {
+        format!(
+            "
+layout (location = {}) uniform vec3 light_pos;
+layout (location = {}) uniform float far; // 0 keeps the depth of the projection
+
+void main() {{
+    float alpha = texture(albedo_map, fragment.tex_coord).a * base_color_factor.a * fragment.color.a;
+    if (alpha_mode == 1 && alpha < alpha_cutoff) {{
+        discard;
+    }}
+    // cube maps store the distance to the light, the same whatever face it is seen through
+    gl_FragDepth = far > 0.0 ? length(fragment.pos - light_pos) / far : gl_FragCoord.z;
+}}
+",
+            Self::LIGHT_POSITION_LOCATION,
+            Self::FAR_LOCATION
+        )
+    }
+
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl ShaderSource for ShadowShaderFrag {
+    fn name(&self) -> &'static str {
+        "shadow.frag"
+    }
+
+    fn type_(&self) -> ShaderType {
+        ShaderType::Fragment
+    }
+
+    fn source(&self) -> String {
+        Self::src()
+    }
+
+    fn data(&self) -> Vec<ShaderDataSource> {
+        vec![
+            ShaderDataSource::FragmentDataIn,
+            ShaderDataSource::MaterialUniforms,
+        ]
+    }
+}
+
 pub struct ScreenShaderVert;
 
 impl ScreenShaderVert {
@@ -546,6 +888,10 @@ void main() {
 }
 
 impl ShaderSource for ScreenShaderVert {
+    fn name(&self) -> &'static str {
+        "screen.vert"
+    }
+
     fn type_(&self) -> ShaderType {
         ShaderType::Vertex
     }
@@ -603,6 +949,10 @@ void main() {{
 }
 
 impl ShaderSource for ScreenShaderFrag {
+    fn name(&self) -> &'static str {
+        "screen.frag"
+    }
+
     fn type_(&self) -> ShaderType {
         ShaderType::Fragment
     }
@@ -616,7 +966,29 @@ impl ShaderSource for ScreenShaderFrag {
     }
 }
 
-pub fn build_shader(shader_source: &impl ShaderSource, context_version: Version) -> Shader {
+pub const SHADER_OVERRIDES_DIR: &str = "shaders"; // under the asset root
+
+pub fn build_shader(
+    shader_source: &impl ShaderSource,
+    context_version: Version,
+    vfs: &Vfs,
+) -> Shader {
+    match try_build_shader(shader_source, context_version, vfs) {
+        Ok(shader) => shader,
+        Err(log) => {
+            println!("{log}");
+            panic!("Shader compilation error")
+        }
+    }
+}
+
+// Same as `build_shader`, but leaves it to the caller to decide what to do with a broken
+// shader, which is needed to keep the old program running while shaders are being edited
+pub fn try_build_shader(
+    shader_source: &impl ShaderSource,
+    context_version: Version,
+    vfs: &Vfs,
+) -> Result<Shader, String> {
     let shader = Shader::new(shader_source.type_() as GLenum).unwrap();
     let mut source = format!(
         "#version {}{}0 core\n",
@@ -625,14 +997,19 @@ pub fn build_shader(shader_source: &impl ShaderSource, context_version: Version)
     for data in shader_source.data() {
         source.push_str(&data.source());
     }
-    source.push_str(&shader_source.source());
+    source.push_str(&shader_source_override(shader_source, vfs).unwrap_or(shader_source.source()));
     println!("{source}");
     shader.set_source(&source);
     shader.compile();
     if !shader.compile_success() {
-        println!("{}", shader.info_log());
-        panic!("Shader compilation error")
+        return Err(format!("{}: {}", shader_source.name(), shader.info_log()));
     }
 
-    shader
+    Ok(shader)
+}
+
+// Replaces the body of a built-in shader, the data blocks are still generated
+fn shader_source_override(shader_source: &impl ShaderSource, vfs: &Vfs) -> Option<String> {
+    let path = AssetPath::new(&format!("{SHADER_OVERRIDES_DIR}/{}.glsl", shader_source.name()));
+    vfs.read_to_string(&path.resolve()).ok()
 }
@@ -0,0 +1,336 @@
+use crate::{
+    asset_loader::{DecodedImage, PixelType},
+    cook::{self, Compression},
+    utils::StbImage,
+};
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const KTX2_MAGIC: &[u8; 12] = b"\xABKTX 20\xBB\r\n\x1A\n";
+
+// DDS header flags
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDPF_FOURCC: u32 = 0x4;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+const DDS_DIMENSION_TEXTURE2D: u32 = 3;
+
+#[derive(Clone, Copy)]
+enum Format {
+    Pixels {
+        channels: usize,
+        pixel_type: PixelType,
+        bgr: bool, // red and blue are swapped on load
+    },
+    Blocks(Compression),
+}
+
+impl Format {
+    const fn pixels(channels: usize, pixel_type: PixelType) -> Self {
+        Format::Pixels {
+            channels,
+            pixel_type,
+            bgr: false,
+        }
+    }
+
+    // None when the header's size doesn't fit in memory
+    fn level_len(&self, size: (usize, usize)) -> Option<usize> {
+        match self {
+            Format::Pixels {
+                channels,
+                pixel_type,
+                ..
+            } => size
+                .0
+                .checked_mul(size.1)?
+                .checked_mul(*channels)?
+                .checked_mul(pixel_type.size()),
+            Format::Blocks(compression) => size
+                .0
+                .div_ceil(4)
+                .checked_mul(size.1.div_ceil(4))?
+                .checked_mul(compression.block_size()),
+        }
+    }
+}
+
+// Decodes any image file the engine supports. DDS and KTX2 files come with their own mip
+// chains and may be block compressed, they are uploaded as stored, so export them with the
+// origin in the lower left corner (texconv -vflip, toktx --lower_left_maps_to_s0t0).
+// Everything else goes through stb_image, which flips when `vflip` is set
+pub fn decode(bytes: &[u8], vflip: bool) -> Option<DecodedImage> {
+    if bytes.starts_with(DDS_MAGIC) {
+        read_dds(bytes)
+    } else if bytes.starts_with(KTX2_MAGIC) {
+        read_ktx2(bytes)
+    } else {
+        StbImage::try_load_from_memory(bytes, vflip).map(|img| DecodedImage::from_stb(&img))
+    }
+}
+
+fn read_dds(bytes: &[u8]) -> Option<DecodedImage> {
+    let flags = u32_at(bytes, 8)?;
+    let size = (u32_at(bytes, 16)? as usize, u32_at(bytes, 12)? as usize);
+    let level_count = if flags & DDSD_MIPMAPCOUNT != 0 {
+        u32_at(bytes, 28)?
+    } else {
+        1
+    };
+    let level_count = level_count_of(size, level_count)?;
+    let pixel_flags = u32_at(bytes, 80)?;
+    let four_cc = bytes.get(84..88)?;
+    let caps2 = u32_at(bytes, 112)?;
+
+    let (format, data_offset) = if four_cc == b"DX10" {
+        let dimension = u32_at(bytes, 132)?;
+        let misc_flags = u32_at(bytes, 136)?;
+        let array_size = u32_at(bytes, 140)?;
+        if dimension != DDS_DIMENSION_TEXTURE2D
+            || misc_flags & DDS_RESOURCE_MISC_TEXTURECUBE != 0
+            || array_size > 1
+        {
+            println!("Only plain 2D DDS textures are supported");
+            return None;
+        }
+        (dxgi_format(u32_at(bytes, 128)?), 148)
+    } else if caps2 & (DDSCAPS2_CUBEMAP | DDSCAPS2_VOLUME) != 0 {
+        println!("Only plain 2D DDS textures are supported");
+        return None;
+    } else if pixel_flags & DDPF_FOURCC != 0 {
+        (four_cc_format(four_cc), 128)
+    } else {
+        (legacy_dds_format(bytes), 128)
+    };
+    let format = match format {
+        Some(format) => format,
+        None => {
+            println!("Unsupported DDS pixel format");
+            return None;
+        }
+    };
+
+    // Levels follow each other tightly, largest first
+    let mut offset = data_offset;
+    let mut levels = Vec::with_capacity(level_count);
+    for level in 0..level_count {
+        let len = format.level_len(cook::level_size(size, level))?;
+        let end = offset.checked_add(len)?;
+        levels.push(bytes.get(offset..end)?.to_vec());
+        offset = end;
+    }
+
+    Some(image(format, size, levels))
+}
+
+fn four_cc_format(four_cc: &[u8]) -> Option<Format> {
+    let format = match four_cc {
+        b"DXT1" => Format::Blocks(Compression::Bc1),
+        b"DXT2" | b"DXT3" => Format::Blocks(Compression::Bc2),
+        b"DXT4" | b"DXT5" => Format::Blocks(Compression::Bc3),
+        b"ATI1" | b"BC4U" => Format::Blocks(Compression::Bc4),
+        b"ATI2" | b"BC5U" => Format::Blocks(Compression::Bc5),
+        // D3DFORMAT values stored in place of a four character code
+        _ => match u32::from_le_bytes(four_cc.try_into().unwrap()) {
+            36 => Format::pixels(4, PixelType::U16),
+            111 => Format::pixels(1, PixelType::F16),
+            112 => Format::pixels(2, PixelType::F16),
+            113 => Format::pixels(4, PixelType::F16),
+            114 => Format::pixels(1, PixelType::F32),
+            115 => Format::pixels(2, PixelType::F32),
+            116 => Format::pixels(4, PixelType::F32),
+            _ => return None,
+        },
+    };
+    Some(format)
+}
+
+// Uncompressed files written without a DX10 header describe their layout with bit masks
+fn legacy_dds_format(bytes: &[u8]) -> Option<Format> {
+    let masks = (
+        u32_at(bytes, 88)?,
+        u32_at(bytes, 92)?,
+        u32_at(bytes, 96)?,
+        u32_at(bytes, 100)?,
+        u32_at(bytes, 104)?,
+    );
+    let format = match masks {
+        (32, 0xff, 0xff00, 0xff0000, 0xff000000) => Format::pixels(4, PixelType::U8),
+        (32, 0xff0000, 0xff00, 0xff, 0xff000000) => Format::Pixels {
+            channels: 4,
+            pixel_type: PixelType::U8,
+            bgr: true,
+        },
+        (8, 0xff, 0, 0, 0) => Format::pixels(1, PixelType::U8),
+        _ => return None,
+    };
+    Some(format)
+}
+
+// Typeless and snorm formats are left out. Whether the data is sRGB is up to the material
+// slot the texture is used in, the same as for other images
+fn dxgi_format(dxgi_format: u32) -> Option<Format> {
+    let format = match dxgi_format {
+        2 => Format::pixels(4, PixelType::F32),
+        6 => Format::pixels(3, PixelType::F32),
+        10 => Format::pixels(4, PixelType::F16),
+        11 => Format::pixels(4, PixelType::U16),
+        16 => Format::pixels(2, PixelType::F32),
+        28 | 29 => Format::pixels(4, PixelType::U8),
+        34 => Format::pixels(2, PixelType::F16),
+        35 => Format::pixels(2, PixelType::U16),
+        41 => Format::pixels(1, PixelType::F32),
+        49 => Format::pixels(2, PixelType::U8),
+        54 => Format::pixels(1, PixelType::F16),
+        56 => Format::pixels(1, PixelType::U16),
+        61 => Format::pixels(1, PixelType::U8),
+        71 | 72 => Format::Blocks(Compression::Bc1),
+        74 | 75 => Format::Blocks(Compression::Bc2),
+        77 | 78 => Format::Blocks(Compression::Bc3),
+        80 => Format::Blocks(Compression::Bc4),
+        83 => Format::Blocks(Compression::Bc5),
+        87 | 91 => Format::Pixels {
+            channels: 4,
+            pixel_type: PixelType::U8,
+            bgr: true,
+        },
+        95 => Format::Blocks(Compression::Bc6h),
+        98 | 99 => Format::Blocks(Compression::Bc7),
+        _ => return None,
+    };
+    Some(format)
+}
+
+fn read_ktx2(bytes: &[u8]) -> Option<DecodedImage> {
+    let vk_format = u32_at(bytes, 12)?;
+    let size = (u32_at(bytes, 20)? as usize, u32_at(bytes, 24)? as usize);
+    let depth = u32_at(bytes, 28)?;
+    let layer_count = u32_at(bytes, 32)?;
+    let face_count = u32_at(bytes, 36)?;
+    let level_count = u32_at(bytes, 40)?;
+    let supercompression = u32_at(bytes, 44)?;
+
+    if size.1 == 0 || depth != 0 || layer_count != 0 || face_count != 1 {
+        println!("Only plain 2D KTX2 textures are supported");
+        return None;
+    }
+    // Basis Universal and zstd need a transcoder we don't have
+    if supercompression != 0 {
+        println!("Supercompressed KTX2 textures aren't supported");
+        return None;
+    }
+    let format = match vk_format_of(vk_format) {
+        Some(format) => format,
+        None => {
+            println!("Unsupported KTX2 format {vk_format}");
+            return None;
+        }
+    };
+    let level_count = level_count_of(size, level_count)?;
+
+    // The level index right after the header lists the largest level first
+    let mut levels = Vec::with_capacity(level_count);
+    for level in 0..level_count {
+        let entry = 80 + level * 24;
+        let offset = u64_at(bytes, entry)? as usize;
+        let len = u64_at(bytes, entry + 8)? as usize;
+        if Some(len) != format.level_len(cook::level_size(size, level)) {
+            println!("KTX2 level {level} has an unexpected size");
+            return None;
+        }
+        levels.push(bytes.get(offset..offset.checked_add(len)?)?.to_vec());
+    }
+
+    Some(image(format, size, levels))
+}
+
+// Same as for DDS, sRGB variants are read as their linear counterparts
+fn vk_format_of(vk_format: u32) -> Option<Format> {
+    let format = match vk_format {
+        9 | 15 => Format::pixels(1, PixelType::U8),
+        16 | 22 => Format::pixels(2, PixelType::U8),
+        23 | 29 => Format::pixels(3, PixelType::U8),
+        37 | 43 => Format::pixels(4, PixelType::U8),
+        44 | 50 => Format::Pixels {
+            channels: 4,
+            pixel_type: PixelType::U8,
+            bgr: true,
+        },
+        70 => Format::pixels(1, PixelType::U16),
+        76 => Format::pixels(1, PixelType::F16),
+        77 => Format::pixels(2, PixelType::U16),
+        83 => Format::pixels(2, PixelType::F16),
+        84 => Format::pixels(3, PixelType::U16),
+        90 => Format::pixels(3, PixelType::F16),
+        91 => Format::pixels(4, PixelType::U16),
+        97 => Format::pixels(4, PixelType::F16),
+        100 => Format::pixels(1, PixelType::F32),
+        103 => Format::pixels(2, PixelType::F32),
+        106 => Format::pixels(3, PixelType::F32),
+        109 => Format::pixels(4, PixelType::F32),
+        131..=134 => Format::Blocks(Compression::Bc1),
+        135 | 136 => Format::Blocks(Compression::Bc2),
+        137 | 138 => Format::Blocks(Compression::Bc3),
+        139 => Format::Blocks(Compression::Bc4),
+        141 => Format::Blocks(Compression::Bc5),
+        143 => Format::Blocks(Compression::Bc6h),
+        145 | 146 => Format::Blocks(Compression::Bc7),
+        _ => return None,
+    };
+    Some(format)
+}
+
+fn image(format: Format, size: (usize, usize), mut levels: Vec<Vec<u8>>) -> DecodedImage {
+    match format {
+        Format::Pixels {
+            channels,
+            pixel_type,
+            bgr,
+        } => {
+            if bgr {
+                for level in &mut levels {
+                    for pixel in level.chunks_exact_mut(channels) {
+                        pixel.swap(0, 2);
+                    }
+                }
+            }
+            DecodedImage {
+                levels,
+                size,
+                channels,
+                pixel_type,
+                compression: None,
+            }
+        }
+        Format::Blocks(compression) => DecodedImage {
+            levels,
+            size,
+            channels: compression.channels(),
+            pixel_type: PixelType::U8,
+            compression: Some(compression),
+        },
+    }
+}
+
+// Headers are trusted no further than the full mip chain of their size. Zero, which KTX2
+// uses to ask for generated mipmaps, is read as a single level
+fn level_count_of(size: (usize, usize), stored: u32) -> Option<usize> {
+    if size.0 == 0 || size.1 == 0 {
+        println!("Texture has a zero size");
+        return None;
+    }
+    let full_chain = (usize::BITS - size.0.max(size.1).leading_zeros()) as usize;
+    Some((stored as usize).clamp(1, full_chain))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().unwrap(),
+    ))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().unwrap(),
+    ))
+}
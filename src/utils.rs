@@ -1,3 +1,4 @@
+use crate::asset_loader::PixelType;
 use fxhash::FxHasher32;
 use stb_image::stb_image;
 use std::{
@@ -285,27 +286,95 @@ pub struct StbImage {
     x: usize,
     y: usize,
     channels: usize,
+    pixel_type: PixelType,
 }
 
 impl StbImage {
     pub fn load(path: &str, vflip: bool) -> Self {
+        Self::try_load(path, vflip).unwrap_or_else(|| panic!("Failed to load image {path}"))
+    }
+
+    // Files that are being written to by an editor can fail to decode
+    pub fn try_load(path: &str, vflip: bool) -> Option<Self> {
         let filename = CString::new(path.as_bytes()).unwrap();
 
         let mut x = 0;
         let mut y = 0;
         let mut channels = 0;
-        let data = unsafe {
+        let (data, pixel_type) = unsafe {
             stb_image::stbi_set_flip_vertically_on_load(vflip as i32); // Could be needed
-            stb_image::stbi_load(filename.as_ptr(), &mut x, &mut y, &mut channels, 0)
+            let filename = filename.as_ptr();
+            if stb_image::stbi_is_hdr(filename) != 0 {
+                let data = stb_image::stbi_loadf(filename, &mut x, &mut y, &mut channels, 0);
+                (data.cast(), PixelType::F32)
+            } else if stb_image::stbi_is_16_bit(filename) != 0 {
+                let data = stb_image::stbi_load_16(filename, &mut x, &mut y, &mut channels, 0);
+                (data.cast(), PixelType::U16)
+            } else {
+                let data = stb_image::stbi_load(filename, &mut x, &mut y, &mut channels, 0);
+                (data, PixelType::U8)
+            }
         };
-        assert_ne!(data, std::ptr::null_mut());
+        Self::from_raw(data, x, y, channels, pixel_type)
+    }
 
-        Self {
+    // Compressed file contents, e.g. a png embedded into a model. Radiance files are decoded
+    // to floats and 16-bit pngs keep their precision
+    pub fn try_load_from_memory(bytes: &[u8], vflip: bool) -> Option<Self> {
+        let mut x = 0;
+        let mut y = 0;
+        let mut channels = 0;
+        let buffer = bytes.as_ptr();
+        let len = bytes.len() as i32;
+        let (data, pixel_type) = unsafe {
+            stb_image::stbi_set_flip_vertically_on_load(vflip as i32);
+            if stb_image::stbi_is_hdr_from_memory(buffer, len) != 0 {
+                let data = stb_image::stbi_loadf_from_memory(
+                    buffer,
+                    len,
+                    &mut x,
+                    &mut y,
+                    &mut channels,
+                    0,
+                );
+                (data.cast(), PixelType::F32)
+            } else if stb_image::stbi_is_16_bit_from_memory(buffer, len) != 0 {
+                let data = stb_image::stbi_load_16_from_memory(
+                    buffer,
+                    len,
+                    &mut x,
+                    &mut y,
+                    &mut channels,
+                    0,
+                );
+                (data.cast(), PixelType::U16)
+            } else {
+                let data =
+                    stb_image::stbi_load_from_memory(buffer, len, &mut x, &mut y, &mut channels, 0);
+                (data, PixelType::U8)
+            }
+        };
+        Self::from_raw(data, x, y, channels, pixel_type)
+    }
+
+    fn from_raw(
+        data: *mut u8,
+        x: i32,
+        y: i32,
+        channels: i32,
+        pixel_type: PixelType,
+    ) -> Option<Self> {
+        if data.is_null() {
+            return None;
+        }
+
+        Some(Self {
             data,
             x: x as usize,
             y: y as usize,
             channels: channels as usize,
-        }
+            pixel_type,
+        })
     }
 
     pub fn x(&self) -> usize {
@@ -320,16 +389,24 @@ impl StbImage {
         self.channels
     }
 
+    pub fn pixel_type(&self) -> PixelType {
+        self.pixel_type
+    }
+
+    // Raw bytes, components wider than a byte are in native order
     pub fn data(&self) -> &[u8] {
-        unsafe { std::slice::from_raw_parts(self.data, (self.x * self.y * self.channels) as usize) }
+        let len = self.x * self.y * self.channels * self.pixel_type.size();
+        unsafe { std::slice::from_raw_parts(self.data, len) }
     }
 
     pub fn extract_channel(&self, channel_offset: usize) -> Vec<u8> {
         assert!(channel_offset < self.channels);
-        let mut result = Vec::with_capacity(self.x * self.y);
+        let size = self.pixel_type.size();
+        let mut result = Vec::with_capacity(self.x * self.y * size);
         let data = self.data();
-        for i in (0..self.x * self.y * self.channels).step_by(self.channels) {
-            result.push(data[i + channel_offset]);
+        for i in (0..data.len()).step_by(self.channels * size) {
+            let start = i + channel_offset * size;
+            result.extend_from_slice(&data[start..start + size]);
         }
 
         result
@@ -0,0 +1,515 @@
+use crate::asset_path;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use fxhash::FxHashMap;
+use russimp::{
+    fs::{FileOperations, FileSystem},
+    sys::{aiFile, aiFileIO, aiOrigin, aiReturn},
+};
+use std::{
+    env,
+    ffi::{c_char, CStr},
+    fs::{self, File},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    process, ptr, slice,
+    sync::Mutex,
+};
+
+const USAGE: &str = "usage:
+    pack [-o <archive>] [--store]
+
+Packs the asset root (assets/ or $ASSET_ROOT) and cache/ into a single archive, data.pak unless -o is given. The engine
+mounts data.pak from the working directory instead of the loose folders when it exists
+
+    --store     stores every file uncompressed";
+
+pub const ARCHIVE: &str = "data.pak";
+const CACHE_DIR: &str = "cache";
+const MAGIC: &[u8; 4] = b"LGPK";
+const VERSION: u32 = 1;
+const HEADER_SIZE: u64 = 16; // magic, version, index offset
+
+pub fn pack(args: &[String]) {
+    let mut output = ARCHIVE.to_string();
+    let mut store = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" if args.len() > 0 => output = args.next().unwrap().clone(),
+            "--store" => store = true,
+            _ => {
+                eprintln!("{USAGE}");
+                process::exit(2);
+            }
+        }
+    }
+
+    let mut files = [asset_path::root(), CACHE_DIR]
+        .iter()
+        .flat_map(|dir| Vfs::Loose.list(dir))
+        .collect::<Vec<String>>();
+    files.sort();
+
+    let mut out = BufWriter::new(File::create(&output).unwrap());
+    out.write_all(MAGIC).unwrap();
+    out.write_all(&VERSION.to_le_bytes()).unwrap();
+    out.write_all(&0u64.to_le_bytes()).unwrap(); // index offset, written at the end
+
+    let mut index = Vec::new();
+    index.extend_from_slice(&(files.len() as u32).to_le_bytes());
+    let mut offset = HEADER_SIZE;
+    let mut total_size = 0;
+
+    for path in &files {
+        let data = fs::read(path).unwrap();
+        // Images and cooked blobs often don't get any smaller, those are stored as they are
+        let compressed = (!store)
+            .then(|| deflate(&data))
+            .filter(|compressed| compressed.len() < data.len());
+        let stored = compressed.as_deref().unwrap_or(&data);
+        out.write_all(stored).unwrap();
+
+        let name = normalize(path);
+        index.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        index.extend_from_slice(name.as_bytes());
+        index.extend_from_slice(&offset.to_le_bytes());
+        index.extend_from_slice(&(stored.len() as u64).to_le_bytes());
+        index.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        index.push(compressed.is_some() as u8);
+        index.extend_from_slice(&crc32fast::hash(&data).to_le_bytes());
+
+        offset += stored.len() as u64;
+        total_size += data.len();
+    }
+
+    out.write_all(&index).unwrap();
+    out.write_all(&crc32fast::hash(&index).to_le_bytes())
+        .unwrap();
+    out.seek(SeekFrom::Start(8)).unwrap();
+    out.write_all(&offset.to_le_bytes()).unwrap();
+    out.flush().unwrap();
+
+    println!(
+        "packed {} files, {:.1} KiB into {:.1} KiB",
+        files.len(),
+        total_size as f64 / 1024.0,
+        (offset + index.len() as u64 + 4) as f64 / 1024.0
+    );
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+// Files are addressed by the same relative paths ("assets/scenes/sample.json") whether
+// they lie loose on disk or are packed
+pub enum Vfs {
+    Loose,
+    Archive(Archive),
+}
+
+impl Vfs {
+    // The archive wins when both are present
+    pub fn mount() -> Self {
+        if Path::new(ARCHIVE).exists() {
+            match Archive::open(ARCHIVE) {
+                Ok(archive) => return Vfs::Archive(archive),
+                Err(error) => println!("Failed to mount {ARCHIVE}: {error}"),
+            }
+        }
+        Vfs::Loose
+    }
+
+    pub fn is_loose(&self) -> bool {
+        matches!(self, Vfs::Loose)
+    }
+
+    pub fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        match self {
+            Vfs::Loose => match fs::read(path) {
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    fs::read(Self::find_ignoring_case(path).ok_or(error)?)
+                }
+                result => result,
+            },
+            Vfs::Archive(archive) => archive.read(&normalize(path)),
+        }
+    }
+
+    pub fn read_to_string(&self, path: &str) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    // Files under `dir` and its subdirectories
+    pub fn list(&self, dir: &str) -> Vec<String> {
+        match self {
+            Vfs::Loose => {
+                let mut result = Vec::new();
+                Self::walk(Path::new(dir), &mut result);
+                result
+            }
+            Vfs::Archive(archive) => {
+                let prefix = format!("{}/", normalize(dir));
+                let mut result = archive
+                    .index
+                    .keys()
+                    .filter(|path| path.starts_with(&prefix))
+                    .cloned()
+                    .collect::<Vec<String>>();
+                result.sort();
+                result
+            }
+        }
+    }
+
+    // Asset files referenced with a different case than they have on disk, which only
+    // works by accident on Windows
+    fn find_ignoring_case(path: &str) -> Option<PathBuf> {
+        let mut found = PathBuf::new();
+        for part in normalize(path).split('/') {
+            if part.is_empty() {
+                found.push("/"); // absolute path
+                continue;
+            }
+            let exact = found.join(part);
+            if part == ".." || exact.exists() {
+                found = exact;
+                continue;
+            }
+            let dir = if found.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                &found
+            };
+            let entry = fs::read_dir(dir).ok()?.flatten().find(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(part))
+            })?;
+            found.push(entry.file_name());
+        }
+        Some(found)
+    }
+
+    fn walk(dir: &Path, out: &mut Vec<String>) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                Self::walk(&path, out);
+            } else {
+                out.push(path.to_str().unwrap().to_string());
+            }
+        }
+    }
+}
+
+pub struct Archive {
+    file: Mutex<File>, // shared by the loader threads
+    index: FxHashMap<String, ArchiveEntry>,
+    lowercase: FxHashMap<String, String>, // names by their lowercase form, for case misses
+}
+
+struct ArchiveEntry {
+    offset: u64,
+    stored_size: u64,
+    size: u64,
+    compressed: bool,
+    checksum: u32, // crc32 of the uncompressed data
+}
+
+impl Archive {
+    fn open(path: &str) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut header = [0; HEADER_SIZE as usize];
+        file.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC || header[4..8] != VERSION.to_le_bytes() {
+            return Err(Self::corrupted("not an archive of this version"));
+        }
+        let index_offset = u64::from_le_bytes(header[8..16].try_into().unwrap());
+
+        let mut index_data = Vec::new();
+        _ = file.seek(SeekFrom::Start(index_offset))?;
+        _ = file.read_to_end(&mut index_data)?;
+        if index_data.len() < 4 {
+            return Err(Self::corrupted("index is missing"));
+        }
+        let (index_data, checksum) = index_data.split_at(index_data.len() - 4);
+        if crc32fast::hash(index_data).to_le_bytes() != checksum {
+            return Err(Self::corrupted("index is corrupted"));
+        }
+
+        let mut reader = index_data;
+        let count = Self::read_u32(&mut reader)?;
+        let mut index = FxHashMap::default();
+        for _ in 0..count {
+            let mut name = vec![0; Self::read_u32(&mut reader)? as usize];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| Self::corrupted("invalid path"))?;
+            let entry = ArchiveEntry {
+                offset: Self::read_u64(&mut reader)?,
+                stored_size: Self::read_u64(&mut reader)?,
+                size: Self::read_u64(&mut reader)?,
+                compressed: Self::read_u8(&mut reader)? != 0,
+                checksum: Self::read_u32(&mut reader)?,
+            };
+            _ = index.insert(name, entry);
+        }
+        // Names were packed sorted, the first of the ones differing only in case wins
+        let mut names = index.keys().collect::<Vec<&String>>();
+        names.sort();
+        let mut lowercase = FxHashMap::default();
+        for name in names {
+            _ = lowercase
+                .entry(name.to_ascii_lowercase())
+                .or_insert_with(|| name.clone());
+        }
+
+        Ok(Self {
+            file: Mutex::new(file),
+            index,
+            lowercase,
+        })
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let entry = self
+            .index
+            .get(path)
+            .or_else(|| {
+                let name = self.lowercase.get(&path.to_ascii_lowercase())?;
+                self.index.get(name)
+            })
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("{path} is not packed"))
+            })?;
+
+        let mut stored = vec![0; entry.stored_size as usize];
+        {
+            let mut file = self.file.lock().unwrap();
+            _ = file.seek(SeekFrom::Start(entry.offset))?;
+            file.read_exact(&mut stored)?;
+        }
+
+        let data = if entry.compressed {
+            let mut data = Vec::with_capacity(entry.size as usize);
+            _ = DeflateDecoder::new(stored.as_slice()).read_to_end(&mut data)?;
+            data
+        } else {
+            stored
+        };
+        if crc32fast::hash(&data) != entry.checksum {
+            return Err(Self::corrupted(&format!("{path} is corrupted")));
+        }
+        Ok(data)
+    }
+
+    fn corrupted(message: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+    }
+
+    fn read_u8(reader: &mut &[u8]) -> io::Result<u8> {
+        let mut bytes = [0; 1];
+        reader.read_exact(&mut bytes)?;
+        Ok(bytes[0])
+    }
+
+    fn read_u32(reader: &mut &[u8]) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn read_u64(reader: &mut &[u8]) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        reader.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+// Relative to the working directory with forward slashes and without "." and "..", the
+// way paths are stored in the archive. Backslashes are separators on every platform
+pub fn normalize(path: &str) -> String {
+    let mut path = PathBuf::from(path.replace('\\', "/"));
+    if path.is_absolute() {
+        if let Ok(cwd) = env::current_dir().and_then(fs::canonicalize) {
+            // Verbatim prefix added on Windows by canonicalize
+            let cwd = PathBuf::from(cwd.to_str().unwrap().trim_start_matches(r"\\?\"));
+            if let Ok(relative) = path.strip_prefix(&cwd) {
+                path = relative.to_path_buf();
+            }
+        }
+    }
+
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str().unwrap()),
+            Component::ParentDir if parts.last().is_some_and(|part| *part != "..") => {
+                _ = parts.pop();
+            }
+            Component::ParentDir => parts.push(".."),
+            Component::Prefix(prefix) => parts.push(prefix.as_os_str().to_str().unwrap()),
+            Component::RootDir if parts.is_empty() => parts.push(""),
+            Component::RootDir | Component::CurDir => {}
+        }
+    }
+    parts.join("/")
+}
+
+// Lets assimp open a model and the files it references (.bin buffers, .mtl libraries)
+// through the mounted file system
+pub struct ModelFiles<'a>(pub &'a Vfs);
+
+impl FileSystem for ModelFiles<'_> {
+    fn open(&self, file_path: &str, mode: &str) -> Option<Box<dyn FileOperations>> {
+        if mode.contains('w') {
+            return None;
+        }
+        let data = self.0.read(file_path).ok()?;
+        Some(Box::new(MemoryFile { data, pos: 0 }))
+    }
+}
+
+impl ModelFiles<'_> {
+    // The same for imports through the assimp C API, `self` has to outlive the import
+    pub fn ai_file_io(&self) -> aiFileIO {
+        aiFileIO {
+            OpenProc: Some(ai_open),
+            CloseProc: Some(ai_close),
+            UserData: (self as *const Self).cast_mut().cast(),
+        }
+    }
+}
+
+unsafe extern "C" fn ai_open(
+    file_io: *mut aiFileIO,
+    path: *const c_char,
+    mode: *const c_char,
+) -> *mut aiFile {
+    let files = &*((*file_io).UserData as *const ModelFiles);
+    let (Ok(path), Ok(mode)) = (CStr::from_ptr(path).to_str(), CStr::from_ptr(mode).to_str())
+    else {
+        return ptr::null_mut();
+    };
+    if mode.contains('w') {
+        return ptr::null_mut();
+    }
+    let Ok(data) = files.0.read(path) else {
+        return ptr::null_mut();
+    };
+    let file = Box::new(MemoryFile { data, pos: 0 });
+    Box::into_raw(Box::new(aiFile {
+        ReadProc: Some(ai_read),
+        WriteProc: Some(ai_write),
+        TellProc: Some(ai_tell),
+        FileSizeProc: Some(ai_size),
+        SeekProc: Some(ai_seek),
+        FlushProc: Some(ai_flush),
+        UserData: Box::into_raw(file).cast(),
+    }))
+}
+
+unsafe extern "C" fn ai_close(_: *mut aiFileIO, file: *mut aiFile) {
+    let file = Box::from_raw(file);
+    drop(Box::from_raw(file.UserData as *mut MemoryFile));
+}
+
+unsafe fn memory_file<'a>(file: *mut aiFile) -> &'a mut MemoryFile {
+    &mut *((*file).UserData as *mut MemoryFile)
+}
+
+// Counts whole items of `size` bytes
+unsafe extern "C" fn ai_read(
+    file: *mut aiFile,
+    buffer: *mut c_char,
+    size: usize,
+    count: usize,
+) -> usize {
+    if size == 0 {
+        return 0;
+    }
+    let buffer = slice::from_raw_parts_mut(buffer.cast::<u8>(), size * count);
+    let file = memory_file(file);
+    let count = count.min((file.data.len() - file.pos) / size);
+    _ = file.read(&mut buffer[..size * count]);
+    count
+}
+
+unsafe extern "C" fn ai_write(_: *mut aiFile, _: *const c_char, _: usize, _: usize) -> usize {
+    0
+}
+
+unsafe extern "C" fn ai_tell(file: *mut aiFile) -> usize {
+    memory_file(file).tell()
+}
+
+unsafe extern "C" fn ai_size(file: *mut aiFile) -> usize {
+    memory_file(file).size()
+}
+
+// Negative offsets come wrapped around
+unsafe extern "C" fn ai_seek(file: *mut aiFile, offset: usize, origin: aiOrigin) -> aiReturn {
+    let seek_from = match origin {
+        russimp::sys::aiOrigin_aiOrigin_SET => SeekFrom::Start(offset as u64),
+        russimp::sys::aiOrigin_aiOrigin_CUR => SeekFrom::Current(offset as i64),
+        russimp::sys::aiOrigin_aiOrigin_END => SeekFrom::End(offset as i64),
+        _ => return russimp::sys::aiReturn_aiReturn_FAILURE,
+    };
+    match memory_file(file).seek(seek_from) {
+        Ok(()) => russimp::sys::aiReturn_aiReturn_SUCCESS,
+        Err(()) => russimp::sys::aiReturn_aiReturn_FAILURE,
+    }
+}
+
+unsafe extern "C" fn ai_flush(_: *mut aiFile) {}
+
+struct MemoryFile {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl FileOperations for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let len = buf.len().min(self.data.len() - self.pos);
+        buf[..len].copy_from_slice(&self.data[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+
+    fn write(&mut self, _: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+
+    fn tell(&mut self) -> usize {
+        self.pos
+    }
+
+    fn size(&mut self) -> usize {
+        self.data.len()
+    }
+
+    fn seek(&mut self, seek_from: SeekFrom) -> Result<(), ()> {
+        let pos = match seek_from {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+            SeekFrom::End(offset) => self.data.len() as i64 + offset,
+        };
+        if pos < 0 || pos as usize > self.data.len() {
+            return Err(());
+        }
+        self.pos = pos as usize;
+        Ok(())
+    }
+
+    fn flush(&mut self) {}
+
+    fn close(&mut self) {}
+}